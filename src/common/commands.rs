@@ -14,24 +14,98 @@
 
 use druid::Selector;
 
+use crate::model::sheet::AtlasFormat;
+use crate::model::sheet::SheetLayout;
+use crate::model::types::BlendMode;
+use crate::model::types::ColorMode;
+use crate::model::types::Orientation;
+use crate::model::types::PalettePreset;
+use crate::model::types::PaletteSort;
 use crate::model::types::ToolState;
 
 pub const EDIT_SELECT_ALL: Selector = Selector::new("edit-select-all");
 pub const EDIT_DESELECT: Selector = Selector::new("edit-deselect");
+pub const EDIT_PASTE_NEW: Selector = Selector::new("edit-paste-new");
+pub const EDIT_UNDO_DEPTH: Selector<usize> = Selector::new("edit-undo-depth");
+pub const EDIT_UNDO_BUDGET: Selector<usize> = Selector::new("edit-undo-budget");
+pub const EDIT_BRUSH_COLOR: Selector = Selector::new("edit-brush-color");
 
+pub const NEW_FILE_INTERNAL: Selector = Selector::new("new-file-internal");
 pub const OPEN_FILE_INTERNAL: Selector = Selector::new("open-file-internal");
 
+pub const FRAME_ADD: Selector = Selector::new("frame-add");
+pub const FRAME_DELETE: Selector = Selector::new("frame-delete");
+pub const FRAME_DUPLICATE: Selector = Selector::new("frame-duplicate");
+pub const FRAME_DURATION: Selector<u32> = Selector::new("frame-duration");
+pub const FRAME_MOVE_EARLIER: Selector = Selector::new("frame-move-earlier");
+pub const FRAME_MOVE_LATER: Selector = Selector::new("frame-move-later");
+pub const FRAME_NEXT: Selector = Selector::new("frame-next");
+pub const FRAME_PREVIOUS: Selector = Selector::new("frame-previous");
+pub const FRAME_TAG: Selector = Selector::new("frame-tag");
+
 pub const IMAGE_BLACK_AND_WHITE: Selector = Selector::new("image-black-and-white");
 pub const IMAGE_BRIGHTEN: Selector = Selector::new("image-brighten");
 pub const IMAGE_CLEAR: Selector = Selector::new("image-clear");
+pub const IMAGE_COLOR_MODE: Selector<ColorMode> = Selector::new("image-color-mode");
+pub const IMAGE_CROP: Selector = Selector::new("image-crop");
 pub const IMAGE_DARKEN: Selector = Selector::new("image-darken");
 pub const IMAGE_DESATURATE: Selector = Selector::new("image-desaturate");
 pub const IMAGE_DITHER_FLOYD: Selector = Selector::new("image-dither-floyd");
 pub const IMAGE_ERASER: Selector<ToolState> = Selector::new("image-eraser");
 pub const IMAGE_FILL: Selector<bool> = Selector::new("image-fill");
+pub const IMAGE_FILL_SHAPES: Selector = Selector::new("image-fill-shapes");
+pub const IMAGE_LASSO: Selector<Vec<druid::Point>> = Selector::new("image-lasso");
 pub const IMAGE_MARQUEE: Selector<ToolState> = Selector::new("image-marquee");
 pub const IMAGE_MOVE: Selector<ToolState> = Selector::new("image-move");
 pub const IMAGE_MOVE_DROP: Selector = Selector::new("image-move-drop");
+pub const IMAGE_ORIENT: Selector<Orientation> = Selector::new("image-orient");
 pub const IMAGE_PAINT: Selector<ToolState> = Selector::new("image-paint");
+pub const IMAGE_PIXEL_PERFECT: Selector = Selector::new("image-pixel-perfect");
+pub const IMAGE_SHAPE: Selector<Vec<druid::Point>> = Selector::new("image-shape");
+pub const IMAGE_TRIM: Selector = Selector::new("image-trim");
+
+pub const LAYER_ADD: Selector = Selector::new("layer-add");
+pub const LAYER_BLEND_MODE: Selector<BlendMode> = Selector::new("layer-blend-mode");
+pub const LAYER_DELETE: Selector = Selector::new("layer-delete");
+pub const LAYER_OPACITY: Selector<f64> = Selector::new("layer-opacity");
+pub const LAYER_SELECT_ABOVE: Selector = Selector::new("layer-select-above");
+pub const LAYER_SELECT_BELOW: Selector = Selector::new("layer-select-below");
+pub const LAYER_TOGGLE_LOCKED: Selector = Selector::new("layer-toggle-locked");
+pub const LAYER_TOGGLE_VISIBLE: Selector = Selector::new("layer-toggle-visible");
+
+pub const PALETTE_ADD_COLOR: Selector = Selector::new("palette-add-color");
+pub const PALETTE_DELETE_COLOR: Selector<usize> = Selector::new("palette-delete-color");
+pub const PALETTE_EDIT_COLOR: Selector<usize> = Selector::new("palette-edit-color");
+pub const PALETTE_LOAD: Selector<druid::FileInfo> = Selector::new("palette-load");
+pub const PALETTE_MOVE_COLOR: Selector<(usize, usize)> = Selector::new("palette-move-color");
+pub const PALETTE_PRESET: Selector<PalettePreset> = Selector::new("palette-preset");
+pub const PALETTE_SAVE: Selector<druid::FileInfo> = Selector::new("palette-save");
+pub const PALETTE_SORT: Selector<PaletteSort> = Selector::new("palette-sort");
+
+pub const SELECT_BY_COLOR: Selector = Selector::new("select-by-color");
+pub const SELECT_GROW: Selector<usize> = Selector::new("select-grow");
+pub const SELECT_INVERT: Selector = Selector::new("select-invert");
+pub const SELECT_MAGIC_WAND: Selector = Selector::new("select-magic-wand");
+pub const SELECT_SHRINK: Selector<usize> = Selector::new("select-shrink");
+pub const SELECT_WAND_CONTIGUOUS: Selector = Selector::new("select-wand-contiguous");
+pub const SELECT_WAND_DIAGONAL: Selector = Selector::new("select-wand-diagonal");
+pub const SELECT_WAND_TOLERANCE: Selector<u8> = Selector::new("select-wand-tolerance");
+
+pub const SHEET_EXPORT: Selector<druid::FileInfo> = Selector::new("sheet-export");
+pub const SHEET_EXTRUDE: Selector<usize> = Selector::new("sheet-extrude");
+pub const SHEET_FORMAT: Selector<AtlasFormat> = Selector::new("sheet-format");
+pub const SHEET_IMPORT_FILES: Selector = Selector::new("sheet-import-files");
+pub const SHEET_IMPORT_FRAMES: Selector = Selector::new("sheet-import-frames");
+pub const SHEET_IMPORT_OPEN: Selector<druid::FileInfo> = Selector::new("sheet-import-open");
+pub const SHEET_LAYOUT: Selector<SheetLayout> = Selector::new("sheet-layout");
+pub const SHEET_PADDING: Selector<usize> = Selector::new("sheet-padding");
+pub const SHEET_TAGGED_ONLY: Selector = Selector::new("sheet-tagged-only");
 
+pub const VIEW_ONION_SKIN_AFTER: Selector<usize> = Selector::new("view-onion-skin-after");
+pub const VIEW_ONION_SKIN_BEFORE: Selector<usize> = Selector::new("view-onion-skin-before");
 pub const VIEW_SHOW_GRID: Selector = Selector::new("view-show-grid");
+pub const VIEW_SHOW_ONION_SKIN: Selector = Selector::new("view-show-onion-skin");
+pub const VIEW_ZOOM_ACTUAL: Selector = Selector::new("view-zoom-actual");
+pub const VIEW_ZOOM_FIT: Selector = Selector::new("view-zoom-fit");
+pub const VIEW_ZOOM_IN: Selector = Selector::new("view-zoom-in");
+pub const VIEW_ZOOM_OUT: Selector = Selector::new("view-zoom-out");
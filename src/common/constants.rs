@@ -13,10 +13,52 @@
 // limitations under the License.
 
 /// File types that we support.
-pub const ALLOWED_FILE_TYPES: [druid::FileSpec; 1] = [druid::FileSpec::PNG];
+pub const ALLOWED_FILE_TYPES: [druid::FileSpec; 2] = [druid::FileSpec::PNG, druid::FileSpec::GIF];
+
+/// Palette file types that we support.
+pub const PALETTE_FILE_TYPES: [druid::FileSpec; 4] = [
+    druid::FileSpec::new("GIMP Palette", &["gpl"]),
+    druid::FileSpec::new("JASC Palette", &["pal"]),
+    druid::FileSpec::new("Adobe Color Table", &["act"]),
+    druid::FileSpec::new("Hex Palette", &["hex"]),
+];
+
+/// Max number of colors in a palette. That's as many as an indexed document can use, and
+/// as many as the palette shows.
+pub const MAX_PALETTE_COLORS: usize = 256;
 
 /// Max pixel dimensions.
-pub const MAX_PIXEL_DIMS: u32 = 48;
+pub const MAX_PIXEL_DIMS: u32 = 1024;
 
 /// Default pixel dimensions.
-pub const DEFAULT_PIXEL_DIMS: u32 = MAX_PIXEL_DIMS;
+pub const DEFAULT_PIXEL_DIMS: u32 = 48;
+
+/// Max sprite sheet dimensions, when importing. Sheets are sliced into cells, and it's the
+/// cells that need to fit within the max pixel dimensions.
+pub const MAX_SHEET_DIMS: u32 = 4096;
+
+/// Default frame duration, in milliseconds.
+pub const DEFAULT_FRAME_DURATION: u32 = 100;
+
+/// Max number of frames the onion skin shows on either side of the current frame.
+pub const MAX_ONION_SKIN_FRAMES: usize = 3;
+
+/// Default number of steps kept in the undo history.
+pub const UNDO_DEPTH: usize = 64;
+
+/// Default number of bytes the undo history can hold. Most steps only record what's
+/// changed, but resizing, flipping or rotating the image records every cel, and a full
+/// size image is 4 MiB per cel. That's enough for a few of those on a modest animation.
+pub const UNDO_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Palette index that's transparent in indexed documents. It has a slot of its own, rather
+/// than taking over a color, so index 1 is the first color in the palette and so on.
+pub const TRANSPARENT_INDEX: u8 = 0;
+
+/// Max number of palette colors that indexed documents can use. One index is taken up by
+/// transparency, so a full palette can't go indexed unless its last color repeats one of
+/// the others, and an indexed palette can't grow past this.
+pub const MAX_INDEXED_COLORS: usize = 255;
+
+/// Zoom levels, i.e. the size of each pixel on screen.
+pub const ZOOM_LEVELS: [usize; 12] = [1, 2, 3, 4, 6, 8, 12, 16, 24, 32, 48, 64];
@@ -15,6 +15,11 @@
 use crate::common::commands;
 use crate::controller;
 use crate::model::app::AppState;
+use crate::model::palette::PaletteState;
+use crate::model::sheet::AtlasFormat;
+use crate::model::sheet::SheetLayout;
+use crate::model::types::BlendMode;
+use crate::model::types::ColorMode;
 use crate::view::menu;
 
 pub struct Delegate;
@@ -34,6 +39,10 @@ impl druid::AppDelegate<AppState> for Delegate {
                 controller::file::new(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::NEW_FILE_INTERNAL) => {
+                controller::file::new_internal(ctx, cmd, data);
+                druid::Handled::Yes
+            }
             _ if cmd.is(druid::commands::OPEN_FILE) => {
                 controller::file::open(ctx, cmd, data);
                 druid::Handled::Yes
@@ -64,6 +73,14 @@ impl druid::AppDelegate<AppState> for Delegate {
                 controller::edit::redo(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::EDIT_UNDO_DEPTH) => {
+                controller::edit::undo_depth(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::EDIT_UNDO_BUDGET) => {
+                controller::edit::undo_budget(ctx, cmd, data);
+                druid::Handled::Yes
+            }
             _ if cmd.is(druid::commands::CUT) => {
                 controller::edit::cut(ctx, cmd, data);
                 druid::Handled::Yes
@@ -76,6 +93,10 @@ impl druid::AppDelegate<AppState> for Delegate {
                 controller::edit::paste(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::EDIT_PASTE_NEW) => {
+                controller::edit::paste_new(ctx, cmd, data);
+                druid::Handled::Yes
+            }
             _ if cmd.is(commands::EDIT_SELECT_ALL) => {
                 controller::edit::select_all(ctx, cmd, data);
                 druid::Handled::Yes
@@ -84,6 +105,10 @@ impl druid::AppDelegate<AppState> for Delegate {
                 controller::edit::deselect(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::EDIT_BRUSH_COLOR) => {
+                controller::edit::brush_color(ctx, cmd, data);
+                druid::Handled::Yes
+            }
 
             // Image.
             _ if cmd.is(commands::IMAGE_BLACK_AND_WHITE) => {
@@ -98,6 +123,14 @@ impl druid::AppDelegate<AppState> for Delegate {
                 controller::image::clear(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::IMAGE_COLOR_MODE) => {
+                controller::image::color_mode(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::IMAGE_CROP) => {
+                controller::image::crop(ctx, cmd, data);
+                druid::Handled::Yes
+            }
             _ if cmd.is(commands::IMAGE_DARKEN) => {
                 controller::image::darken(ctx, cmd, data);
                 druid::Handled::Yes
@@ -118,6 +151,14 @@ impl druid::AppDelegate<AppState> for Delegate {
                 controller::image::fill(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::IMAGE_FILL_SHAPES) => {
+                controller::image::fill_shapes(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::IMAGE_LASSO) => {
+                controller::image::lasso(ctx, cmd, data);
+                druid::Handled::Yes
+            }
             _ if cmd.is(commands::IMAGE_MARQUEE) => {
                 controller::image::marquee(ctx, cmd, data);
                 druid::Handled::Yes
@@ -130,16 +171,238 @@ impl druid::AppDelegate<AppState> for Delegate {
                 controller::image::move_drop(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::IMAGE_ORIENT) => {
+                controller::image::orient(ctx, cmd, data);
+                druid::Handled::Yes
+            }
             _ if cmd.is(commands::IMAGE_PAINT) => {
                 controller::image::paint(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::IMAGE_PIXEL_PERFECT) => {
+                controller::image::pixel_perfect(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::IMAGE_SHAPE) => {
+                controller::image::shape(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::IMAGE_TRIM) => {
+                controller::image::trim(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+
+            // Layer.
+            _ if cmd.is(commands::LAYER_ADD) => {
+                controller::layer::add(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::LAYER_BLEND_MODE) => {
+                controller::layer::blend_mode(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::LAYER_DELETE) => {
+                controller::layer::delete(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::LAYER_OPACITY) => {
+                controller::layer::opacity(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::LAYER_SELECT_ABOVE) => {
+                controller::layer::select_above(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::LAYER_SELECT_BELOW) => {
+                controller::layer::select_below(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::LAYER_TOGGLE_LOCKED) => {
+                controller::layer::toggle_locked(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::LAYER_TOGGLE_VISIBLE) => {
+                controller::layer::toggle_visible(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+
+            // Frame.
+            _ if cmd.is(commands::FRAME_ADD) => {
+                controller::frame::add(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_DELETE) => {
+                controller::frame::delete(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_DUPLICATE) => {
+                controller::frame::duplicate(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_DURATION) => {
+                controller::frame::duration(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_MOVE_EARLIER) => {
+                controller::frame::move_earlier(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_MOVE_LATER) => {
+                controller::frame::move_later(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_NEXT) => {
+                controller::frame::next(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_PREVIOUS) => {
+                controller::frame::previous(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::FRAME_TAG) => {
+                controller::frame::tag(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+
+            // Palette.
+            _ if cmd.is(commands::PALETTE_ADD_COLOR) => {
+                controller::palette::add_color(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::PALETTE_DELETE_COLOR) => {
+                controller::palette::delete_color(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::PALETTE_EDIT_COLOR) => {
+                controller::palette::edit_color(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::PALETTE_LOAD) => {
+                controller::palette::load(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::PALETTE_MOVE_COLOR) => {
+                controller::palette::move_color(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::PALETTE_PRESET) => {
+                controller::palette::preset(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::PALETTE_SAVE) => {
+                controller::palette::save(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::PALETTE_SORT) => {
+                controller::palette::sort(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+
+            // Select.
+            _ if cmd.is(commands::SELECT_BY_COLOR) => {
+                controller::select::by_color(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SELECT_GROW) => {
+                controller::select::grow(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SELECT_INVERT) => {
+                controller::select::invert(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SELECT_MAGIC_WAND) => {
+                controller::select::magic_wand(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SELECT_SHRINK) => {
+                controller::select::shrink(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SELECT_WAND_CONTIGUOUS) => {
+                controller::select::wand_contiguous(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SELECT_WAND_DIAGONAL) => {
+                controller::select::wand_diagonal(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SELECT_WAND_TOLERANCE) => {
+                controller::select::wand_tolerance(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+
+            // Sprite sheet.
+            _ if cmd.is(commands::SHEET_EXPORT) => {
+                controller::sheet::export(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_EXTRUDE) => {
+                controller::sheet::extrude(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_FORMAT) => {
+                controller::sheet::format(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_IMPORT_FILES) => {
+                controller::sheet::import_files(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_IMPORT_FRAMES) => {
+                controller::sheet::import_frames(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_IMPORT_OPEN) => {
+                controller::sheet::import_open(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_LAYOUT) => {
+                controller::sheet::layout(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_PADDING) => {
+                controller::sheet::padding(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::SHEET_TAGGED_ONLY) => {
+                controller::sheet::tagged_only(ctx, cmd, data);
+                druid::Handled::Yes
+            }
 
             // View.
+            _ if cmd.is(commands::VIEW_ONION_SKIN_AFTER) => {
+                controller::view::onion_skin_after(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::VIEW_ONION_SKIN_BEFORE) => {
+                controller::view::onion_skin_before(ctx, cmd, data);
+                druid::Handled::Yes
+            }
             _ if cmd.is(commands::VIEW_SHOW_GRID) => {
                 controller::view::show_grid(ctx, cmd, data);
                 druid::Handled::Yes
             }
+            _ if cmd.is(commands::VIEW_SHOW_ONION_SKIN) => {
+                controller::view::show_onion_skin(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::VIEW_ZOOM_ACTUAL) => {
+                controller::view::zoom_actual(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::VIEW_ZOOM_FIT) => {
+                controller::view::zoom_fit(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::VIEW_ZOOM_IN) => {
+                controller::view::zoom_in(ctx, cmd, data);
+                druid::Handled::Yes
+            }
+            _ if cmd.is(commands::VIEW_ZOOM_OUT) => {
+                controller::view::zoom_out(ctx, cmd, data);
+                druid::Handled::Yes
+            }
 
             _ => druid::Handled::No,
         };
@@ -159,18 +422,163 @@ fn rebuild_menu_bar(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &m
 
     menu_opts.disable(
         menu::COMMON_MENU_FILE_SAVE,
-        data.doc().path().is_none() || !data.doc().pixels().dirty(),
+        data.doc().path().is_none() || !data.doc().dirty(),
     );
 
     let empty_selection = data.doc().selection().is_none();
 
     menu_opts.disable(menu::COMMON_MENU_UNDO, data.doc().undo().is_empty());
     menu_opts.disable(menu::COMMON_MENU_REDO, data.doc().redo().is_empty());
+    let undo_depth = data.doc().undo().depth();
+    for &(key, value) in menu::MENU_EDIT_UNDO_DEPTHS.iter() {
+        menu_opts.select(key, undo_depth == value);
+    }
+    let undo_budget = data.doc().undo().budget();
+    for &(key, value) in menu::MENU_EDIT_UNDO_BUDGETS.iter() {
+        menu_opts.select(key, undo_budget == value);
+    }
     menu_opts.disable(menu::COMMON_MENU_CUT, empty_selection);
     menu_opts.disable(menu::COMMON_MENU_COPY, empty_selection);
     menu_opts.disable(menu::EDIT_MENU_DESELECT, empty_selection);
+    menu_opts.disable(menu::MENU_IMAGE_CROP, empty_selection);
+    for &(key, _) in menu::MENU_SELECT_GROWS
+        .iter()
+        .chain(menu::MENU_SELECT_SHRINKS.iter())
+    {
+        menu_opts.disable(key, empty_selection);
+    }
+
+    // Pasting into a new document replaces this one, so don't offer it while there are
+    // unsaved changes.
+    menu_opts.disable(menu::EDIT_MENU_PASTE_NEW, data.doc().dirty());
+
+    let layer = data.doc().layer(data.doc().current_layer());
+    let layer_count = data.doc().layers().len();
+
+    menu_opts.disable(menu::MENU_LAYER_DELETE, layer_count == 1);
+    menu_opts.disable(
+        menu::MENU_LAYER_SELECT_ABOVE,
+        data.doc().current_layer() + 1 == layer_count,
+    );
+    menu_opts.disable(
+        menu::MENU_LAYER_SELECT_BELOW,
+        data.doc().current_layer() == 0,
+    );
+    menu_opts.select(menu::MENU_LAYER_VISIBLE, layer.visible());
+    menu_opts.select(menu::MENU_LAYER_LOCKED, layer.locked());
+
+    let blend_mode = layer.blend_mode();
+    menu_opts.select(
+        menu::MENU_LAYER_BLEND_NORMAL,
+        blend_mode == BlendMode::Normal,
+    );
+    menu_opts.select(
+        menu::MENU_LAYER_BLEND_MULTIPLY,
+        blend_mode == BlendMode::Multiply,
+    );
+    menu_opts.select(
+        menu::MENU_LAYER_BLEND_SCREEN,
+        blend_mode == BlendMode::Screen,
+    );
+    menu_opts.select(menu::MENU_LAYER_BLEND_ADD, blend_mode == BlendMode::Add);
+
+    let opacity = layer.opacity();
+    menu_opts.select(menu::MENU_LAYER_OPACITY_100, opacity >= 1.0);
+    menu_opts.select(
+        menu::MENU_LAYER_OPACITY_75,
+        (opacity - 0.75).abs() < f64::EPSILON,
+    );
+    menu_opts.select(
+        menu::MENU_LAYER_OPACITY_50,
+        (opacity - 0.5).abs() < f64::EPSILON,
+    );
+    menu_opts.select(
+        menu::MENU_LAYER_OPACITY_25,
+        (opacity - 0.25).abs() < f64::EPSILON,
+    );
+
+    let frame_count = data.doc().frames().len();
+    let current_frame = data.doc().current_frame();
+
+    menu_opts.disable(menu::MENU_FRAME_DELETE, frame_count == 1);
+    menu_opts.disable(menu::MENU_FRAME_MOVE_EARLIER, current_frame == 0);
+    menu_opts.disable(
+        menu::MENU_FRAME_MOVE_LATER,
+        current_frame + 1 == frame_count,
+    );
+
+    let duration = data.doc().frame(current_frame).duration();
+    for &(key, value) in menu::MENU_FRAME_DURATIONS.iter() {
+        menu_opts.select(key, duration == value);
+    }
 
+    // Importing frames replaces the document, so don't offer it while there are unsaved changes.
+    menu_opts.disable(menu::MENU_SHEET_IMPORT, data.doc().dirty());
+
+    let sheet_options = data.sheet_options();
+    menu_opts.select(
+        menu::MENU_SHEET_LAYOUT_GRID,
+        sheet_options.layout() == SheetLayout::Grid,
+    );
+    menu_opts.select(
+        menu::MENU_SHEET_LAYOUT_PACKED,
+        sheet_options.layout() == SheetLayout::Packed,
+    );
+    menu_opts.select(
+        menu::MENU_SHEET_FORMAT_ASEPRITE,
+        sheet_options.format() == AtlasFormat::Aseprite,
+    );
+    menu_opts.select(
+        menu::MENU_SHEET_FORMAT_TEXTURE_PACKER,
+        sheet_options.format() == AtlasFormat::TexturePacker,
+    );
+    menu_opts.select(menu::MENU_SHEET_TAGGED_ONLY, sheet_options.tagged_only());
+
+    for &(key, value) in menu::MENU_SHEET_PADDINGS.iter() {
+        menu_opts.select(key, sheet_options.padding() == value);
+    }
+    for &(key, value) in menu::MENU_SHEET_EXTRUSIONS.iter() {
+        menu_opts.select(key, sheet_options.extrude() == value);
+    }
+
+    let wand_options = data.wand_options();
+    menu_opts.select(menu::MENU_SELECT_WAND_CONTIGUOUS, wand_options.contiguous());
+    menu_opts.select(menu::MENU_SELECT_WAND_DIAGONAL, wand_options.diagonal());
+    for &(key, value) in menu::MENU_SELECT_WAND_TOLERANCES.iter() {
+        menu_opts.select(key, wand_options.tolerance() == value);
+    }
+
+    let palette = data.doc().palette();
+    menu_opts.disable(
+        menu::MENU_PALETTE_ADD_COLOR,
+        palette.len() >= data.doc().max_palette_colors(),
+    );
+    for &(key, _, preset) in menu::MENU_PALETTE_PRESETS.iter() {
+        let values = PaletteState::from_preset(preset);
+        menu_opts.select(key, palette.values() == values.values());
+    }
+
+    let color_mode = data.doc().color_mode();
+    menu_opts.select(menu::MENU_IMAGE_MODE_RGBA, color_mode == ColorMode::Rgba);
+    menu_opts.select(
+        menu::MENU_IMAGE_MODE_INDEXED,
+        color_mode == ColorMode::Indexed,
+    );
+    menu_opts.disable(
+        menu::MENU_IMAGE_MODE_INDEXED,
+        !data.doc().can_set_color_mode(ColorMode::Indexed),
+    );
+    menu_opts.select(menu::MENU_IMAGE_FILL_SHAPES, data.fill_shapes());
+    menu_opts.select(menu::MENU_IMAGE_PIXEL_PERFECT, data.pixel_perfect());
     menu_opts.select(menu::MENU_VIEW_SHOW_GRID, data.show_grid());
+    menu_opts.select(menu::MENU_VIEW_SHOW_ONION_SKIN, data.show_onion_skin());
+
+    for &(key, value) in menu::MENU_VIEW_ONION_SKIN_BEFORE.iter() {
+        menu_opts.select(key, data.onion_skin_before() == value);
+    }
+    for &(key, value) in menu::MENU_VIEW_ONION_SKIN_AFTER.iter() {
+        menu_opts.select(key, data.onion_skin_after() == value);
+    }
 
     if let druid::Target::Window(id) = cmd.target() {
         let menu_bar: druid::MenuDesc<AppState> = menu::menu_bar(&menu_opts);
@@ -12,28 +12,85 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::file;
 use super::image;
+use crate::common::commands;
 use crate::controller::undo;
 use crate::model::app::AppState;
+use crate::model::color_picker::ColorPicker;
+use crate::model::document::Document;
+use crate::model::document::MoveInfo;
+use crate::model::pixels::PixelState;
+use crate::model::types::*;
+use crate::storage;
+use crate::transforms;
+use crate::view::alert;
 
-pub fn undo(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+/// The clipboard format for PNG data. Each platform has its own name for it.
+#[cfg(target_os = "macos")]
+const PNG_FORMAT: &str = "public.png";
+#[cfg(target_os = "windows")]
+const PNG_FORMAT: &str = "PNG";
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const PNG_FORMAT: &str = "image/png";
+
+pub fn undo(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
     undo::pop(data);
 }
 
-pub fn redo(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+pub fn redo(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
     undo::pop_redo(data);
 }
 
-pub fn cut(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, _data: &mut AppState) {}
+pub fn undo_depth(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let depth = *cmd.get_unchecked(commands::EDIT_UNDO_DEPTH);
+    data.doc_mut().set_undo_depth(depth);
+}
+
+pub fn undo_budget(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let budget = *cmd.get_unchecked(commands::EDIT_UNDO_BUDGET);
+    data.doc_mut().set_undo_budget(budget);
+}
+
+pub fn cut(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    if data.doc().selection().is_some() {
+        copy(ctx, cmd, data);
+        remove(data);
+    }
+}
+
+pub fn copy(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    if let Some(pixels) = capture(data) {
+        write_clipboard(data, pixels);
+    }
+}
 
-pub fn copy(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, _data: &mut AppState) {}
+/// Paste as a floating selection, so that it can be moved into place before it's dropped.
+pub fn paste(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
 
-pub fn paste(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, _data: &mut AppState) {}
+    if !data.doc().editable() {
+        return;
+    }
+
+    if let Some(pixels) = read_clipboard(ctx, data) {
+        float(data, &pixels);
+    }
+}
+
+/// Paste into a new, untitled document the same size as the pasted pixels.
+pub fn paste_new(ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    if let Some(pixels) = read_clipboard(ctx, data) {
+        file::replace(ctx, data, Document::from_pixels(pixels));
+    }
+}
 
 pub fn select_all(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
     image::move_drop(ctx, cmd, data);
 
-    let bounds = data.doc().pixels().header().bounds();
+    let bounds = data.doc().header().bounds();
     data.doc_mut().set_selection(bounds);
 }
 
@@ -42,3 +99,193 @@ pub fn deselect(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut A
 
     data.doc_mut().clear_selection();
 }
+
+/// Show the color picker for the brush color.
+pub fn brush_color(ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    let color_picker = ColorPicker::new(data.brush_color());
+    data.set_color_picker(color_picker);
+
+    data.set_window_state(WindowState::ColorPicker);
+    let alert = alert::color_picker(data.window_pos());
+    ctx.new_window(alert);
+}
+
+/// Get the pixels to cut or copy. If the selection is floating, then that's what we get.
+/// Otherwise it's whatever is under the selection, in the current layer.
+fn capture(data: &AppState) -> Option<PixelState> {
+    match (data.doc().move_info(), data.doc().selection()) {
+        (Some(move_info), _) => Some(move_info.pixels().clone()),
+        (None, Some(_)) => data.doc().clone_selection(),
+        (None, None) => None,
+    }
+}
+
+/// Remove what was cut. A floating selection isn't part of the layer yet, so it's simply
+/// discarded, leaving whatever is underneath it alone. Otherwise the selected area of the
+/// layer is cleared.
+fn remove(data: &mut AppState) {
+    if data.doc().is_moving() {
+        data.doc_mut().clear_move_info();
+    } else {
+        transforms::apply(data, transforms::simple::clear, 0.0);
+    }
+}
+
+/// Float pasted pixels over the image, at the top-left of the current selection, or the
+/// top-left of the image if there isn't one. Anything hanging off the edge is lost.
+fn float(data: &mut AppState, pixels: &PixelState) {
+    let origin = data
+        .doc()
+        .selection()
+        .map_or(druid::Point::new(1.0, 1.0), |s| s.origin());
+
+    let header = data.doc().header();
+    let width = pixels
+        .header()
+        .width()
+        .min(header.width() + 1 - origin.x as usize);
+    let height = pixels
+        .header()
+        .height()
+        .min(header.height() + 1 - origin.y as usize);
+    let pixels = pixels.clone_area(druid::Rect::new(
+        1.0,
+        1.0,
+        width as f64 + 1.0,
+        height as f64 + 1.0,
+    ));
+    let pixels = data.doc().quantize(&pixels);
+    let selection =
+        druid::Rect::from_origin_size(origin, (width as f64 - 1.0, height as f64 - 1.0));
+
+    data.doc_mut().set_selection(selection);
+    data.doc_mut()
+        .set_move_info(MoveInfo::new(origin, selection, pixels));
+    data.set_tool_type(ToolType::Move);
+}
+
+/// Put pixels onto our own clipboard, and the system clipboard too, so that other apps can
+/// paste them.
+fn write_clipboard(data: &mut AppState, pixels: PixelState) {
+    let pixels = data.doc().resolve(&pixels);
+    let mut bytes = Vec::new();
+    if storage::png::write(&mut bytes, &pixels).is_ok() {
+        let mut clipboard = druid::Application::global().clipboard();
+        clipboard.put_formats(&[druid::ClipboardFormat::new(PNG_FORMAT, bytes)]);
+    }
+
+    data.set_clipboard(pixels);
+}
+
+/// Get pixels from the clipboard. Another app might have copied an image since we last did,
+/// so the system clipboard wins. Otherwise we fall back to our own. If there's an image on
+/// the system clipboard that we can't read, then we say so, rather than pasting something
+/// stale.
+fn read_clipboard(ctx: &mut druid::DelegateCtx, data: &mut AppState) -> Option<PixelState> {
+    let clipboard = druid::Application::global().clipboard();
+
+    match clipboard.get_format(PNG_FORMAT) {
+        Some(bytes) => match storage::png::read_image(bytes.as_slice()) {
+            Ok(pixels) => Some(pixels),
+            Err(e) => {
+                data.set_window_state(WindowState::OpenFailed);
+                let alert = alert::open_failed(data.window_pos(), e);
+                ctx.new_window(alert);
+                None
+            }
+        },
+        None => data.clipboard().cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::pixels::PixelHeader;
+    use crate::util::shapes;
+
+    fn app_state() -> AppState {
+        let mut data = AppState::new(druid::WindowId::next());
+        let header = PixelHeader::new(4, 4, 8, 4);
+        let bytes = (0..16).flat_map(|i| [i as u8, 0, 0, 255]).collect();
+        data.set_doc(Document::from_pixels(PixelState::new(header, bytes)));
+        data
+    }
+
+    #[test]
+    fn it_captures_the_selection() {
+        let mut data = app_state();
+        assert!(capture(&data).is_none());
+
+        let selection = druid::Rect::new(2.0, 2.0, 3.0, 4.0);
+        data.doc_mut().set_selection(selection);
+        let pixels = capture(&data).unwrap();
+        let expected = data
+            .doc()
+            .pixels()
+            .clone_area(shapes::inflate_rect(selection));
+        assert_eq!(2, pixels.header().width());
+        assert_eq!(3, pixels.header().height());
+        assert_eq!(expected.bytes(), pixels.bytes());
+
+        // A floating selection is captured as it is, not what's underneath it.
+        let floating = PixelState::empty(PixelHeader::new(2, 3, 8, 4));
+        data.doc_mut().set_move_info(MoveInfo::new(
+            druid::Point::ZERO,
+            selection,
+            floating.clone(),
+        ));
+        assert_eq!(floating.bytes(), capture(&data).unwrap().bytes());
+    }
+
+    #[test]
+    fn it_cuts_the_selection() {
+        let mut data = app_state();
+        let selection = druid::Rect::new(2.0, 2.0, 3.0, 3.0);
+        data.doc_mut().set_selection(selection);
+        remove(&mut data);
+
+        let area = shapes::inflate_rect(selection);
+        let cleared = data.doc().pixels().clone_area(area);
+        assert!(cleared.bytes().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn it_cuts_a_floating_selection() {
+        let mut data = app_state();
+        let before = data.doc().pixels().bytes().clone();
+
+        // The pixels under the float are left as they were.
+        let header = PixelHeader::new(2, 2, 8, 4);
+        float(
+            &mut data,
+            &PixelState::new(header, [9, 8, 7, 255].repeat(4)),
+        );
+        remove(&mut data);
+
+        assert!(data.doc().move_info().is_none());
+        assert_eq!(&before, data.doc().pixels().bytes());
+    }
+
+    #[test]
+    fn it_floats_pasted_pixels() {
+        let mut data = app_state();
+        data.doc_mut()
+            .set_selection(druid::Rect::new(3.0, 2.0, 3.0, 2.0));
+
+        // Pasted pixels are clipped to the edge of the image.
+        let header = PixelHeader::new(3, 2, 8, 4);
+        let pixels = PixelState::new(header, [9, 8, 7, 255].repeat(6));
+        float(&mut data, &pixels);
+
+        let move_info = data.doc().move_info().unwrap();
+        assert_eq!(2, move_info.pixels().header().width());
+        assert_eq!(2, move_info.pixels().header().height());
+        assert_eq!(&[9, 8, 7, 255].repeat(4), move_info.pixels().bytes());
+        assert_eq!(
+            Some(druid::Rect::new(3.0, 2.0, 4.0, 3.0)),
+            data.doc().selection()
+        );
+        assert_eq!(ToolType::Move, data.tool_type());
+    }
+}
@@ -21,15 +21,31 @@ use crate::view::alert;
 pub fn new(ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
     assert!(data.window_state() == WindowState::Normal);
 
-    if data.doc().pixels().dirty() {
+    // Forget about any open that was cancelled, so that we don't open it by mistake.
+    data.doc_mut().clear_new_path();
+    data.clear_pending_doc();
+
+    if data.doc().dirty() {
         data.set_window_state(WindowState::UnsavedAlert);
         let alert = alert::unsaved_file(data.window_pos());
         ctx.new_window(alert);
     } else {
-        data.set_doc(Default::default());
+        new_image(ctx, data);
     }
 }
 
+/// Create the new image, once the user has picked the size and background.
+pub fn new_internal(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    let doc = data.new_image_options().to_document(data.brush_color());
+    data.set_doc(doc);
+}
+
+fn new_image(ctx: &mut druid::DelegateCtx, data: &mut AppState) {
+    data.set_window_state(WindowState::NewImage);
+    let alert = alert::new_image(data.window_pos());
+    ctx.new_window(alert);
+}
+
 pub fn open(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
     assert!(data.window_state() == WindowState::Normal);
 
@@ -38,8 +54,9 @@ pub fn open(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppSt
     let path = file_info.path().to_str().unwrap();
 
     data.doc_mut().set_new_path(String::from(path));
+    data.clear_pending_doc();
 
-    if data.doc().pixels().dirty() {
+    if data.doc().dirty() {
         data.set_window_state(WindowState::UnsavedAlert);
         let alert = alert::unsaved_file(data.window_pos());
         ctx.new_window(alert);
@@ -51,9 +68,11 @@ pub fn open(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppSt
 pub fn open_internal(ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
     assert!(data.window_state() != WindowState::UnsavedAlert);
 
-    if let Some(new_path) = data.doc().new_path() {
-        match storage::png::read_path(&new_path) {
-            Ok(pixels) => data.set_doc(Document::new(pixels, new_path)),
+    if let Some(doc) = data.take_pending_doc() {
+        data.set_doc(doc);
+    } else if let Some(new_path) = data.doc().new_path() {
+        match storage::read_path(&new_path) {
+            Ok(doc) => data.set_doc(doc),
             Err(e) => {
                 data.set_window_state(WindowState::OpenFailed);
                 let alert = alert::open_failed(data.window_pos(), e);
@@ -61,7 +80,23 @@ pub fn open_internal(ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data:
             }
         }
     } else {
-        data.set_doc(Default::default());
+        new_image(ctx, data);
+    }
+}
+
+/// Replace the document with one that was made some other way, e.g. pasted or imported.
+/// If there are unsaved changes then we ask about them first, and the new document waits
+/// until the user has decided.
+pub fn replace(ctx: &mut druid::DelegateCtx, data: &mut AppState, doc: Document) {
+    data.doc_mut().clear_new_path();
+
+    if data.doc().dirty() {
+        data.set_pending_doc(doc);
+        data.set_window_state(WindowState::UnsavedAlert);
+        let alert = alert::unsaved_file(data.window_pos());
+        ctx.new_window(alert);
+    } else {
+        data.set_doc(doc);
     }
 }
 
@@ -69,9 +104,9 @@ pub fn save(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut App
     assert!(data.window_state() == WindowState::Normal);
 
     if let Some(path) = data.doc().path() {
-        match storage::png::write_path(&path, data.doc().pixels()) {
+        match storage::write_path(&path, data.doc()) {
             Ok(()) => {
-                data.doc_mut().pixels_mut().clear_dirty();
+                data.doc_mut().clear_dirty();
             }
             Err(_e) => {}
         };
@@ -85,12 +120,13 @@ pub fn save_as(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut Ap
     let file_info = cmd.get_unchecked(druid::commands::SAVE_FILE_AS);
     let path = file_info.path().to_str().unwrap();
 
-    match storage::png::write_path(path, data.doc().pixels()) {
+    match storage::write_path(path, data.doc()) {
         Ok(()) => {
             if data.window_state() == WindowState::UnsavedSave {
+                data.reset_window_state();
                 open_internal(ctx, cmd, data);
             } else {
-                data.doc_mut().pixels_mut().clear_dirty();
+                data.doc_mut().clear_dirty();
                 data.doc_mut().set_path(String::from(path));
             }
         }
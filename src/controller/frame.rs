@@ -0,0 +1,80 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::image;
+use crate::common::commands;
+use crate::model::app::AppState;
+use crate::model::types::WindowState;
+use crate::view::alert;
+
+pub fn add(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+    data.doc_mut().add_frame();
+}
+
+pub fn duplicate(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+    data.doc_mut().duplicate_frame();
+}
+
+pub fn delete(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+    data.doc_mut().delete_frame();
+}
+
+pub fn move_earlier(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let idx = data.doc().current_frame();
+    if idx > 0 {
+        data.doc_mut().move_frame(idx - 1);
+    }
+}
+
+pub fn move_later(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let idx = data.doc().current_frame();
+    data.doc_mut().move_frame(idx + 1);
+}
+
+/// Step to the next frame. Wraps around to the first frame, like the animation does.
+pub fn next(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let idx = (data.doc().current_frame() + 1) % data.doc().frames().len();
+    data.doc_mut().set_current_frame(idx);
+}
+
+/// Step to the previous frame. Wraps around to the last frame.
+pub fn previous(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let len = data.doc().frames().len();
+    let idx = (data.doc().current_frame() + len - 1) % len;
+    data.doc_mut().set_current_frame(idx);
+}
+
+pub fn duration(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let duration = *cmd.get_unchecked(commands::FRAME_DURATION);
+    data.doc_mut().set_frame_duration(duration);
+}
+
+pub fn tag(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    data.set_window_state(WindowState::FrameTag);
+    let alert = alert::frame_tag(data.window_pos());
+    ctx.new_window(alert);
+}
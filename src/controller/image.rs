@@ -16,6 +16,8 @@ use crate::common::commands;
 use crate::controller::undo;
 use crate::model::app::AppState;
 use crate::model::document::MoveInfo;
+use crate::model::mask::Mask;
+use crate::model::stroke::Stroke;
 use crate::model::types::*;
 use crate::transforms;
 use crate::util::shapes;
@@ -35,6 +37,36 @@ pub fn clear(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppS
     transforms::apply(data, transforms::simple::clear, 0.0);
 }
 
+/// Switch between RGBA and indexed color. Every frame and layer is converted, and the whole
+/// lot is a single undo step.
+pub fn color_mode(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    move_drop(ctx, cmd, data);
+
+    let color_mode = *cmd.get_unchecked(commands::IMAGE_COLOR_MODE);
+    if color_mode != data.doc().color_mode() && data.doc().can_set_color_mode(color_mode) {
+        undo::push_cels(data);
+        data.doc_mut().set_color_mode(color_mode);
+    }
+}
+
+/// Crop the image to the selection. Every frame and layer is cropped.
+pub fn crop(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    move_drop(ctx, cmd, data);
+
+    if let Some(selection) = data.doc().selection() {
+        let bounds = shapes::inflate_rect(data.doc().header().bounds());
+        let area = shapes::inflate_rect(selection).intersect(bounds);
+        crop_to(data, area);
+    }
+}
+
+fn crop_to(data: &mut AppState, area: druid::Rect) {
+    if area.area() > 0.0 && area != shapes::inflate_rect(data.doc().header().bounds()) {
+        undo::push_cels(data);
+        data.doc_mut().crop(area);
+    }
+}
+
 pub fn darken(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
     move_drop(ctx, cmd, data);
     transforms::apply(data, transforms::colors::brightness, -0.05);
@@ -51,13 +83,8 @@ pub fn dither_floyd(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &m
 }
 
 pub fn eraser(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
-    if *cmd.get_unchecked(commands::IMAGE_ERASER) != ToolState::End {
-        let current_pos = data.current_pos();
-        undo::push_point(data, current_pos);
-
-        let color = druid::Color::rgba8(0, 0, 0, 0);
-        data.doc_mut().pixels_mut().write(current_pos, &color);
-    }
+    let state = *cmd.get_unchecked(commands::IMAGE_ERASER);
+    stroke(data, state, &druid::Color::rgba8(0, 0, 0, 0));
 }
 
 pub fn fill(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
@@ -72,15 +99,45 @@ pub fn fill(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppSt
     transforms::apply(data, f, 0.0);
 }
 
-pub fn marquee(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+pub fn fill_shapes(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    data.flip_fill_shapes();
+}
+
+/// Select whatever is inside the lasso. Works for both the freehand and polygonal lasso.
+pub fn lasso(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    move_drop(ctx, cmd, data);
+
+    let points = cmd.get_unchecked(commands::IMAGE_LASSO);
+    let bounds = data.doc().header().bounds();
+    let mask = Mask::from_polygon(points, bounds);
+    let mode = data.selection_mode();
+    data.doc_mut().combine_selection(mask, mode);
+}
+
+pub fn marquee(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
     let start_pos = data.start_pos();
     let current_pos = data.current_pos();
 
     let old_selection = data.doc().selection().unwrap_or(druid::Rect::ZERO);
     let new_selection = shapes::enclosing_rect(start_pos, current_pos);
 
-    if old_selection != new_selection {
-        data.doc_mut().set_selection(new_selection);
+    match data.selection_mode() {
+        SelectionMode::Replace => {
+            if old_selection != new_selection || data.doc().mask().is_some() {
+                data.doc_mut().set_selection(new_selection);
+            }
+        }
+
+        // Combining only happens once the marquee is released. Until then, the canvas
+        // shows the marquee on its own.
+        mode => {
+            let state = *cmd.get_unchecked(commands::IMAGE_MARQUEE);
+            if state == ToolState::End && start_pos != druid::Point::ZERO {
+                let bounds = data.doc().header().bounds();
+                let mask = Mask::from_rect(new_selection.intersect(bounds));
+                data.doc_mut().combine_selection(Some(mask), mode);
+            }
+        }
     }
 }
 
@@ -88,31 +145,21 @@ pub fn move_(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut App
     if let Some(selection) = data.doc().selection() {
         match *cmd.get_unchecked(commands::IMAGE_MOVE) {
             ToolState::Start => {
-                if !data.doc().is_moving() {
+                if let Some(move_info) = data.doc().move_info() {
+                    // Already floating (say, after a paste). Just pick it up from where
+                    // it was grabbed this time.
                     let current_pos = data.current_pos();
-                    let clone_rect = shapes::inflate_rect(selection);
-                    let pixels = data.doc().pixels().clone_area(clone_rect);
-
+                    let pixels = move_info.pixels().clone();
                     let move_info = MoveInfo::new(current_pos, selection, pixels);
                     data.doc_mut().set_move_info(move_info);
-
-                    // FIXME: Yikes, we're always pushing the entire image onto the undo
-                    // stack. The problem is that we need to remember the pixels that we're
-                    // about to drag, *and* the pixels that we'll replace when we eventually
-                    // drop the drag. These might be non-overlapping areas, which our undo
-                    // stack doesn't support. One alternative is to use two undo records, but
-                    // that gets super messy. Another alternative is to push the union onto the
-                    // undo stack at the time we drop.
-                    let bounds = data.doc().pixels().header().bounds();
-                    undo::push(data, bounds);
-
-                    transforms::apply_no_undo(data, transforms::simple::clear, 0.0);
+                } else if data.doc().editable() {
+                    lift(data, selection);
                 }
             }
 
             ToolState::Move => {
                 if let Some(move_info) = data.doc().move_info() {
-                    let bounds = data.doc().pixels().header().bounds();
+                    let bounds = data.doc().header().bounds();
                     let current_pos = data.current_pos();
                     let offset = move_info.offset();
 
@@ -123,7 +170,7 @@ pub fn move_(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut App
                     let rect = shapes::offset_rect(point_rect, offset);
                     let new_selection = shapes::constrain_rect(rect, bounds);
 
-                    data.doc_mut().set_selection(new_selection);
+                    data.doc_mut().move_selection(new_selection);
                 }
             }
 
@@ -132,24 +179,275 @@ pub fn move_(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut App
     }
 }
 
+/// Lift the selected pixels out of the image, leaving the selection clear behind them. The
+/// lift is a step of its own, which only records the area that was lifted.
+fn lift(data: &mut AppState, selection: druid::Rect) {
+    let current_pos = data.current_pos();
+    let pixels = data.doc().clone_selection().unwrap();
+    data.doc_mut()
+        .set_move_info(MoveInfo::new(current_pos, selection, pixels));
+
+    undo::push(data, shapes::inflate_rect(selection));
+    transforms::apply_no_undo(data, transforms::simple::clear, 0.0);
+}
+
+/// Drop the floating selection, if there is one, where it currently sits. The drop is a
+/// step of its own, which only records the area that was dropped onto. If the layer was
+/// locked or hidden while the selection was floating, then it's discarded instead.
 pub fn move_drop(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
-    if data.doc().is_moving() {
-        let selection = shapes::inflate_rect(data.doc().selection().unwrap());
-        let move_info = data.doc().move_info().unwrap().to_owned();
-        let bytes = move_info.pixels().bytes();
+    drop_floating(data);
+}
+
+fn drop_floating(data: &mut AppState) {
+    if let Some(selection) = data.doc().selection() {
+        if data.doc().is_moving() && data.doc().editable() {
+            let move_info = data.doc().move_info().unwrap().to_owned();
+            let bytes = move_info.pixels().bytes();
+
+            let bounds = shapes::inflate_rect(data.doc().header().bounds());
+            let area = shapes::inflate_rect(selection).intersect(bounds);
+            undo::push(data, area);
 
-        data.doc_mut().pixels_mut().write_area(selection, bytes);
+            data.doc_mut().write_selection(bytes);
+        }
     }
 
     data.doc_mut().clear_move_info();
 }
 
+/// Draw a shape from the line, rectangle or ellipse tools. The whole shape is a single
+/// undo step, and anything outside the selection is left alone.
+pub fn shape(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    move_drop(ctx, cmd, data);
+
+    if !data.doc().editable() {
+        return;
+    }
+
+    let bounds = data.doc().bounds();
+    let points: Vec<druid::Point> = cmd
+        .get_unchecked(commands::IMAGE_SHAPE)
+        .iter()
+        .copied()
+        .filter(|&p| bounds.contains(p) && data.doc().is_selected(p))
+        .collect();
+
+    let area = match shapes::bounding_rect(points.iter().copied()) {
+        Some(area) => shapes::inflate_rect(area),
+        None => return,
+    };
+    undo::push(data, area);
+
+    let color = data.brush_color().clone();
+    for p in points {
+        data.doc_mut().write(p, &color);
+    }
+}
+
+/// Carry the brush stroke on from the last mouse position to this one. Mouse samples can
+/// be far apart, so the stroke fills in the gaps.
+fn stroke(data: &mut AppState, state: ToolState, color: &druid::Color) {
+    // The whole stroke is a single undo step.
+    match state {
+        ToolState::Start => {
+            data.set_stroke(Stroke::new(data.pixel_perfect()));
+            undo::begin(data);
+        }
+        ToolState::Move => {}
+        ToolState::End => {
+            undo::commit(data);
+            return;
+        }
+    }
+
+    let current_pos = data.current_pos();
+    if current_pos == druid::Point::ZERO || !data.doc().editable() {
+        return;
+    }
+
+    let mut stroke = data.stroke().clone();
+    let doc = data.doc();
+    let (paint, restore) = stroke.extend(current_pos, |p| doc.read(p));
+    data.set_stroke(stroke);
+
+    let bounds = data.doc().bounds();
+    let writes: Vec<(druid::Point, druid::Color)> = restore
+        .into_iter()
+        .chain(paint.into_iter().map(|p| (p, color.clone())))
+        .filter(|&(p, _)| bounds.contains(p) && data.doc().is_selected(p))
+        .collect();
+
+    let area = match shapes::bounding_rect(writes.iter().map(|&(p, _)| p)) {
+        Some(area) => shapes::inflate_rect(area),
+        None => return,
+    };
+    undo::push(data, area);
+
+    for (p, color) in writes {
+        data.doc_mut().write(p, &color);
+    }
+}
+
+/// Crop away any fully transparent rows and columns around the edges of the image. This
+/// takes every frame and layer into account, so that nothing is lost.
+pub fn trim(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    move_drop(ctx, cmd, data);
+
+    if let Some(area) = data.doc().opaque_bounds() {
+        crop_to(data, area);
+    }
+}
+
+/// Flip or rotate the selection, or the whole image if there isn't one. The whole image
+/// means every layer of every frame, and a quarter turn swaps its width and height.
+pub fn orient(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    move_drop(ctx, cmd, data);
+
+    let orientation = *cmd.get_unchecked(commands::IMAGE_ORIENT);
+    let selection = match data.doc().selection() {
+        Some(selection) => selection,
+        None => {
+            transforms::apply_image(data, |header, bytes| {
+                transforms::geometry::transform_image(header, bytes, orientation)
+            });
+            return;
+        }
+    };
+
+    if !data.doc().editable() {
+        return;
+    }
+
+    // A quarter turn moves pixels outside the selection, so the undo record has to
+    // cover both where they were and where they end up.
+    let bounds = data.doc().header().bounds();
+    let area = transforms::geometry::transformed_area(orientation, selection);
+    let undo_area = shapes::inflate_rect(selection.union(area).intersect(bounds));
+    transforms::apply_geometry_over(
+        data,
+        undo_area,
+        |header, env, bytes| transforms::geometry::transform(header, env, bytes, orientation),
+        0.0,
+    );
+
+    // The selection follows the pixels.
+    let mask = data.doc().selection_mask().and_then(|mask| {
+        mask.map(area.intersect(bounds), |p| {
+            transforms::geometry::transform_point(orientation, selection, area.origin(), p)
+        })
+    });
+    data.doc_mut()
+        .combine_selection(mask, SelectionMode::Replace);
+}
+
 pub fn paint(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
-    if *cmd.get_unchecked(commands::IMAGE_PAINT) != ToolState::End {
-        let current_pos = data.current_pos();
-        undo::push_point(data, current_pos);
+    let state = *cmd.get_unchecked(commands::IMAGE_PAINT);
+    let color = data.brush_color().clone();
+    stroke(data, state, &color);
+}
+
+pub fn pixel_perfect(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    data.flip_pixel_perfect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: (u8, u8, u8, u8) = (255, 0, 0, 255);
+    const BLUE: (u8, u8, u8, u8) = (0, 0, 255, 255);
+
+    fn app_state() -> AppState {
+        let mut data = AppState::new(druid::WindowId::next());
+        data.set_doc(crate::model::document::Document::new(
+            8,
+            8,
+            &druid::Color::rgba8(0, 0, 0, 0),
+        ));
+
+        let (r, g, b, a) = RED;
+        for &(x, y) in [(2.0, 2.0), (3.0, 2.0), (2.0, 3.0), (3.0, 3.0)].iter() {
+            let p = druid::Point::new(x, y);
+            data.doc_mut().write(p, &druid::Color::rgba8(r, g, b, a));
+        }
+        let (r, g, b, a) = BLUE;
+        data.doc_mut().write(
+            druid::Point::new(6.0, 6.0),
+            &druid::Color::rgba8(r, g, b, a),
+        );
+        data
+    }
+
+    fn read(data: &AppState, x: f64, y: f64) -> (u8, u8, u8, u8) {
+        data.doc().read(druid::Point::new(x, y)).as_rgba8()
+    }
+
+    /// Lift the 2x2 block of red and drop it with its top left corner at the given point.
+    fn move_to(data: &mut AppState, x: f64, y: f64) {
+        data.doc_mut()
+            .set_selection(druid::Rect::new(2.0, 2.0, 3.0, 3.0));
+        lift(data, druid::Rect::new(2.0, 2.0, 3.0, 3.0));
+        data.doc_mut()
+            .move_selection(druid::Rect::new(x, y, x + 1.0, y + 1.0));
+        drop_floating(data);
+    }
+
+    fn check_undo_redo(x: f64, y: f64) {
+        let mut data = app_state();
+        let before = data.doc().pixels().bytes().clone();
+        move_to(&mut data, x, y);
+        let after = data.doc().pixels().bytes().clone();
+
+        // The drop is undone first, and then the lift.
+        undo::pop(&mut data);
+        assert_eq!((0, 0, 0, 0), read(&data, 2.0, 2.0));
+        undo::pop(&mut data);
+        assert_eq!(&before, data.doc().pixels().bytes());
+        assert!(data.doc().undo().is_empty());
+
+        undo::pop_redo(&mut data);
+        undo::pop_redo(&mut data);
+        assert_eq!(&after, data.doc().pixels().bytes());
+        assert!(data.doc().redo().is_empty());
+    }
+
+    #[test]
+    fn it_moves_without_overlap() {
+        let mut data = app_state();
+        move_to(&mut data, 5.0, 5.0);
+        assert_eq!((0, 0, 0, 0), read(&data, 2.0, 2.0));
+        assert_eq!(RED, read(&data, 5.0, 5.0));
+        assert_eq!(RED, read(&data, 6.0, 6.0));
+
+        check_undo_redo(5.0, 5.0);
+    }
+
+    #[test]
+    fn it_moves_with_overlap() {
+        let mut data = app_state();
+        move_to(&mut data, 3.0, 3.0);
+        assert_eq!((0, 0, 0, 0), read(&data, 2.0, 2.0));
+        assert_eq!(RED, read(&data, 3.0, 3.0));
+        assert_eq!(RED, read(&data, 4.0, 4.0));
+        assert_eq!(BLUE, read(&data, 6.0, 6.0));
+
+        check_undo_redo(3.0, 3.0);
+    }
+
+    #[test]
+    fn it_discards_a_float_over_a_locked_layer() {
+        let mut data = app_state();
+        data.doc_mut()
+            .set_selection(druid::Rect::new(2.0, 2.0, 3.0, 3.0));
+        lift(&mut data, druid::Rect::new(2.0, 2.0, 3.0, 3.0));
+        data.doc_mut()
+            .move_selection(druid::Rect::new(5.0, 5.0, 6.0, 6.0));
+        let lifted = data.doc().pixels().bytes().clone();
 
-        let color = data.brush_color().clone();
-        data.doc_mut().pixels_mut().write(current_pos, &color);
+        data.doc_mut().modify_layer(|layer| layer.set_locked(true));
+        drop_floating(&mut data);
+        assert!(!data.doc().is_moving());
+        assert_eq!(&lifted, data.doc().pixels().bytes());
+        assert_eq!(BLUE, read(&data, 6.0, 6.0));
     }
 }
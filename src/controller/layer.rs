@@ -0,0 +1,69 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::image;
+use crate::common::commands;
+use crate::model::app::AppState;
+
+pub fn add(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+    data.doc_mut().add_layer();
+}
+
+pub fn delete(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+    data.doc_mut().delete_layer();
+}
+
+pub fn select_above(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let idx = data.doc().current_layer() + 1;
+    if idx < data.doc().layers().len() {
+        data.doc_mut().set_current_layer(idx);
+    }
+}
+
+pub fn select_below(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let idx = data.doc().current_layer();
+    if idx > 0 {
+        data.doc_mut().set_current_layer(idx - 1);
+    }
+}
+
+pub fn toggle_visible(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+    data.doc_mut()
+        .modify_layer(|layer| layer.set_visible(!layer.visible()));
+}
+
+pub fn toggle_locked(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+    data.doc_mut()
+        .modify_layer(|layer| layer.set_locked(!layer.locked()));
+}
+
+pub fn blend_mode(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let blend_mode = *cmd.get_unchecked(commands::LAYER_BLEND_MODE);
+    data.doc_mut()
+        .modify_layer(|layer| layer.set_blend_mode(blend_mode));
+}
+
+pub fn opacity(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let opacity = *cmd.get_unchecked(commands::LAYER_OPACITY);
+    data.doc_mut()
+        .modify_layer(|layer| layer.set_opacity(opacity));
+}
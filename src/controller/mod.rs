@@ -15,6 +15,11 @@
 pub mod delegate;
 pub mod edit;
 pub mod file;
+pub mod frame;
 pub mod image;
+pub mod layer;
+pub mod palette;
+pub mod select;
+pub mod sheet;
 pub mod undo;
 pub mod view;
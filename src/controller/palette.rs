@@ -0,0 +1,105 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::image;
+use crate::common::commands;
+use crate::model::app::AppState;
+use crate::model::palette::PaletteState;
+use crate::model::types::WindowState;
+use crate::storage;
+use crate::storage::error::StorageError;
+use crate::view::alert;
+
+/// Add the brush color to the end of the palette.
+pub fn add_color(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let value = data.brush_color().as_rgba_u32();
+    data.doc_mut().add_palette_color(value);
+}
+
+/// Delete a color from the palette. Indexed pixels that used it get the closest color
+/// that's left.
+pub fn delete_color(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let idx = *cmd.get_unchecked(commands::PALETTE_DELETE_COLOR);
+    data.doc_mut().delete_palette_color(idx);
+}
+
+/// Show the window for editing a color in the palette.
+pub fn edit_color(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let idx = *cmd.get_unchecked(commands::PALETTE_EDIT_COLOR);
+    data.set_window_state(WindowState::PaletteColor);
+    let alert = alert::palette_color(data.window_pos(), idx);
+    ctx.new_window(alert);
+}
+
+/// Replace the document's palette with one from a file. Indexed documents pick up the new
+/// colors straight away.
+pub fn load(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    // If the file dialog passes us an invalid path then all bets are off. Just let it panic.
+    let file_info = cmd.get_unchecked(commands::PALETTE_LOAD);
+    let path = file_info.path().to_str().unwrap();
+
+    match storage::palette::read_path(path) {
+        Ok(palette) => data.doc_mut().set_palette(palette),
+        Err(e) => failed(ctx, data, e),
+    }
+}
+
+/// Move a color to another spot in the palette. Indexed pixels keep their colors.
+pub fn move_color(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let (from, to) = *cmd.get_unchecked(commands::PALETTE_MOVE_COLOR);
+    data.doc_mut().move_palette_color(from, to);
+}
+
+/// Replace the document's palette with one of the built-in ones.
+pub fn preset(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let preset = *cmd.get_unchecked(commands::PALETTE_PRESET);
+    data.doc_mut()
+        .set_palette(PaletteState::from_preset(preset));
+}
+
+pub fn save(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    // If the file dialog passes us an invalid path then all bets are off. Just let it panic.
+    let file_info = cmd.get_unchecked(commands::PALETTE_SAVE);
+    let path = file_info.path().to_str().unwrap();
+
+    if let Err(e) = storage::palette::write_path(path, data.doc().palette()) {
+        failed(ctx, data, e);
+    }
+}
+
+/// Sort the palette. Indexed pixels keep their colors.
+pub fn sort(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let sort = *cmd.get_unchecked(commands::PALETTE_SORT);
+    data.doc_mut().sort_palette(sort);
+}
+
+fn failed(ctx: &mut druid::DelegateCtx, data: &mut AppState, e: StorageError) {
+    data.set_window_state(WindowState::OpenFailed);
+    let alert = alert::open_failed(data.window_pos(), e);
+    ctx.new_window(alert);
+}
@@ -0,0 +1,102 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use super::image;
+use crate::common::commands;
+use crate::model::app::AppState;
+use crate::model::mask::Mask;
+use crate::model::pixels::PixelEnv;
+use crate::model::types::SelectionMode;
+use crate::transforms;
+use crate::util::shapes;
+
+/// Select pixels that match the color under the mouse, in the current layer.
+pub fn magic_wand(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let pixels = data.doc().resolve(data.doc().pixels());
+    let header = pixels.header().clone();
+    let bounds = shapes::inflate_rect(header.bounds());
+    let env = PixelEnv::new(data.brush_color().clone(), data.current_pos(), bounds, 0.0);
+    let mask = transforms::select::magic_wand(&header, &env, pixels.bytes(), data.wand_options());
+
+    let mode = data.selection_mode();
+    data.doc_mut().combine_selection(mask, mode);
+}
+
+/// Select every pixel in the current layer that matches the brush color, within the wand
+/// tolerance.
+pub fn by_color(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let pixels = data.doc().resolve(data.doc().pixels());
+    let header = pixels.header().clone();
+    let bounds = shapes::inflate_rect(header.bounds());
+    let color = data.brush_color().clone();
+    let env = PixelEnv::new(color.clone(), druid::Point::ZERO, bounds, 0.0);
+    let tolerance = data.wand_options().tolerance();
+    let mask = transforms::select::by_color(&header, &env, pixels.bytes(), &color, tolerance);
+
+    data.doc_mut()
+        .combine_selection(mask, SelectionMode::Replace);
+}
+
+pub fn grow(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let by = *cmd.get_unchecked(commands::SELECT_GROW);
+    let bounds = data.doc().header().bounds();
+    if let Some(mask) = data.doc().selection_mask() {
+        let mask = mask.grow(by, bounds);
+        data.doc_mut()
+            .combine_selection(mask, SelectionMode::Replace);
+    }
+}
+
+/// Select everything that isn't selected. Inverting an empty selection selects the whole
+/// image.
+pub fn invert(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let bounds = data.doc().header().bounds();
+    let mask = match data.doc().selection_mask() {
+        Some(mask) => mask.invert(bounds),
+        None => Some(Mask::from_rect(bounds)),
+    };
+    data.doc_mut()
+        .combine_selection(mask, SelectionMode::Replace);
+}
+
+pub fn shrink(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    let by = *cmd.get_unchecked(commands::SELECT_SHRINK);
+    if let Some(mask) = data.doc().selection_mask() {
+        let mask = mask.shrink(by);
+        data.doc_mut()
+            .combine_selection(mask, SelectionMode::Replace);
+    }
+}
+
+pub fn wand_contiguous(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    data.wand_options_mut().flip_contiguous();
+}
+
+pub fn wand_diagonal(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    data.wand_options_mut().flip_diagonal();
+}
+
+pub fn wand_tolerance(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let tolerance = *cmd.get_unchecked(commands::SELECT_WAND_TOLERANCE);
+    data.wand_options_mut().set_tolerance(tolerance);
+}
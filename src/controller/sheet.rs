@@ -0,0 +1,118 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::file;
+use super::image;
+use crate::common::commands;
+use crate::common::constants;
+use crate::model::app::AppState;
+use crate::model::document::Document;
+use crate::model::document::Frame;
+use crate::model::pixels::PixelState;
+use crate::model::types::WindowState;
+use crate::storage;
+use crate::storage::error::StorageError;
+use crate::view::alert;
+
+pub fn export(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    image::move_drop(ctx, cmd, data);
+
+    // If the file dialog passes us an invalid path then all bets are off. Just let it panic.
+    let file_info = cmd.get_unchecked(commands::SHEET_EXPORT);
+    let path = file_info.path().to_str().unwrap();
+
+    // Unlike a save, nothing else depends on the export, so we can tell the user
+    // right away if it didn't work.
+    if let Err(e) = storage::sheet::write_path(path, data.doc(), data.sheet_options()) {
+        failed(ctx, data, e);
+    }
+}
+
+/// The user picked a sheet to import. Ask how it should be sliced.
+pub fn import_open(ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    // If the file dialog passes us an invalid path then all bets are off. Just let it panic.
+    let file_info = cmd.get_unchecked(commands::SHEET_IMPORT_OPEN);
+    let path = file_info.path().to_str().unwrap();
+
+    data.slice_options_mut().set_path(String::from(path));
+
+    data.set_window_state(WindowState::SheetImport);
+    let alert = alert::sheet_import(data.window_pos());
+    ctx.new_window(alert);
+}
+
+/// Slice the sheet into frames of a new, untitled document, once any unsaved changes have
+/// been dealt with.
+pub fn import_frames(ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    if let Some(cells) = read_cells(ctx, data) {
+        let frames = cells
+            .into_iter()
+            .map(|cell| Frame::new(constants::DEFAULT_FRAME_DURATION, vec![cell]))
+            .collect();
+        file::replace(ctx, data, Document::from_frames(frames));
+    }
+}
+
+/// Slice the sheet into separate files, next to the sheet. The current document is left
+/// alone.
+pub fn import_files(ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    if let Some(cells) = read_cells(ctx, data) {
+        let path = data.slice_options().path().unwrap();
+        if let Err(e) = storage::sheet::write_cells(&path, &cells) {
+            failed(ctx, data, e);
+        }
+    }
+}
+
+fn read_cells(ctx: &mut druid::DelegateCtx, data: &mut AppState) -> Option<Vec<PixelState>> {
+    let path = data.slice_options().path()?;
+
+    match storage::sheet::read_path(&path, data.slice_options()) {
+        Ok(cells) => Some(cells),
+        Err(e) => {
+            failed(ctx, data, e);
+            None
+        }
+    }
+}
+
+fn failed(ctx: &mut druid::DelegateCtx, data: &mut AppState, e: StorageError) {
+    data.set_window_state(WindowState::OpenFailed);
+    let alert = alert::open_failed(data.window_pos(), e);
+    ctx.new_window(alert);
+}
+
+pub fn layout(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let layout = *cmd.get_unchecked(commands::SHEET_LAYOUT);
+    data.sheet_options_mut().set_layout(layout);
+}
+
+pub fn format(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let format = *cmd.get_unchecked(commands::SHEET_FORMAT);
+    data.sheet_options_mut().set_format(format);
+}
+
+pub fn padding(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let padding = *cmd.get_unchecked(commands::SHEET_PADDING);
+    data.sheet_options_mut().set_padding(padding);
+}
+
+pub fn extrude(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let extrude = *cmd.get_unchecked(commands::SHEET_EXTRUDE);
+    data.sheet_options_mut().set_extrude(extrude);
+}
+
+pub fn tagged_only(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    data.sheet_options_mut().flip_tagged_only();
+}
@@ -14,16 +14,21 @@
 
 use crate::model::app::AppState;
 use crate::model::mod_stack::ModRecord;
+use crate::model::mod_stack::Transaction;
+use crate::model::pixels::PixelState;
 
-/// Push a point onto the undo stack.
-pub fn push_point(data: &mut AppState, p: druid::Point) {
-    let area = druid::Rect::new(p.x, p.y, p.x + 1.0, p.y + 1.0);
-    push(data, area);
-}
-
-/// Push an area onto the undo stack.
+/// Push an area onto the undo stack. If there's a transaction in progress on the current
+/// cel, then the area is added to that instead.
 pub fn push(data: &mut AppState, area: druid::Rect) {
-    push_inner(data, area);
+    let frame = data.doc().current_frame();
+    let layer = data.doc().current_layer();
+
+    match data.doc_mut().transaction_mut() {
+        Some(transaction) if transaction.frame() == frame && transaction.layer() == layer => {
+            transaction.extend(area)
+        }
+        _ => push_inner(data, frame, layer, area),
+    }
 
     // Important: reset the redo stack!
     // This is okay: undo -> undo -> redo -> redo
@@ -31,39 +36,135 @@ pub fn push(data: &mut AppState, area: druid::Rect) {
     data.doc_mut().redo_mut().clear();
 }
 
-fn push_inner(data: &mut AppState, area: druid::Rect) {
-    let bytes = data.doc().pixels().read_area(area);
-    let record = ModRecord::new(area, bytes);
+/// Begin a transaction on the current cel. Everything that's pushed for the cel from now
+/// until the commit becomes a single step, covering all of it. Any transaction that's
+/// still in progress is committed first.
+pub fn begin(data: &mut AppState) {
+    commit(data);
+
+    let frame = data.doc().current_frame();
+    let layer = data.doc().current_layer();
+    let pixels = data.doc().pixels().clone();
+    data.doc_mut()
+        .set_transaction(Transaction::new(frame, layer, pixels));
+}
+
+/// Commit the transaction that's in progress, if there is one. Nothing goes onto the undo
+/// stack if nothing changed.
+pub fn commit(data: &mut AppState) {
+    if let Some(transaction) = data.doc_mut().take_transaction() {
+        if let Some(record) = transaction.into_record() {
+            data.doc_mut().undo_mut().push(record);
+        }
+    }
+}
+
+/// Push every cel onto the undo stack, as a single step. Each record holds the header of
+/// its cel, so use this before changing the dimensions of the image.
+pub fn push_cels(data: &mut AppState) {
+    let frames = data.doc().frames().len();
+    let layers = data.doc().layers().len();
+
+    for frame in 0..frames {
+        for layer in 0..layers {
+            let mut record = snapshot(data, frame, layer);
+            record.set_joined(frame > 0 || layer > 0);
+            data.doc_mut().undo_mut().push(record);
+        }
+    }
+
+    data.doc_mut().redo_mut().clear();
+}
+
+fn push_inner(data: &mut AppState, frame: usize, layer: usize, area: druid::Rect) {
+    let bytes = data.doc().cel(frame, layer).read_area(area);
+    let record = ModRecord::new(frame, layer, area, bytes);
 
     data.doc_mut().undo_mut().push(record);
 }
 
-/// Pop a record from the undo stack and apply it.
-pub fn pop(data: &mut AppState) {
-    if let Some(record) = data.doc_mut().undo_mut().pop() {
-        // Before we undo, record what we just did, so that we can redo it again.
-        let area = record.area();
-        push_redo(data, area);
+fn snapshot(data: &AppState, frame: usize, layer: usize) -> ModRecord {
+    let pixels = data.doc().cel(frame, layer);
+    ModRecord::with_header(
+        frame,
+        layer,
+        pixels.header().clone(),
+        pixels.bytes().clone(),
+    )
+}
+
+/// Record the state that the given record is about to overwrite.
+fn opposite(data: &AppState, record: &ModRecord, joined: bool) -> ModRecord {
+    let frame = record.frame();
+    let layer = record.layer();
+
+    let mut opposite = match record.header() {
+        Some(_) => snapshot(data, frame, layer),
+        None => {
+            let bytes = data.doc().cel(frame, layer).read_area(record.area());
+            ModRecord::new(frame, layer, record.area(), bytes)
+        }
+    };
+    opposite.set_joined(joined);
+    opposite
+}
+
+fn apply(data: &mut AppState, record: &ModRecord) {
+    let pixels = data.doc_mut().cel_mut(record.frame(), record.layer());
+
+    if let Some(header) = record.header() {
+        *pixels = PixelState::empty(header.clone());
+    }
+    pixels.write_area(record.area(), record.bytes());
+}
 
-        data.doc_mut().pixels_mut().write_area(area, record.bytes());
+/// Cels that were added after the step was recorded won't have been restored. Make sure
+/// they match the dimensions of the rest of the image.
+fn conform(data: &mut AppState, record: &ModRecord) {
+    if let Some(header) = record.header() {
+        let doc = data.doc_mut();
+        doc.conform(header);
+        doc.clear_selection();
     }
 }
 
-fn push_redo(data: &mut AppState, area: druid::Rect) {
-    let bytes = data.doc().pixels().read_area(area);
-    let record = ModRecord::new(area, bytes);
+/// Pop a step from the undo stack and apply it. Joined records are applied together.
+pub fn pop(data: &mut AppState) {
+    commit(data);
+
+    let mut joined = false;
+
+    while let Some(record) = data.doc_mut().undo_mut().pop() {
+        // Before we undo, record what we just did, so that we can redo it again.
+        let redo = opposite(data, &record, joined);
+        data.doc_mut().redo_mut().push(redo);
+        apply(data, &record);
 
-    data.doc_mut().redo_mut().push(record);
+        if !record.joined() {
+            conform(data, &record);
+            break;
+        }
+        joined = true;
+    }
 }
 
-/// Pop a record from the redo stack and apply it.
+/// Pop a step from the redo stack and apply it. Joined records are applied together.
 pub fn pop_redo(data: &mut AppState) {
-    if let Some(record) = data.doc_mut().redo_mut().pop() {
+    commit(data);
+
+    let mut joined = false;
+
+    while let Some(record) = data.doc_mut().redo_mut().pop() {
         // Before we redo, record what we just did, so that we can undo it again.
-        // But call the inner function, so that we don't reset the redo stack!
-        let area = record.area();
-        push_inner(data, record.area());
+        // But don't go through push, so that we don't reset the redo stack!
+        let undo = opposite(data, &record, joined);
+        data.doc_mut().undo_mut().push(undo);
+        apply(data, &record);
 
-        data.doc_mut().pixels_mut().write_area(area, record.bytes());
+        if !record.joined() {
+            conform(data, &record);
+            break;
+        }
+        joined = true;
     }
 }
@@ -12,8 +12,54 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::common::commands;
 use crate::model::app::AppState;
+use crate::view::canvas;
+use crate::view::theme;
 
 pub fn show_grid(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
     data.flip_grid()
 }
+
+pub fn show_onion_skin(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    data.flip_onion_skin()
+}
+
+pub fn onion_skin_before(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let before = *cmd.get_unchecked(commands::VIEW_ONION_SKIN_BEFORE);
+    data.set_onion_skin_before(before);
+}
+
+pub fn onion_skin_after(_ctx: &mut druid::DelegateCtx, cmd: &druid::Command, data: &mut AppState) {
+    let after = *cmd.get_unchecked(commands::VIEW_ONION_SKIN_AFTER);
+    data.set_onion_skin_after(after);
+}
+
+/// Zoom so that the whole image fits in the canvas.
+pub fn zoom_fit(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    let header = data.doc().header().clone();
+    data.viewport_mut().fit(
+        &header,
+        theme::CANVAS_MIN_VIEW_SIZE,
+        theme::CANVAS_MAX_VIEW_RECT,
+    );
+}
+
+/// Show the image at one screen pixel per image pixel.
+pub fn zoom_actual(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    let center = canvas::view_rect(data).center();
+    data.viewport_mut().set_zoom(1, center);
+    canvas::settle(data);
+}
+
+pub fn zoom_in(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    let center = canvas::view_rect(data).center();
+    data.viewport_mut().zoom_in(center);
+    canvas::settle(data);
+}
+
+pub fn zoom_out(_ctx: &mut druid::DelegateCtx, _cmd: &druid::Command, data: &mut AppState) {
+    let center = canvas::view_rect(data).center();
+    data.viewport_mut().zoom_out(center);
+    canvas::settle(data);
+}
@@ -12,7 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::color_picker::ColorPicker;
 use super::document::Document;
+use super::new_image::NewImageOptions;
+use super::pixels::PixelState;
+use super::sheet::SheetOptions;
+use super::sheet::SliceOptions;
+use super::stroke::Stroke;
+use super::viewport::Viewport;
+use super::wand::WandOptions;
+use crate::common::constants;
 use crate::model::types::*;
 
 /// Application state.
@@ -25,8 +34,23 @@ pub struct AppState {
     window_pos: druid::Point,
     window_state: WindowState,
     tool_type: ToolType,
+    selection_mode: SelectionMode,
+    fill_shapes: bool,
+    pixel_perfect: bool,
+    stroke: Stroke,
     show_grid: bool,
+    show_onion_skin: bool,
+    onion_skin_before: usize,
+    onion_skin_after: usize,
+    new_image_options: NewImageOptions,
+    color_picker: ColorPicker,
+    sheet_options: SheetOptions,
+    slice_options: SliceOptions,
+    wand_options: WandOptions,
+    viewport: Viewport,
+    clipboard: Option<PixelState>,
     doc: Document,
+    pending_doc: Option<Document>,
 
     #[data(same_fn = "PartialEq::eq")]
     window_id: druid::WindowId,
@@ -42,8 +66,23 @@ impl AppState {
             window_pos: Default::default(),
             window_state: Default::default(),
             tool_type: ToolType::Paint,
+            selection_mode: Default::default(),
+            fill_shapes: false,
+            pixel_perfect: false,
+            stroke: Default::default(),
             show_grid: true,
+            show_onion_skin: false,
+            onion_skin_before: 1,
+            onion_skin_after: 1,
+            new_image_options: Default::default(),
+            color_picker: Default::default(),
+            sheet_options: Default::default(),
+            slice_options: Default::default(),
+            wand_options: Default::default(),
+            viewport: Default::default(),
+            clipboard: None,
             doc: Default::default(),
+            pending_doc: None,
             window_id,
         }
     }
@@ -108,6 +147,39 @@ impl AppState {
         self.tool_type = tool_type;
     }
 
+    pub fn selection_mode(&self) -> SelectionMode {
+        self.selection_mode
+    }
+
+    pub fn set_selection_mode(&mut self, selection_mode: SelectionMode) {
+        self.selection_mode = selection_mode;
+    }
+
+    pub fn fill_shapes(&self) -> bool {
+        self.fill_shapes
+    }
+
+    pub fn flip_fill_shapes(&mut self) {
+        self.fill_shapes = !self.fill_shapes;
+    }
+
+    pub fn pixel_perfect(&self) -> bool {
+        self.pixel_perfect
+    }
+
+    pub fn flip_pixel_perfect(&mut self) {
+        self.pixel_perfect = !self.pixel_perfect;
+    }
+
+    /// Get the brush stroke that's being painted, or that was painted last.
+    pub fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    pub fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
     pub fn show_grid(&self) -> bool {
         self.show_grid
     }
@@ -116,10 +188,140 @@ impl AppState {
         self.show_grid = !self.show_grid
     }
 
+    pub fn show_onion_skin(&self) -> bool {
+        self.show_onion_skin
+    }
+
+    pub fn flip_onion_skin(&mut self) {
+        self.show_onion_skin = !self.show_onion_skin
+    }
+
+    /// Get the number of frames the onion skin shows before the current frame.
+    pub fn onion_skin_before(&self) -> usize {
+        self.onion_skin_before
+    }
+
+    pub fn set_onion_skin_before(&mut self, onion_skin_before: usize) {
+        self.onion_skin_before = onion_skin_before.min(constants::MAX_ONION_SKIN_FRAMES);
+    }
+
+    /// Get the number of frames the onion skin shows after the current frame.
+    pub fn onion_skin_after(&self) -> usize {
+        self.onion_skin_after
+    }
+
+    pub fn set_onion_skin_after(&mut self, onion_skin_after: usize) {
+        self.onion_skin_after = onion_skin_after.min(constants::MAX_ONION_SKIN_FRAMES);
+    }
+
+    /// Get the frames the onion skin shows, as offsets from the current frame. There are
+    /// none if it's hidden, and never more than there are frames on either side. Frames
+    /// before come first, and each side goes from farthest to nearest, so that nearer
+    /// frames paint over farther ones.
+    pub fn onion_skin_frames(&self) -> Vec<isize> {
+        if !self.show_onion_skin {
+            return Vec::new();
+        }
+
+        let current = self.doc.current_frame();
+        let before = self.onion_skin_before.min(current);
+        let after = self
+            .onion_skin_after
+            .min(self.doc.frames().len() - current - 1);
+
+        let before = (1..before + 1).rev().map(|distance| -(distance as isize));
+        let after = (1..after + 1).rev().map(|distance| distance as isize);
+        before.chain(after).collect()
+    }
+
     pub fn window_id(&self) -> druid::WindowId {
         self.window_id
     }
 
+    pub fn new_image_options(&self) -> &NewImageOptions {
+        &self.new_image_options
+    }
+
+    pub fn new_image_options_mut(&mut self) -> &mut NewImageOptions {
+        &mut self.new_image_options
+    }
+
+    pub fn color_picker(&self) -> &ColorPicker {
+        &self.color_picker
+    }
+
+    pub fn set_color_picker(&mut self, color_picker: ColorPicker) {
+        self.color_picker = color_picker;
+    }
+
+    /// Change the color picker, and make its color the brush color.
+    pub fn modify_color_picker<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut ColorPicker),
+    {
+        f(&mut self.color_picker);
+        self.brush_color = self.color_picker.color();
+    }
+
+    pub fn sheet_options(&self) -> &SheetOptions {
+        &self.sheet_options
+    }
+
+    pub fn sheet_options_mut(&mut self) -> &mut SheetOptions {
+        &mut self.sheet_options
+    }
+
+    pub fn slice_options(&self) -> &SliceOptions {
+        &self.slice_options
+    }
+
+    pub fn slice_options_mut(&mut self) -> &mut SliceOptions {
+        &mut self.slice_options
+    }
+
+    pub fn wand_options(&self) -> &WandOptions {
+        &self.wand_options
+    }
+
+    pub fn wand_options_mut(&mut self) -> &mut WandOptions {
+        &mut self.wand_options
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    pub fn viewport_mut(&mut self) -> &mut Viewport {
+        &mut self.viewport
+    }
+
+    /// Get the pixels that were last cut or copied.
+    pub fn clipboard(&self) -> Option<&PixelState> {
+        self.clipboard.as_ref()
+    }
+
+    pub fn set_clipboard(&mut self, clipboard: PixelState) {
+        self.clipboard = Some(clipboard);
+    }
+
+    /// Get the document that's waiting to replace the current one, once the user has
+    /// decided what to do about unsaved changes.
+    pub fn pending_doc(&self) -> Option<&Document> {
+        self.pending_doc.as_ref()
+    }
+
+    pub fn set_pending_doc(&mut self, pending_doc: Document) {
+        self.pending_doc = Some(pending_doc);
+    }
+
+    pub fn take_pending_doc(&mut self) -> Option<Document> {
+        self.pending_doc.take()
+    }
+
+    pub fn clear_pending_doc(&mut self) {
+        self.pending_doc = None;
+    }
+
     pub fn doc(&self) -> &Document {
         &self.doc
     }
@@ -128,7 +330,70 @@ impl AppState {
         &mut self.doc
     }
 
+    /// Replace the document. The new document keeps the undo depth and budget of the old
+    /// one, but everything else, including the palette, comes from the new document.
     pub fn set_doc(&mut self, doc: Document) {
+        let depth = self.doc.undo().depth();
+        let budget = self.doc.undo().budget();
         self.doc = doc;
+        self.doc.set_undo_depth(depth);
+        self.doc.set_undo_budget(budget);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::palette::PaletteState;
+
+    #[test]
+    fn it_clamps_onion_skin_frames() {
+        let mut data = AppState::new(druid::WindowId::next());
+        data.set_onion_skin_before(constants::MAX_ONION_SKIN_FRAMES + 1);
+        data.set_onion_skin_after(constants::MAX_ONION_SKIN_FRAMES + 1);
+        assert_eq!(constants::MAX_ONION_SKIN_FRAMES, data.onion_skin_before());
+        assert_eq!(constants::MAX_ONION_SKIN_FRAMES, data.onion_skin_after());
+    }
+
+    #[test]
+    fn it_picks_onion_skin_frames() {
+        let mut data = AppState::new(druid::WindowId::next());
+        for _ in 0..4 {
+            data.doc_mut().add_frame();
+        }
+        data.set_onion_skin_before(2);
+        data.set_onion_skin_after(3);
+
+        // Hidden until it's switched on.
+        data.doc_mut().set_current_frame(2);
+        assert!(data.onion_skin_frames().is_empty());
+
+        data.flip_onion_skin();
+        assert_eq!(vec![-2, -1, 2, 1], data.onion_skin_frames());
+
+        // Clamped to the frames on either side.
+        data.doc_mut().set_current_frame(0);
+        assert_eq!(vec![3, 2, 1], data.onion_skin_frames());
+        data.doc_mut().set_current_frame(4);
+        assert_eq!(vec![-2, -1], data.onion_skin_frames());
+
+        data.flip_onion_skin();
+        assert!(data.onion_skin_frames().is_empty());
+    }
+
+    #[test]
+    fn it_replaces_the_doc_with_its_own_palette() {
+        let mut data = AppState::new(druid::WindowId::next());
+        data.doc_mut().set_undo_depth(3);
+        data.doc_mut()
+            .set_palette(PaletteState::new(vec![0xff0000ff]));
+
+        data.set_doc(Default::default());
+        assert_eq!(3, data.doc().undo().depth());
+        assert_eq!(
+            PaletteState::default().values(),
+            data.doc().palette().values()
+        );
+        assert!(!data.doc().dirty());
     }
 }
@@ -0,0 +1,233 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::transforms::util;
+
+/// Color picker state. The color can be edited as RGB, HSL or HSV, with every value in the
+/// range 0 to 1. Grays don't have a hue, so we hang on to it separately; otherwise the hue
+/// would jump back to red every time the color passed through one.
+#[derive(Clone, druid::Data)]
+pub struct ColorPicker {
+    red: f64,
+    green: f64,
+    blue: f64,
+    alpha: f64,
+    hue: f64,
+    original: druid::Color,
+}
+
+impl ColorPicker {
+    /// Create a color picker for the given color, which is kept around so that it can be
+    /// restored if the picker is cancelled.
+    pub fn new(color: &druid::Color) -> Self {
+        let (red, green, blue, alpha) = color.as_rgba();
+        let (hue, _, _, _) = util::rgba_to_hsla(red, green, blue, alpha);
+
+        Self {
+            red,
+            green,
+            blue,
+            alpha,
+            hue,
+            original: color.clone(),
+        }
+    }
+
+    /// Get the color that's being picked.
+    pub fn color(&self) -> druid::Color {
+        druid::Color::rgba(self.red, self.green, self.blue, self.alpha)
+    }
+
+    /// Get the color that the picker started with.
+    pub fn original(&self) -> &druid::Color {
+        &self.original
+    }
+
+    pub fn red(&self) -> f64 {
+        self.red
+    }
+
+    pub fn set_red(&mut self, red: f64) {
+        self.set_rgb(red, self.green, self.blue);
+    }
+
+    pub fn green(&self) -> f64 {
+        self.green
+    }
+
+    pub fn set_green(&mut self, green: f64) {
+        self.set_rgb(self.red, green, self.blue);
+    }
+
+    pub fn blue(&self) -> f64 {
+        self.blue
+    }
+
+    pub fn set_blue(&mut self, blue: f64) {
+        self.set_rgb(self.red, self.green, blue);
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    pub fn set_alpha(&mut self, alpha: f64) {
+        self.alpha = alpha.clamp(0.0, 1.0);
+    }
+
+    /// Get the hue, which is shared by HSL and HSV.
+    pub fn hue(&self) -> f64 {
+        self.hue
+    }
+
+    pub fn set_hue(&mut self, hue: f64) {
+        let (_, saturation, luminance) = self.hsl();
+        self.hue = hue.clamp(0.0, 1.0);
+        self.set_hsl(saturation, luminance);
+    }
+
+    pub fn hsl_saturation(&self) -> f64 {
+        self.hsl().1
+    }
+
+    pub fn set_hsl_saturation(&mut self, saturation: f64) {
+        self.set_hsl(saturation, self.luminance());
+    }
+
+    pub fn luminance(&self) -> f64 {
+        self.hsl().2
+    }
+
+    pub fn set_luminance(&mut self, luminance: f64) {
+        self.set_hsl(self.hsl_saturation(), luminance);
+    }
+
+    pub fn hsv_saturation(&self) -> f64 {
+        self.hsv().1
+    }
+
+    pub fn set_hsv_saturation(&mut self, saturation: f64) {
+        self.set_hsv(saturation, self.value());
+    }
+
+    pub fn value(&self) -> f64 {
+        self.hsv().2
+    }
+
+    pub fn set_value(&mut self, value: f64) {
+        self.set_hsv(self.hsv_saturation(), value);
+    }
+
+    /// Get the color as a u32 representation of RGBA, like the palette uses.
+    pub fn rgba_u32(&self) -> u32 {
+        self.color().as_rgba_u32()
+    }
+
+    pub fn set_rgba_u32(&mut self, value: u32) {
+        let (red, green, blue, alpha) = druid::Color::from_rgba32_u32(value).as_rgba();
+        self.alpha = alpha;
+        self.set_rgb(red, green, blue);
+    }
+
+    /// Set the RGB values. The hue only follows along if there is one.
+    fn set_rgb(&mut self, red: f64, green: f64, blue: f64) {
+        self.red = red.clamp(0.0, 1.0);
+        self.green = green.clamp(0.0, 1.0);
+        self.blue = blue.clamp(0.0, 1.0);
+
+        let (hue, saturation, _, _) = util::rgba_to_hsla(self.red, self.green, self.blue, 1.0);
+        if saturation > 0.0 {
+            self.hue = hue;
+        }
+    }
+
+    /// Get the color as HSL, using the hue that we hung on to.
+    fn hsl(&self) -> (f64, f64, f64) {
+        let (_, saturation, luminance, _) =
+            util::rgba_to_hsla(self.red, self.green, self.blue, 1.0);
+        (self.hue, saturation, luminance)
+    }
+
+    fn set_hsl(&mut self, saturation: f64, luminance: f64) {
+        let saturation = saturation.clamp(0.0, 1.0);
+        let luminance = luminance.clamp(0.0, 1.0);
+        let (red, green, blue, _) = util::hsla_to_rgba(self.hue, saturation, luminance, 1.0);
+        self.red = red.clamp(0.0, 1.0);
+        self.green = green.clamp(0.0, 1.0);
+        self.blue = blue.clamp(0.0, 1.0);
+    }
+
+    /// Get the color as HSV, using the hue that we hung on to.
+    fn hsv(&self) -> (f64, f64, f64) {
+        let (hue, saturation, luminance) = self.hsl();
+        let (hue, saturation, value, _) = util::hsla_to_hsva(hue, saturation, luminance, 1.0);
+        (hue, saturation, value)
+    }
+
+    fn set_hsv(&mut self, saturation: f64, value: f64) {
+        let saturation = saturation.clamp(0.0, 1.0);
+        let value = value.clamp(0.0, 1.0);
+        let (_, saturation, luminance, _) = util::hsva_to_hsla(self.hue, saturation, value, 1.0);
+        self.set_hsl(saturation, luminance);
+    }
+}
+
+impl Default for ColorPicker {
+    fn default() -> Self {
+        Self::new(&druid::Color::BLACK)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx_eq(a: f64, b: f64) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn it_keeps_hue_through_grays() {
+        let mut picker = ColorPicker::new(&druid::Color::rgb8(0, 0, 255));
+        approx_eq(2.0 / 3.0, picker.hue());
+
+        picker.set_hsl_saturation(0.0);
+        approx_eq(2.0 / 3.0, picker.hue());
+        assert_eq!(picker.red(), picker.blue());
+
+        picker.set_hsl_saturation(1.0);
+        assert_eq!((0, 0, 255, 255), picker.color().as_rgba8());
+    }
+
+    #[test]
+    fn it_edits_hsv() {
+        let mut picker = ColorPicker::new(&druid::Color::rgb8(255, 0, 0));
+        approx_eq(1.0, picker.hsv_saturation());
+        approx_eq(1.0, picker.value());
+
+        picker.set_value(0.5);
+        assert_eq!((128, 0, 0, 255), picker.color().as_rgba8());
+
+        picker.set_hue(1.0 / 3.0);
+        assert_eq!((0, 128, 0, 255), picker.color().as_rgba8());
+    }
+
+    #[test]
+    fn it_sets_hex() {
+        let mut picker = ColorPicker::new(&druid::Color::BLACK);
+        picker.set_rgba_u32(0x3366cc80);
+        assert_eq!(0x3366cc80, picker.rgba_u32());
+        assert_eq!(&druid::Color::BLACK, picker.original());
+    }
+}
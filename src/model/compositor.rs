@@ -0,0 +1,137 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::document::Layer;
+use crate::model::pixels::PixelHeader;
+use crate::model::pixels::PixelState;
+use crate::model::types::BlendMode;
+
+/// Flatten the given layers, and their cels, into a single image. Layers are composited
+/// bottom to top, and hidden layers are skipped entirely.
+pub fn flatten(header: &PixelHeader, layers: &[Layer], cels: &[PixelState]) -> PixelState {
+    let mut bytes = vec![0; header.width() * header.height() * 4];
+
+    for (layer, cel) in layers.iter().zip(cels).filter(|(layer, _)| layer.visible()) {
+        let src_bytes = cel.bytes();
+
+        for (dst, src) in bytes.chunks_exact_mut(4).zip(src_bytes.chunks_exact(4)) {
+            let blended = blend(
+                [dst[0], dst[1], dst[2], dst[3]],
+                [src[0], src[1], src[2], src[3]],
+                layer.opacity(),
+                layer.blend_mode(),
+            );
+            dst.copy_from_slice(&blended);
+        }
+    }
+
+    PixelState::new(header.clone(), bytes)
+}
+
+/// Blend a source pixel onto a backdrop pixel. Both are RGBA8. This follows the usual
+/// separable blend mode rules, where the blended color is weighted by the backdrop alpha,
+/// and the result is composited "source over".
+pub fn blend(backdrop: [u8; 4], src: [u8; 4], opacity: f64, mode: BlendMode) -> [u8; 4] {
+    fn to_f64(v: u8) -> f64 {
+        v as f64 / 255.0
+    }
+
+    fn to_u8(v: f64) -> u8 {
+        (v.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+
+    fn blend_channel(cb: f64, cs: f64, mode: BlendMode) -> f64 {
+        match mode {
+            BlendMode::Normal => cs,
+            BlendMode::Multiply => cb * cs,
+            BlendMode::Screen => cb + cs - cb * cs,
+            BlendMode::Add => f64::min(cb + cs, 1.0),
+        }
+    }
+
+    let alpha_s = to_f64(src[3]) * opacity;
+    let alpha_b = to_f64(backdrop[3]);
+    let alpha_o = alpha_s + alpha_b * (1.0 - alpha_s);
+    if alpha_o <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+
+    let mut out = [0, 0, 0, to_u8(alpha_o)];
+    for i in 0..3 {
+        let cb = to_f64(backdrop[i]);
+        let cs = to_f64(src[i]);
+        let mixed = (1.0 - alpha_b) * cs + alpha_b * blend_channel(cb, cs, mode);
+        let co = alpha_s * mixed + alpha_b * (1.0 - alpha_s) * cb;
+        out[i] = to_u8(co / alpha_o);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_blends_normal_opaque() {
+        let got = blend(
+            [10, 20, 30, 255],
+            [200, 100, 50, 255],
+            1.0,
+            BlendMode::Normal,
+        );
+        assert_eq!([200, 100, 50, 255], got);
+    }
+
+    #[test]
+    fn it_blends_transparent_source() {
+        let got = blend([10, 20, 30, 255], [200, 100, 50, 0], 1.0, BlendMode::Normal);
+        assert_eq!([10, 20, 30, 255], got);
+    }
+
+    #[test]
+    fn it_blends_onto_transparent_backdrop() {
+        let got = blend([0, 0, 0, 0], [200, 100, 50, 255], 1.0, BlendMode::Multiply);
+        assert_eq!([200, 100, 50, 255], got);
+    }
+
+    #[test]
+    fn it_blends_with_opacity() {
+        let got = blend([0, 0, 0, 255], [255, 255, 255, 255], 0.5, BlendMode::Normal);
+        assert_eq!([128, 128, 128, 255], got);
+    }
+
+    #[test]
+    fn it_blends_multiply() {
+        let got = blend(
+            [255, 128, 0, 255],
+            [128, 255, 255, 255],
+            1.0,
+            BlendMode::Multiply,
+        );
+        assert_eq!([128, 128, 0, 255], got);
+    }
+
+    #[test]
+    fn it_blends_screen() {
+        let got = blend([0, 128, 255, 255], [128, 0, 0, 255], 1.0, BlendMode::Screen);
+        assert_eq!([128, 128, 255, 255], got);
+    }
+
+    #[test]
+    fn it_blends_add() {
+        let got = blend([200, 100, 0, 255], [100, 100, 0, 255], 1.0, BlendMode::Add);
+        assert_eq!([255, 200, 0, 255], got);
+    }
+}
@@ -12,8 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::common::constants;
+use crate::model::compositor;
+use crate::model::mask::Mask;
 use crate::model::mod_stack::ModStack;
+use crate::model::mod_stack::Transaction;
+use crate::model::palette;
+use crate::model::palette::PaletteState;
+use crate::model::pixels::PixelHeader;
 use crate::model::pixels::PixelState;
+use crate::model::types::BlendMode;
+use crate::model::types::ColorMode;
+use crate::model::types::PaletteSort;
+use crate::model::types::SelectionMode;
+use crate::util::shapes;
 
 #[derive(Clone, druid::Data, Default)]
 pub struct MoveInfo {
@@ -40,37 +55,313 @@ impl MoveInfo {
     }
 }
 
+/// A named layer. Layers are stacked bottom to top, and flattened by the compositor
+/// whenever we need a single image (painting the canvas, saving, etc). The pixels for
+/// a layer live in each frame, one cel per layer.
+#[derive(Clone, druid::Data)]
+pub struct Layer {
+    name: String,
+    visible: bool,
+    locked: bool,
+    opacity: f64,
+    blend_mode: BlendMode,
+}
+
+impl Layer {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            blend_mode: Default::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn set_locked(&mut self, locked: bool) {
+        self.locked = locked;
+    }
+
+    /// Get the opacity, from 0.0 (transparent) to 1.0 (opaque).
+    pub fn opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: f64) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    /// Can this layer be modified? Hidden layers are off limits too, because you
+    /// wouldn't be able to see what you're doing.
+    pub fn editable(&self) -> bool {
+        self.visible && !self.locked
+    }
+}
+
+/// A single frame of animation. Holds the pixels for every layer (the cels), how long the
+/// frame is shown for, and an optional tag. Runs of frames with the same tag make up a
+/// sprite (like "walk" or "idle") when exporting a sprite sheet.
+#[derive(Clone, druid::Data)]
+pub struct Frame {
+    duration: u32,
+    tag: Option<String>,
+    cels: Arc<Vec<PixelState>>,
+}
+
+impl Frame {
+    pub fn new(duration: u32, cels: Vec<PixelState>) -> Self {
+        Self {
+            duration,
+            tag: None,
+            cels: Arc::new(cels),
+        }
+    }
+
+    /// Get the duration, in milliseconds.
+    pub fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: u32) {
+        self.duration = duration;
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    pub fn set_tag(&mut self, tag: Option<String>) {
+        self.tag = tag;
+    }
+
+    /// Get the cels, one per layer, bottom to top.
+    pub fn cels(&self) -> &[PixelState] {
+        &self.cels
+    }
+
+    pub fn cel(&self, layer: usize) -> &PixelState {
+        &self.cels[layer]
+    }
+
+    pub fn cel_mut(&mut self, layer: usize) -> &mut PixelState {
+        &mut Arc::make_mut(&mut self.cels)[layer]
+    }
+
+    fn cels_mut(&mut self) -> &mut Vec<PixelState> {
+        Arc::make_mut(&mut self.cels)
+    }
+}
+
 /// Per-document state.
-#[derive(Clone, druid::Data, Default)]
+#[derive(Clone, druid::Data)]
 pub struct Document {
     selection: Option<druid::Rect>,
+    mask: Option<Mask>,
     move_info: Option<MoveInfo>,
-    pixels: PixelState,
+    layers: Arc<Vec<Layer>>,
+    frames: Arc<Vec<Frame>>,
+    current_layer: usize,
+    current_frame: usize,
+    next_layer_id: usize,
+    loop_count: u16,
+    transparent_index: Option<u8>,
+    palette: PaletteState,
+    dirty: bool,
     path: Option<String>,
     new_path: Option<String>,
     undo: ModStack,
     redo: ModStack,
+    transaction: Option<Transaction>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::from_pixels(Default::default())
+    }
 }
 
 impl Document {
-    pub fn new(pixels: PixelState, path: String) -> Self {
+    /// Create an untitled document of the given size, filled with the given color.
+    pub fn new(width: u32, height: u32, color: &druid::Color) -> Self {
+        let (r, g, b, a) = color.as_rgba8();
+        let header = PixelHeader::new(width, height, 8, 4);
+        let bytes = [r, g, b, a].repeat(header.width() * header.height());
+
+        Self::from_pixels(PixelState::new(header, bytes))
+    }
+
+    /// Create an untitled document with a single layer and a single frame, holding
+    /// the given pixels.
+    pub fn from_pixels(pixels: PixelState) -> Self {
+        let frame = Frame::new(constants::DEFAULT_FRAME_DURATION, vec![pixels]);
+
+        Self::from_frames(vec![frame])
+    }
+
+    /// Create an untitled document with a single layer, and the given frames. Each frame
+    /// must hold exactly one cel.
+    pub fn from_frames(frames: Vec<Frame>) -> Self {
+        assert!(!frames.is_empty());
+        assert!(frames.iter().all(|frame| frame.cels().len() == 1));
+
         Self {
-            pixels,
-            path: Some(path),
-            ..Default::default()
+            selection: None,
+            mask: None,
+            move_info: None,
+            layers: Arc::new(vec![Layer::new(Self::layer_name(1))]),
+            frames: Arc::new(frames),
+            current_layer: 0,
+            current_frame: 0,
+            next_layer_id: 2,
+            loop_count: 0,
+            transparent_index: None,
+            palette: Default::default(),
+            dirty: false,
+            path: None,
+            new_path: None,
+            undo: Default::default(),
+            redo: Default::default(),
+            transaction: None,
         }
     }
 
+    fn layer_name(id: usize) -> String {
+        format!("Layer {}", id)
+    }
+
+    /// Get the selection. This is the bounding rectangle; if the selection isn't
+    /// rectangular, then the mask says which pixels within it are selected.
     pub fn selection(&self) -> Option<druid::Rect> {
         self.selection
     }
 
+    /// Get the selection mask. This is None for a rectangular selection.
+    pub fn mask(&self) -> Option<&Mask> {
+        self.mask.as_ref()
+    }
+
     pub fn clear_selection(&mut self) {
         self.selection = None;
+        self.mask = None;
     }
 
+    /// Set a rectangular selection.
     pub fn set_selection(&mut self, selection: druid::Rect) {
         self.selection = Some(selection);
+        self.mask = None;
+    }
+
+    /// Set a selection from a mask. If every pixel in the mask is selected, then it's just
+    /// a rectangular selection.
+    pub fn set_selection_mask(&mut self, mask: Mask) {
+        self.selection = Some(mask.area());
+        self.mask = if mask.is_full() { None } else { Some(mask) };
+    }
+
+    /// Get the selection as a mask, whether or not it's rectangular.
+    pub fn selection_mask(&self) -> Option<Mask> {
+        match (&self.selection, &self.mask) {
+            (_, Some(mask)) => Some(mask.clone()),
+            (Some(selection), None) => Some(Mask::from_rect(*selection)),
+            (None, None) => None,
+        }
+    }
+
+    /// Combine a new selection with the current one. A None mask is an empty selection.
+    pub fn combine_selection(&mut self, mask: Option<Mask>, mode: SelectionMode) {
+        let combined = match (self.selection_mask(), mask) {
+            (Some(current), Some(mask)) => current.combine(&mask, mode),
+            (Some(current), None) => match mode {
+                SelectionMode::Add | SelectionMode::Subtract => Some(current),
+                _ => None,
+            },
+            (None, mask) => match mode {
+                SelectionMode::Subtract => None,
+                _ => mask,
+            },
+        };
+
+        match combined {
+            Some(mask) => self.set_selection_mask(mask),
+            None => self.clear_selection(),
+        }
+    }
+
+    /// Move the selection, and its mask, to the given rectangle.
+    pub fn move_selection(&mut self, selection: druid::Rect) {
+        self.selection = Some(selection);
+        self.mask = self
+            .mask
+            .take()
+            .map(|mask| mask.with_origin(selection.origin()));
+    }
+
+    /// Is the given point selected? Everything is selected if there's no selection.
+    pub fn is_selected(&self, p: druid::Point) -> bool {
+        match (&self.selection, &self.mask) {
+            (_, Some(mask)) => mask.contains(p),
+            (Some(selection), None) => shapes::inflate_rect(*selection).contains(p),
+            (None, None) => true,
+        }
+    }
+
+    /// Copy the selected pixels of the current frame and layer. Anything outside the mask
+    /// is left transparent.
+    pub fn clone_selection(&self) -> Option<PixelState> {
+        let selection = self.selection?;
+        let mut pixels = self.pixels().clone_area(shapes::inflate_rect(selection));
+
+        if let Some(mask) = &self.mask {
+            let transparent = self.transparent_raw();
+            let (x0, y0) = (selection.x0 as usize, selection.y0 as usize);
+            for y in y0..selection.y1 as usize + 1 {
+                for x in x0..selection.x1 as usize + 1 {
+                    if !mask.contains_xy(x, y) {
+                        let p = druid::Point::new((x + 1 - x0) as f64, (y + 1 - y0) as f64);
+                        pixels.write_raw(p, &transparent);
+                    }
+                }
+            }
+        }
+
+        Some(pixels)
+    }
+
+    /// Write to the selected pixels of the current frame and layer. The bytes cover the
+    /// whole selection, but anything outside the mask is left alone.
+    pub fn write_selection(&mut self, bytes: &[u8]) {
+        if let Some(selection) = self.selection {
+            let mask = self.mask.clone();
+            let pixels = self.pixels_mut();
+            match mask {
+                Some(mask) => pixels.write_mask(&mask, bytes),
+                None => pixels.write_area(shapes::inflate_rect(selection), bytes),
+            }
+        }
     }
 
     pub fn is_moving(&self) -> bool {
@@ -89,12 +380,560 @@ impl Document {
         self.move_info = Some(move_info);
     }
 
+    /// Get the pixel header. All cels share the same dimensions.
+    pub fn header(&self) -> &PixelHeader {
+        self.frames[0].cel(0).header()
+    }
+
+    /// Get the layers, bottom to top.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, idx: usize) -> &Layer {
+        &self.layers[idx]
+    }
+
+    pub fn current_layer(&self) -> usize {
+        self.current_layer
+    }
+
+    pub fn set_current_layer(&mut self, idx: usize) {
+        assert!(idx < self.layers.len());
+        self.current_layer = idx;
+    }
+
+    /// Insert a new, empty layer directly above the current one and make it current.
+    pub fn add_layer(&mut self) {
+        let name = Self::layer_name(self.next_layer_id);
+        let empty = PixelState::empty(self.header().clone());
+        let idx = self.current_layer + 1;
+
+        Arc::make_mut(&mut self.layers).insert(idx, Layer::new(name));
+        for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+            frame.cels_mut().insert(idx, empty.clone());
+        }
+
+        // Undo records refer to layers by index, so they need to shift along with them.
+        self.undo.insert_layer(idx);
+        self.redo.insert_layer(idx);
+
+        self.next_layer_id += 1;
+        self.current_layer = idx;
+        self.dirty = true;
+    }
+
+    /// Delete the current layer. There must always be at least one layer.
+    pub fn delete_layer(&mut self) {
+        if self.layers.len() > 1 {
+            let idx = self.current_layer;
+
+            Arc::make_mut(&mut self.layers).remove(idx);
+            for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+                frame.cels_mut().remove(idx);
+            }
+
+            self.undo.remove_layer(idx);
+            self.redo.remove_layer(idx);
+
+            self.current_layer = idx.saturating_sub(1);
+            self.dirty = true;
+        }
+    }
+
+    /// Modify the properties of the current layer. This marks the document as dirty,
+    /// because layer properties affect the flattened image.
+    pub fn modify_layer<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Layer),
+    {
+        let idx = self.current_layer;
+        f(&mut Arc::make_mut(&mut self.layers)[idx]);
+        self.dirty = true;
+    }
+
+    /// Is the current layer editable?
+    pub fn editable(&self) -> bool {
+        self.layers[self.current_layer].editable()
+    }
+
+    /// Get the frames, in playback order.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn frame(&self, idx: usize) -> &Frame {
+        &self.frames[idx]
+    }
+
+    pub fn frame_mut(&mut self, idx: usize) -> &mut Frame {
+        &mut Arc::make_mut(&mut self.frames)[idx]
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    pub fn set_current_frame(&mut self, idx: usize) {
+        assert!(idx < self.frames.len());
+        self.current_frame = idx;
+    }
+
+    /// Insert a new, empty frame directly after the current one and make it current.
+    /// The new frame gets the same duration as the current one.
+    pub fn add_frame(&mut self) {
+        let current = &self.frames[self.current_frame];
+        let empty = PixelState::empty(self.header().clone());
+        let frame = Frame::new(current.duration(), vec![empty; self.layers.len()]);
+
+        self.insert_frame(frame);
+    }
+
+    /// Insert a copy of the current frame directly after it and make it current.
+    pub fn duplicate_frame(&mut self) {
+        let frame = self.frames[self.current_frame].clone();
+
+        self.insert_frame(frame);
+    }
+
+    fn insert_frame(&mut self, frame: Frame) {
+        let idx = self.current_frame + 1;
+
+        Arc::make_mut(&mut self.frames).insert(idx, frame);
+
+        // Undo records refer to frames by index, so they need to shift along with them.
+        self.undo.insert_frame(idx);
+        self.redo.insert_frame(idx);
+
+        self.current_frame = idx;
+        self.dirty = true;
+    }
+
+    /// Delete the current frame. There must always be at least one frame.
+    pub fn delete_frame(&mut self) {
+        if self.frames.len() > 1 {
+            let idx = self.current_frame;
+
+            Arc::make_mut(&mut self.frames).remove(idx);
+
+            self.undo.remove_frame(idx);
+            self.redo.remove_frame(idx);
+
+            self.current_frame = idx.saturating_sub(1);
+            self.dirty = true;
+        }
+    }
+
+    /// Move the current frame to the given position, and keep it current.
+    pub fn move_frame(&mut self, to: usize) {
+        let from = self.current_frame;
+        if to != from && to < self.frames.len() {
+            let frames = Arc::make_mut(&mut self.frames);
+            let frame = frames.remove(from);
+            frames.insert(to, frame);
+
+            self.undo.move_frame(from, to);
+            self.redo.move_frame(from, to);
+
+            self.current_frame = to;
+            self.dirty = true;
+        }
+    }
+
+    /// Set the duration of the current frame, in milliseconds.
+    pub fn set_frame_duration(&mut self, duration: u32) {
+        let idx = self.current_frame;
+        self.frame_mut(idx).set_duration(duration);
+        self.dirty = true;
+    }
+
+    /// Set the tag of the current frame. An empty tag clears it.
+    pub fn set_frame_tag(&mut self, tag: &str) {
+        let idx = self.current_frame;
+        let tag = Some(tag).filter(|tag| !tag.is_empty());
+        if self.frames[idx].tag() != tag {
+            self.frame_mut(idx).set_tag(tag.map(String::from));
+            self.dirty = true;
+        }
+    }
+
+    /// Get the number of times the animation plays. Zero means it loops forever.
+    pub fn loop_count(&self) -> u16 {
+        self.loop_count
+    }
+
+    pub fn set_loop_count(&mut self, loop_count: u16) {
+        self.loop_count = loop_count;
+    }
+
+    /// Get the palette index used for transparency, if the document came from a file
+    /// format (like GIF) that has one.
+    pub fn transparent_index(&self) -> Option<u8> {
+        self.transparent_index
+    }
+
+    pub fn set_transparent_index(&mut self, transparent_index: Option<u8>) {
+        self.transparent_index = transparent_index;
+    }
+
+    /// Get the pixels of the given frame and layer.
+    pub fn cel(&self, frame: usize, layer: usize) -> &PixelState {
+        self.frames[frame].cel(layer)
+    }
+
+    /// Get the pixels of the given frame and layer, for modification.
+    pub fn cel_mut(&mut self, frame: usize, layer: usize) -> &mut PixelState {
+        self.frame_mut(frame).cel_mut(layer)
+    }
+
+    /// Get the pixels of the current frame and layer.
     pub fn pixels(&self) -> &PixelState {
-        &self.pixels
+        self.cel(self.current_frame, self.current_layer)
     }
 
+    /// Get the pixels of the current frame and layer, for modification.
     pub fn pixels_mut(&mut self) -> &mut PixelState {
-        &mut self.pixels
+        self.cel_mut(self.current_frame, self.current_layer)
+    }
+
+    /// Read the color at a point in the current frame and layer. Indexed pixels are looked
+    /// up in the palette. Will return an empty color if outside bounds.
+    pub fn read(&self, p: druid::Point) -> druid::Color {
+        let pixels = self.pixels();
+        if !pixels.contains(p) {
+            return druid::Color::rgba8(0, 0, 0, 0);
+        }
+
+        let [r, g, b, a] = self.palette.to_rgba(pixels.read_raw(p));
+        druid::Color::rgba8(r, g, b, a)
+    }
+
+    /// Write a color to a point in the current frame and layer. Indexed documents get the
+    /// closest color in the palette.
+    pub fn write(&mut self, p: druid::Point, color: &druid::Color) {
+        match self.color_mode() {
+            ColorMode::Rgba => self.pixels_mut().write(p, color),
+            ColorMode::Indexed => {
+                let (r, g, b, a) = color.as_rgba8();
+                let idx = self.palette.to_index([r, g, b, a]);
+                self.pixels_mut().write_raw(p, &[idx]);
+            }
+        }
+    }
+
+    /// Get the raw bytes of a fully transparent pixel.
+    fn transparent_raw(&self) -> Vec<u8> {
+        match self.color_mode() {
+            ColorMode::Rgba => vec![0, 0, 0, 0],
+            ColorMode::Indexed => vec![constants::TRANSPARENT_INDEX],
+        }
+    }
+
+    /// Get the palette. Indexed documents refer to it for every pixel, so changing it
+    /// changes the image.
+    pub fn palette(&self) -> &PaletteState {
+        &self.palette
+    }
+
+    /// Replace the palette. It's saved with the document, so this makes it dirty. Indexed
+    /// pixels keep their indices, so they pick up whatever color is now at each one.
+    pub fn set_palette(&mut self, palette: PaletteState) {
+        self.palette = palette;
+        self.dirty = true;
+    }
+
+    /// Set the color at the given index in the palette.
+    pub fn set_palette_color(&mut self, idx: usize, value: u32) {
+        let mut palette = self.palette.clone();
+        palette.set_value(idx, value);
+        self.set_palette(palette);
+    }
+
+    /// Get the max number of colors the palette can have. Indexed documents can't use as
+    /// many, because one index is taken up by transparency.
+    pub fn max_palette_colors(&self) -> usize {
+        match self.color_mode() {
+            ColorMode::Rgba => constants::MAX_PALETTE_COLORS,
+            ColorMode::Indexed => constants::MAX_INDEXED_COLORS,
+        }
+    }
+
+    /// Add a color to the end of the palette. Does nothing if the palette is full.
+    pub fn add_palette_color(&mut self, value: u32) {
+        if self.palette.len() >= self.max_palette_colors() {
+            return;
+        }
+
+        let mut palette = self.palette.clone();
+        if palette.push(value) {
+            self.set_palette(palette);
+        }
+    }
+
+    /// Delete the color at the given index from the palette. Indexed pixels that used it
+    /// get the closest color that's left. The last color can't be deleted.
+    pub fn delete_palette_color(&mut self, idx: usize) {
+        let len = self.palette.len();
+        if idx >= len || len == 1 {
+            return;
+        }
+
+        let order: Vec<usize> = (0..len).filter(|&i| i != idx).collect();
+        self.rearrange_palette(&order);
+    }
+
+    /// Move a color from one index in the palette to another.
+    pub fn move_palette_color(&mut self, from: usize, to: usize) {
+        let len = self.palette.len();
+        if from == to || from >= len || to >= len {
+            return;
+        }
+
+        let mut order: Vec<usize> = (0..len).collect();
+        let idx = order.remove(from);
+        order.insert(to, idx);
+        self.rearrange_palette(&order);
+    }
+
+    /// Sort the palette.
+    pub fn sort_palette(&mut self, sort: PaletteSort) {
+        let usage = match sort {
+            PaletteSort::Usage => self.palette_usage(),
+            _ => Vec::new(),
+        };
+
+        let order = self.palette.sort_order(sort, &usage);
+        self.rearrange_palette(&order);
+    }
+
+    /// Count the pixels that use each color in the palette, across every frame and layer.
+    /// RGBA pixels count towards any color that they match exactly. Transparent pixels
+    /// don't count at all.
+    fn palette_usage(&self) -> Vec<usize> {
+        let mut usage = vec![0; self.palette.len()];
+        let cels = self.frames.iter().flat_map(|frame| frame.cels());
+
+        match self.color_mode() {
+            ColorMode::Indexed => {
+                for &idx in cels.flat_map(|cel| cel.bytes().iter()) {
+                    if let Some(count) = palette::to_entry(idx).and_then(|e| usage.get_mut(e)) {
+                        *count += 1;
+                    }
+                }
+            }
+            ColorMode::Rgba => {
+                let mut counts: HashMap<u32, usize> = HashMap::new();
+                for p in cels.flat_map(|cel| cel.bytes().chunks_exact(4)) {
+                    if p[3] != 0 {
+                        *counts
+                            .entry(u32::from_be_bytes([p[0], p[1], p[2], p[3]]))
+                            .or_default() += 1;
+                    }
+                }
+                for (count, value) in usage.iter_mut().zip(self.palette.values()) {
+                    *count = counts.get(value).copied().unwrap_or(0);
+                }
+            }
+        }
+
+        usage
+    }
+
+    /// Rearrange the palette into the given order, which holds the old index of each color.
+    /// Colors that aren't in the order are dropped. Indexed pixels, including the ones in
+    /// the undo and redo history, are mapped to the new indices, so the image stays the
+    /// same as far as possible.
+    fn rearrange_palette(&mut self, order: &[usize]) {
+        let palette = self.palette.reorder(order);
+
+        if self.color_mode() == ColorMode::Indexed {
+            // Dropped colors go to the closest one that's left. The transparent index, and
+            // indices past the end of the palette, are transparent, so they stay that way.
+            let mut map = vec![constants::TRANSPARENT_INDEX; 256];
+            let reachable = self.palette.len().min(constants::MAX_INDEXED_COLORS);
+            for entry in 0..reachable {
+                map[palette::from_entry(entry) as usize] = match order
+                    .iter()
+                    .position(|&old_entry| old_entry == entry)
+                {
+                    Some(pos) if pos < constants::MAX_INDEXED_COLORS => palette::from_entry(pos),
+                    _ => palette.to_index(self.palette.rgba(entry)),
+                };
+            }
+
+            for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+                for cel in frame.cels_mut().iter_mut() {
+                    let bytes = cel.bytes().iter().map(|&idx| map[idx as usize]).collect();
+                    cel.set_bytes(bytes);
+                }
+            }
+
+            self.undo.remap_indices(&map, 1);
+            self.redo.remap_indices(&map, 1);
+        }
+
+        self.set_palette(palette);
+    }
+
+    /// Get the color mode. All cels share the same one.
+    pub fn color_mode(&self) -> ColorMode {
+        match self.header().bytes_per_pixel() {
+            1 => ColorMode::Indexed,
+            _ => ColorMode::Rgba,
+        }
+    }
+
+    /// Check whether the document can go to the given color mode. It can't go indexed if
+    /// that would leave colors in the palette without an index.
+    pub fn can_set_color_mode(&self, color_mode: ColorMode) -> bool {
+        color_mode == ColorMode::Rgba || self.palette.indexable()
+    }
+
+    /// Convert every cel to the given color mode. Going to indexed picks the closest color
+    /// in the palette for each pixel, so it can lose detail. Does nothing if the document
+    /// can't go to the color mode.
+    pub fn set_color_mode(&mut self, color_mode: ColorMode) {
+        if color_mode == self.color_mode() || !self.can_set_color_mode(color_mode) {
+            return;
+        }
+
+        let palette = self.palette.clone();
+        for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+            for cel in frame.cels_mut().iter_mut() {
+                *cel = match color_mode {
+                    ColorMode::Rgba => palette.resolve(cel),
+                    ColorMode::Indexed => palette.quantize(cel),
+                };
+            }
+        }
+
+        self.move_info = None;
+        self.dirty = true;
+    }
+
+    /// Convert pixels from the document's color mode to RGBA.
+    pub fn resolve(&self, pixels: &PixelState) -> PixelState {
+        self.palette.resolve(pixels)
+    }
+
+    /// Convert pixels to the document's color mode.
+    pub fn quantize(&self, pixels: &PixelState) -> PixelState {
+        match self.color_mode() {
+            ColorMode::Rgba => self.palette.resolve(pixels),
+            ColorMode::Indexed => self.palette.quantize(pixels),
+        }
+    }
+
+    /// Convert transformed pixels back to the document's color mode. Indexed pixels that
+    /// kept their color keep their index too, rather than going to the first palette entry
+    /// with that color.
+    pub fn requantize(&self, old: &PixelState, pixels: &PixelState) -> PixelState {
+        match self.color_mode() {
+            ColorMode::Rgba => self.palette.resolve(pixels),
+            ColorMode::Indexed => self.palette.requantize(old, pixels),
+        }
+    }
+
+    /// Flatten all visible layers of the current frame into a single image.
+    pub fn flatten(&self) -> PixelState {
+        self.flatten_frame(self.current_frame)
+    }
+
+    /// Flatten all visible layers of the given frame into a single image. Indexed cels
+    /// are resolved through the palette first.
+    pub fn flatten_frame(&self, idx: usize) -> PixelState {
+        let cels: Vec<PixelState> = self.frames[idx]
+            .cels()
+            .iter()
+            .map(|cel| self.resolve(cel))
+            .collect();
+        compositor::flatten(self.header(), &self.layers, &cels)
+    }
+
+    /// Crop every cel to the given area, which must lie within the image.
+    pub fn crop(&mut self, area: druid::Rect) {
+        for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+            for cel in frame.cels_mut().iter_mut() {
+                *cel = cel.clone_area(area);
+            }
+        }
+
+        self.clear_selection();
+        self.move_info = None;
+        self.dirty = true;
+    }
+
+    /// Replace every cel with the result of the given function. The function may change
+    /// the dimensions, so long as it does the same to every cel.
+    pub fn map_cels<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&PixelState) -> PixelState,
+    {
+        for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+            for cel in frame.cels_mut().iter_mut() {
+                *cel = f(cel);
+            }
+        }
+
+        self.clear_selection();
+        self.move_info = None;
+        self.dirty = true;
+    }
+
+    /// Find the smallest area that holds all of the non-transparent pixels, across every
+    /// frame and layer. Returns None if the image is entirely transparent.
+    pub fn opaque_bounds(&self) -> Option<druid::Rect> {
+        self.frames
+            .iter()
+            .flat_map(|frame| frame.cels())
+            .filter_map(|cel| self.resolve(cel).opaque_bounds())
+            .reduce(|a, b| a.union(b))
+    }
+
+    /// Resize any cels that don't match the given header. Their pixels stay anchored at
+    /// the top left. Cels in the wrong color mode are converted too.
+    pub fn conform(&mut self, header: &PixelHeader) {
+        let palette = self.palette.clone();
+        for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+            for cel in frame.cels_mut().iter_mut() {
+                *cel = match header.bytes_per_pixel() {
+                    1 => palette.quantize(cel),
+                    _ => palette.resolve(cel),
+                };
+
+                let (width, height) = (cel.header().width(), cel.header().height());
+                if width != header.width() || height != header.height() {
+                    let area = druid::Rect::new(
+                        1.0,
+                        1.0,
+                        1.0 + width.min(header.width()) as f64,
+                        1.0 + height.min(header.height()) as f64,
+                    );
+                    let bytes = cel.read_area(area);
+                    *cel = PixelState::empty(header.clone());
+                    cel.write_area(area, &bytes);
+                }
+            }
+        }
+    }
+
+    /// Has the document changed since it was last saved?
+    pub fn dirty(&self) -> bool {
+        self.dirty
+            || self
+                .frames
+                .iter()
+                .any(|frame| frame.cels().iter().any(|cel| cel.dirty()))
+    }
+
+    /// Clear dirty flags, typically after a save.
+    pub fn clear_dirty(&mut self) {
+        for frame in Arc::make_mut(&mut self.frames).iter_mut() {
+            for cel in frame.cels_mut().iter_mut() {
+                cel.clear_dirty();
+            }
+        }
+        self.dirty = false;
     }
 
     pub fn path(&self) -> Option<String> {
@@ -113,6 +952,10 @@ impl Document {
         self.new_path = Some(new_path);
     }
 
+    pub fn clear_new_path(&mut self) {
+        self.new_path = None;
+    }
+
     pub fn undo(&self) -> &ModStack {
         &self.undo
     }
@@ -129,12 +972,362 @@ impl Document {
         &mut self.redo
     }
 
+    /// Set how many steps the undo and redo stacks can hold.
+    pub fn set_undo_depth(&mut self, depth: usize) {
+        self.undo.set_depth(depth);
+        self.redo.set_depth(depth);
+    }
+
+    /// Set how many bytes the undo and redo stacks can hold, each.
+    pub fn set_undo_budget(&mut self, budget: usize) {
+        self.undo.set_budget(budget);
+        self.redo.set_budget(budget);
+    }
+
+    /// Get the undo transaction that's in progress, if any.
+    pub fn transaction_mut(&mut self) -> Option<&mut Transaction> {
+        self.transaction.as_mut()
+    }
+
+    /// Start an undo transaction. It holds a copy of the cel, which counts against the undo
+    /// budget until the transaction is taken.
+    pub fn set_transaction(&mut self, transaction: Transaction) {
+        self.undo.set_reserved(transaction.size());
+        self.transaction = Some(transaction);
+    }
+
+    pub fn take_transaction(&mut self) -> Option<Transaction> {
+        self.undo.set_reserved(0);
+        self.transaction.take()
+    }
+
     /// Get the current boundary. If a selection exists, then that's the boundary.
     /// Otherwise, it's the entire canvas. The result is in canvas coords.
     pub fn bounds(&self) -> druid::Rect {
-        let b = self
-            .selection
-            .unwrap_or_else(|| self.pixels.header().bounds());
+        let b = self.selection.unwrap_or_else(|| self.header().bounds());
         druid::Rect::new(b.x0, b.y0, b.x1 + 1.0, b.y1 + 1.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(width: u32, height: u32, color: &druid::Color) -> Document {
+        let mut doc = Document::new(width, height, color);
+        doc.clear_dirty();
+        doc
+    }
+
+    #[test]
+    fn it_adds_and_deletes_layers() {
+        let red = druid::Color::rgb8(255, 0, 0);
+        let mut doc = doc(2, 2, &red);
+        doc.add_frame();
+
+        doc.add_layer();
+        assert_eq!(2, doc.layers().len());
+        assert_eq!(1, doc.current_layer());
+        assert_eq!("Layer 2", doc.layer(1).name());
+        assert!(doc.dirty());
+        for frame in doc.frames() {
+            assert_eq!(2, frame.cels().len());
+            assert!(frame.cel(1).bytes().iter().all(|&b| b == 0));
+        }
+
+        // New layers go directly above the current one.
+        doc.set_current_layer(0);
+        doc.add_layer();
+        assert_eq!(1, doc.current_layer());
+        assert_eq!("Layer 3", doc.layer(1).name());
+        assert_eq!("Layer 2", doc.layer(2).name());
+
+        // Deleting the bottom layer leaves the one above it current.
+        doc.set_current_layer(0);
+        doc.delete_layer();
+        assert_eq!(0, doc.current_layer());
+        assert_eq!("Layer 3", doc.layer(0).name());
+        assert_eq!(2, doc.frame(1).cels().len());
+
+        doc.set_current_layer(1);
+        doc.delete_layer();
+        assert_eq!(0, doc.current_layer());
+        assert_eq!(1, doc.layers().len());
+    }
+
+    #[test]
+    fn it_keeps_the_last_layer() {
+        let red = druid::Color::rgb8(255, 0, 0);
+        let mut doc = doc(2, 2, &red);
+
+        doc.delete_layer();
+        assert_eq!(1, doc.layers().len());
+        assert_eq!(red, doc.read(druid::Point::new(1.0, 1.0)));
+        assert!(!doc.dirty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_rejects_a_layer_out_of_bounds() {
+        let mut doc = doc(2, 2, &druid::Color::BLACK);
+        doc.set_current_layer(1);
+    }
+
+    #[test]
+    fn it_only_edits_visible_unlocked_layers() {
+        let mut doc = doc(2, 2, &druid::Color::BLACK);
+        assert!(doc.editable());
+
+        doc.modify_layer(|layer| layer.set_locked(true));
+        assert!(!doc.editable());
+        assert!(doc.dirty());
+
+        doc.modify_layer(|layer| {
+            layer.set_locked(false);
+            layer.set_visible(false);
+        });
+        assert!(!doc.editable());
+    }
+
+    #[test]
+    fn it_adds_and_duplicates_frames() {
+        let red = druid::Color::rgb8(255, 0, 0);
+        let mut doc = doc(2, 2, &red);
+        doc.add_layer();
+        doc.set_frame_duration(250);
+
+        doc.add_frame();
+        assert_eq!(2, doc.frames().len());
+        assert_eq!(1, doc.current_frame());
+        assert_eq!(250, doc.frame(1).duration());
+        assert_eq!(2, doc.frame(1).cels().len());
+        assert!(doc.frame(1).cel(0).bytes().iter().all(|&b| b == 0));
+
+        // Duplicates go directly after the current frame.
+        doc.set_current_frame(0);
+        doc.duplicate_frame();
+        assert_eq!(3, doc.frames().len());
+        assert_eq!(1, doc.current_frame());
+        assert_eq!(doc.cel(0, 0).bytes(), doc.cel(1, 0).bytes());
+        assert!(doc.cel(2, 0).bytes().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn it_deletes_and_moves_frames() {
+        let mut doc = doc(1, 1, &druid::Color::BLACK);
+        doc.add_frame();
+        doc.set_frame_tag("walk");
+        doc.add_frame();
+        doc.set_frame_tag("idle");
+
+        doc.move_frame(0);
+        assert_eq!(0, doc.current_frame());
+        let tags: Vec<_> = doc.frames().iter().map(|frame| frame.tag()).collect();
+        assert_eq!(vec![Some("idle"), None, Some("walk")], tags);
+
+        // Moving past the end does nothing.
+        doc.move_frame(3);
+        assert_eq!(0, doc.current_frame());
+        assert_eq!(Some("idle"), doc.frame(0).tag());
+
+        doc.delete_frame();
+        assert_eq!(0, doc.current_frame());
+        assert_eq!(2, doc.frames().len());
+        assert_eq!(None, doc.frame(0).tag());
+
+        doc.set_current_frame(1);
+        doc.delete_frame();
+        doc.delete_frame();
+        assert_eq!(0, doc.current_frame());
+        assert_eq!(1, doc.frames().len());
+    }
+
+    #[test]
+    fn it_clears_empty_frame_tags() {
+        let mut doc = doc(1, 1, &druid::Color::BLACK);
+        doc.set_frame_tag("");
+        assert!(!doc.dirty());
+
+        doc.set_frame_tag("walk");
+        assert_eq!(Some("walk"), doc.frame(0).tag());
+        doc.set_frame_tag("");
+        assert_eq!(None, doc.frame(0).tag());
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_rejects_a_frame_out_of_bounds() {
+        let mut doc = doc(1, 1, &druid::Color::BLACK);
+        doc.set_current_frame(1);
+    }
+
+    #[test]
+    fn it_crops_every_cel() {
+        let red = druid::Color::rgb8(255, 0, 0);
+        let green = druid::Color::rgb8(0, 255, 0);
+        let mut doc = doc(4, 3, &druid::Color::rgba8(0, 0, 0, 0));
+        doc.add_frame();
+        doc.add_layer();
+        assert_eq!(None, doc.opaque_bounds());
+
+        doc.set_current_frame(0);
+        doc.set_current_layer(0);
+        doc.write(druid::Point::new(2.0, 1.0), &red);
+        doc.set_current_frame(1);
+        doc.set_current_layer(1);
+        doc.write(druid::Point::new(3.0, 2.0), &green);
+
+        // The bounds hold the opaque pixels of every frame and layer.
+        let area = doc.opaque_bounds().unwrap();
+        assert_eq!(druid::Rect::new(2.0, 1.0, 4.0, 3.0), area);
+
+        doc.set_selection(druid::Rect::new(1.0, 1.0, 2.0, 2.0));
+        doc.crop(area);
+        assert_eq!(None, doc.selection());
+        for frame in doc.frames() {
+            for cel in frame.cels() {
+                assert_eq!((2, 2), (cel.header().width(), cel.header().height()));
+            }
+        }
+        assert_eq!(red, doc.cel(0, 0).read(druid::Point::new(1.0, 1.0)));
+        assert_eq!(green, doc.cel(1, 1).read(druid::Point::new(2.0, 2.0)));
+    }
+
+    #[test]
+    fn it_conforms_cels_to_a_header() {
+        let red = druid::Color::rgb8(255, 0, 0);
+        let mut doc = doc(2, 2, &red);
+        doc.set_palette(PaletteState::new(vec![0x000000ff, 0xff0000ff]));
+
+        // Pixels stay anchored at the top left, and anything new is transparent.
+        doc.conform(&PixelHeader::new(3, 1, 8, 4));
+        assert_eq!(
+            vec![255, 0, 0, 255, 255, 0, 0, 255, 0, 0, 0, 0],
+            *doc.pixels().bytes()
+        );
+
+        doc.conform(&PixelHeader::new(1, 2, 8, 1));
+        assert_eq!(ColorMode::Indexed, doc.color_mode());
+        assert_eq!(vec![2, constants::TRANSPARENT_INDEX], *doc.pixels().bytes());
+    }
+
+    #[test]
+    fn it_combines_selections() {
+        let mut doc = doc(4, 4, &druid::Color::BLACK);
+        let p = druid::Point::new;
+        let square = |x, y| Some(Mask::from_rect(druid::Rect::new(x, y, x + 1.0, y + 1.0)));
+
+        doc.combine_selection(square(1.0, 1.0), SelectionMode::Add);
+        assert_eq!(Some(druid::Rect::new(1.0, 1.0, 2.0, 2.0)), doc.selection());
+        assert!(doc.mask().is_none());
+
+        doc.combine_selection(square(2.0, 2.0), SelectionMode::Add);
+        assert_eq!(Some(druid::Rect::new(1.0, 1.0, 3.0, 3.0)), doc.selection());
+        assert!(doc.is_selected(p(3.0, 3.0)));
+        assert!(!doc.is_selected(p(3.0, 1.0)));
+
+        doc.combine_selection(square(2.0, 2.0), SelectionMode::Subtract);
+        assert_eq!(Some(druid::Rect::new(1.0, 1.0, 2.0, 2.0)), doc.selection());
+        assert!(doc.is_selected(p(2.0, 1.0)));
+        assert!(!doc.is_selected(p(2.0, 2.0)));
+
+        // Whatever's left is just a rectangle, so it doesn't need a mask.
+        doc.combine_selection(square(2.0, 1.0), SelectionMode::Intersect);
+        assert_eq!(Some(druid::Rect::new(2.0, 1.0, 2.0, 1.0)), doc.selection());
+        assert!(doc.mask().is_none());
+
+        doc.combine_selection(square(3.0, 3.0), SelectionMode::Intersect);
+        assert_eq!(None, doc.selection());
+        assert!(doc.is_selected(p(4.0, 4.0)));
+    }
+
+    #[test]
+    fn it_combines_empty_selections() {
+        let mut doc = doc(4, 4, &druid::Color::BLACK);
+        let square = Some(Mask::from_rect(druid::Rect::new(1.0, 1.0, 2.0, 2.0)));
+
+        doc.combine_selection(square.clone(), SelectionMode::Subtract);
+        assert_eq!(None, doc.selection());
+
+        doc.combine_selection(square, SelectionMode::Replace);
+        doc.combine_selection(None, SelectionMode::Add);
+        doc.combine_selection(None, SelectionMode::Subtract);
+        assert_eq!(Some(druid::Rect::new(1.0, 1.0, 2.0, 2.0)), doc.selection());
+
+        doc.combine_selection(None, SelectionMode::Replace);
+        assert_eq!(None, doc.selection());
+    }
+
+    fn indexed(bytes: Vec<u8>) -> Document {
+        let header = PixelHeader::new(bytes.len() as u32, 1, 8, 1);
+        let mut doc = Document::from_pixels(PixelState::new(header, bytes));
+        doc.set_palette(PaletteState::new(vec![0x000000ff, 0xff0000ff, 0x00ff00ff]));
+        doc.clear_dirty();
+        doc
+    }
+
+    #[test]
+    fn it_remaps_indexed_cels_when_moving_palette_colors() {
+        let mut doc = indexed(vec![constants::TRANSPARENT_INDEX, 1, 2, 3]);
+        doc.duplicate_frame();
+
+        doc.move_palette_color(0, 2);
+        assert_eq!(
+            &[0xff0000ff, 0x00ff00ff, 0x000000ff],
+            doc.palette().values()
+        );
+        for frame in doc.frames() {
+            assert_eq!(
+                vec![constants::TRANSPARENT_INDEX, 3, 1, 2],
+                *frame.cel(0).bytes()
+            );
+        }
+        assert!(doc.dirty());
+
+        // Out of bounds does nothing.
+        doc.clear_dirty();
+        doc.move_palette_color(0, 3);
+        doc.move_palette_color(3, 0);
+        assert_eq!(
+            &[0xff0000ff, 0x00ff00ff, 0x000000ff],
+            doc.palette().values()
+        );
+        assert!(!doc.dirty());
+    }
+
+    #[test]
+    fn it_remaps_indexed_cels_when_deleting_palette_colors() {
+        let mut doc = indexed(vec![constants::TRANSPARENT_INDEX, 1, 2, 3]);
+
+        // Pixels that used the deleted color get the closest one that's left.
+        doc.delete_palette_color(1);
+        assert_eq!(&[0x000000ff, 0x00ff00ff], doc.palette().values());
+        assert_eq!(
+            vec![constants::TRANSPARENT_INDEX, 1, 1, 2],
+            *doc.pixels().bytes()
+        );
+
+        doc.delete_palette_color(2);
+        assert_eq!(2, doc.palette().len());
+
+        doc.delete_palette_color(0);
+        doc.delete_palette_color(0);
+        assert_eq!(&[0x00ff00ff], doc.palette().values());
+        assert_eq!(
+            vec![constants::TRANSPARENT_INDEX, 1, 1, 1],
+            *doc.pixels().bytes()
+        );
+    }
+
+    #[test]
+    fn it_leaves_rgba_cels_alone_when_rearranging_the_palette() {
+        let red = druid::Color::rgb8(255, 0, 0);
+        let mut doc = doc(1, 1, &red);
+        doc.set_palette(PaletteState::new(vec![0x000000ff, 0xff0000ff]));
+
+        doc.move_palette_color(1, 0);
+        doc.delete_palette_color(0);
+        assert_eq!(&[0x000000ff], doc.palette().values());
+        assert_eq!(red, doc.read(druid::Point::new(1.0, 1.0)));
+    }
+}
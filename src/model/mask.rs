@@ -0,0 +1,385 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::model::types::SelectionMode;
+
+/// A selection mask. This holds a flag for every pixel within an area of the image, saying
+/// whether or not that pixel is selected. The area is inclusive, like a selection, and in
+/// canvas coordinates (so it starts at 1,1).
+#[derive(Clone, druid::Data)]
+pub struct Mask {
+    area: druid::Rect,
+    bits: Arc<Vec<bool>>,
+}
+
+impl Mask {
+    /// Create a mask covering the given area, with nothing selected.
+    pub fn new(area: druid::Rect) -> Self {
+        Self::filled(area, false)
+    }
+
+    /// Create a mask covering the given area, with everything selected.
+    pub fn from_rect(area: druid::Rect) -> Self {
+        Self::filled(area, true)
+    }
+
+    fn filled(area: druid::Rect, value: bool) -> Self {
+        let len = (area.width() as usize + 1) * (area.height() as usize + 1);
+
+        Self {
+            area,
+            bits: Arc::new(vec![value; len]),
+        }
+    }
+
+    /// Create a mask from a closed polygon, such as a lasso. Every pixel inside the polygon
+    /// is selected, as is every pixel that the outline passes through. The mask is clipped
+    /// to the given bounds, and trimmed to fit. Returns None if nothing is selected.
+    pub fn from_polygon(points: &[druid::Point], bounds: druid::Rect) -> Option<Self> {
+        let first = points.first()?;
+        let area = points
+            .iter()
+            .fold(druid::Rect::from_points(*first, *first), |area, p| {
+                area.union_pt(*p)
+            });
+        if area.x1 < bounds.x0 || area.y1 < bounds.y0 || area.x0 > bounds.x1 || area.y0 > bounds.y1
+        {
+            return None;
+        }
+        let area = area.intersect(bounds);
+
+        let mut mask = Self::new(area);
+
+        for y in area.y0 as usize..area.y1 as usize + 1 {
+            for x in area.x0 as usize..area.x1 as usize + 1 {
+                if Self::inside(points, x as f64, y as f64) {
+                    mask.set_xy(x, y, true);
+                }
+            }
+        }
+
+        // The outline. Walk each edge a pixel at a time, wrapping around to close the shape.
+        for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+            let steps = (b.x - a.x).abs().max((b.y - a.y).abs()).max(1.0);
+            for i in 0..steps as usize + 1 {
+                let t = i as f64 / steps;
+                let p = a.lerp(*b, t).round();
+                if mask.in_area(p) {
+                    mask.set_xy(p.x as usize, p.y as usize, true);
+                }
+            }
+        }
+
+        mask.trim()
+    }
+
+    /// Even-odd test of whether a point lies within the polygon.
+    fn inside(points: &[druid::Point], x: f64, y: f64) -> bool {
+        let mut inside = false;
+
+        for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+            if (a.y > y) != (b.y > y) {
+                let cross = a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y);
+                if x < cross {
+                    inside = !inside;
+                }
+            }
+        }
+
+        inside
+    }
+
+    /// Get the area covered by the mask.
+    pub fn area(&self) -> druid::Rect {
+        self.area
+    }
+
+    #[inline]
+    fn in_area(&self, p: druid::Point) -> bool {
+        p.x >= self.area.x0 && p.x <= self.area.x1 && p.y >= self.area.y0 && p.y <= self.area.y1
+    }
+
+    #[inline]
+    fn xy_to_idx(&self, x: usize, y: usize) -> usize {
+        let width = self.area.width() as usize + 1;
+        (y - self.area.y0 as usize) * width + (x - self.area.x0 as usize)
+    }
+
+    /// Is the given point selected?
+    #[inline]
+    pub fn contains(&self, p: druid::Point) -> bool {
+        self.in_area(p) && self.bits[self.xy_to_idx(p.x as usize, p.y as usize)]
+    }
+
+    /// Is the given point selected?
+    #[inline]
+    pub fn contains_xy(&self, x: usize, y: usize) -> bool {
+        self.contains(druid::Point::new(x as f64, y as f64))
+    }
+
+    /// Select or deselect the given point, which must lie within the area.
+    pub fn set_xy(&mut self, x: usize, y: usize, value: bool) {
+        let idx = self.xy_to_idx(x, y);
+        Arc::make_mut(&mut self.bits)[idx] = value;
+    }
+
+    /// Is every pixel in the area selected? If so, the mask is just a rectangle.
+    pub fn is_full(&self) -> bool {
+        self.bits.iter().all(|&bit| bit)
+    }
+
+    /// Get a copy of the mask, moved so that its area starts at the given point.
+    pub fn with_origin(&self, origin: druid::Point) -> Self {
+        Self {
+            area: self.area.with_origin(origin),
+            bits: self.bits.clone(),
+        }
+    }
+
+    /// Combine another mask with this one. Returns None if nothing is left selected.
+    pub fn combine(&self, other: &Mask, mode: SelectionMode) -> Option<Self> {
+        let area = match mode {
+            SelectionMode::Replace => return Some(other.clone()),
+            SelectionMode::Add => self.area.union(other.area),
+            SelectionMode::Subtract => self.area,
+            SelectionMode::Intersect => self.area.intersect(other.area),
+        };
+
+        let mut mask = Self::new(area);
+        for y in area.y0 as usize..area.y1 as usize + 1 {
+            for x in area.x0 as usize..area.x1 as usize + 1 {
+                let a = self.contains_xy(x, y);
+                let b = other.contains_xy(x, y);
+                let selected = match mode {
+                    SelectionMode::Add => a || b,
+                    SelectionMode::Subtract => a && !b,
+                    _ => a && b,
+                };
+                mask.set_xy(x, y, selected);
+            }
+        }
+
+        mask.trim()
+    }
+
+    /// Move every selected pixel to wherever the function says, within the given area.
+    /// Anything that lands outside the area is dropped. Returns None if nothing is left
+    /// selected.
+    pub fn map<F>(&self, area: druid::Rect, f: F) -> Option<Self>
+    where
+        F: Fn(druid::Point) -> druid::Point,
+    {
+        let mut mask = Self::new(area);
+        for y in self.area.y0 as usize..self.area.y1 as usize + 1 {
+            for x in self.area.x0 as usize..self.area.x1 as usize + 1 {
+                if self.contains_xy(x, y) {
+                    let p = f(druid::Point::new(x as f64, y as f64));
+                    if mask.in_area(p) {
+                        mask.set_xy(p.x as usize, p.y as usize, true);
+                    }
+                }
+            }
+        }
+
+        mask.trim()
+    }
+
+    /// Select everything within the bounds that isn't selected now, and vice versa.
+    pub fn invert(&self, bounds: druid::Rect) -> Option<Self> {
+        let mut mask = Self::new(bounds);
+        for y in bounds.y0 as usize..bounds.y1 as usize + 1 {
+            for x in bounds.x0 as usize..bounds.x1 as usize + 1 {
+                mask.set_xy(x, y, !self.contains_xy(x, y));
+            }
+        }
+
+        mask.trim()
+    }
+
+    /// Grow the selection outwards by the given number of pixels, diagonals included. The
+    /// result is clipped to the bounds.
+    pub fn grow(&self, by: usize, bounds: druid::Rect) -> Option<Self> {
+        let area = self.area.inflate(by as f64, by as f64).intersect(bounds);
+        self.morph(area, by, true)
+    }
+
+    /// Shrink the selection inwards by the given number of pixels, diagonals included.
+    /// Returns None if nothing is left selected.
+    pub fn shrink(&self, by: usize) -> Option<Self> {
+        self.morph(self.area, by, false)
+    }
+
+    /// Grow (dilate) or shrink (erode) the selection with a square the given number of
+    /// pixels either side. The square is separable, so we do a pass across each row, and
+    /// then another down each column.
+    fn morph(&self, area: druid::Rect, by: usize, grow: bool) -> Option<Self> {
+        fn check(mut values: impl Iterator<Item = bool>, grow: bool) -> bool {
+            if grow {
+                values.any(|v| v)
+            } else {
+                values.all(|v| v)
+            }
+        }
+
+        let mut rows = Self::new(area);
+        for y in area.y0 as usize..area.y1 as usize + 1 {
+            for x in area.x0 as usize..area.x1 as usize + 1 {
+                let values = (x as isize - by as isize..x as isize + by as isize + 1)
+                    .map(|x| x > 0 && self.contains_xy(x as usize, y));
+                rows.set_xy(x, y, check(values, grow));
+            }
+        }
+
+        let mut mask = Self::new(area);
+        for y in area.y0 as usize..area.y1 as usize + 1 {
+            for x in area.x0 as usize..area.x1 as usize + 1 {
+                let values = (y as isize - by as isize..y as isize + by as isize + 1)
+                    .map(|y| y > 0 && rows.contains_xy(x, y as usize));
+                mask.set_xy(x, y, check(values, grow));
+            }
+        }
+
+        mask.trim()
+    }
+
+    /// Shrink the area to fit the selected pixels. Returns None if nothing is selected.
+    pub fn trim(self) -> Option<Self> {
+        let mut bounds: Option<druid::Rect> = None;
+
+        for y in self.area.y0 as usize..self.area.y1 as usize + 1 {
+            for x in self.area.x0 as usize..self.area.x1 as usize + 1 {
+                if self.contains_xy(x, y) {
+                    let p = druid::Point::new(x as f64, y as f64);
+                    bounds = Some(bounds.map_or(druid::Rect::from_points(p, p), |b| b.union_pt(p)));
+                }
+            }
+        }
+
+        let bounds = bounds?;
+        if bounds == self.area {
+            return Some(self);
+        }
+
+        let mut mask = Self::new(bounds);
+        for y in bounds.y0 as usize..bounds.y1 as usize + 1 {
+            for x in bounds.x0 as usize..bounds.x1 as usize + 1 {
+                mask.set_xy(x, y, self.contains_xy(x, y));
+            }
+        }
+
+        Some(mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(mask: &Mask) -> Vec<String> {
+        let area = mask.area();
+        (area.y0 as usize..area.y1 as usize + 1)
+            .map(|y| {
+                (area.x0 as usize..area.x1 as usize + 1)
+                    .map(|x| if mask.contains_xy(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_fills_polygon() {
+        let points = [
+            druid::Point::new(3.0, 1.0),
+            druid::Point::new(5.0, 3.0),
+            druid::Point::new(3.0, 5.0),
+            druid::Point::new(1.0, 3.0),
+        ];
+        let mask = Mask::from_polygon(&points, druid::Rect::new(1.0, 1.0, 8.0, 8.0)).unwrap();
+        assert_eq!(druid::Rect::new(1.0, 1.0, 5.0, 5.0), mask.area());
+        assert_eq!(
+            vec!["..#..", ".###.", "#####", ".###.", "..#.."],
+            rows(&mask)
+        );
+    }
+
+    #[test]
+    fn it_clips_polygon() {
+        let points = [
+            druid::Point::new(0.0, 0.0),
+            druid::Point::new(3.0, 0.0),
+            druid::Point::new(3.0, 3.0),
+            druid::Point::new(0.0, 3.0),
+        ];
+        let mask = Mask::from_polygon(&points, druid::Rect::new(1.0, 1.0, 8.0, 8.0)).unwrap();
+        assert_eq!(druid::Rect::new(1.0, 1.0, 3.0, 3.0), mask.area());
+        assert!(mask.is_full());
+    }
+
+    #[test]
+    fn it_combines() {
+        let a = Mask::from_rect(druid::Rect::new(1.0, 1.0, 2.0, 2.0));
+        let b = Mask::from_rect(druid::Rect::new(2.0, 2.0, 3.0, 3.0));
+
+        let add = a.combine(&b, SelectionMode::Add).unwrap();
+        assert_eq!(vec!["##.", "###", ".##"], rows(&add));
+
+        let subtract = a.combine(&b, SelectionMode::Subtract).unwrap();
+        assert_eq!(vec!["##", "#."], rows(&subtract));
+
+        let intersect = a.combine(&b, SelectionMode::Intersect).unwrap();
+        assert_eq!(druid::Rect::new(2.0, 2.0, 2.0, 2.0), intersect.area());
+        assert!(intersect.is_full());
+
+        let c = Mask::from_rect(druid::Rect::new(4.0, 4.0, 4.0, 4.0));
+        assert!(a.combine(&c, SelectionMode::Intersect).is_none());
+    }
+
+    #[test]
+    fn it_inverts() {
+        let mask = Mask::from_rect(druid::Rect::new(1.0, 1.0, 2.0, 3.0));
+        let inverted = mask.invert(druid::Rect::new(1.0, 1.0, 3.0, 3.0)).unwrap();
+        assert_eq!(druid::Rect::new(3.0, 1.0, 3.0, 3.0), inverted.area());
+        assert!(inverted.is_full());
+    }
+
+    #[test]
+    fn it_grows_and_shrinks() {
+        let mut mask = Mask::new(druid::Rect::new(3.0, 3.0, 3.0, 3.0));
+        mask.set_xy(3, 3, true);
+
+        let grown = mask.grow(1, druid::Rect::new(1.0, 1.0, 8.0, 8.0)).unwrap();
+        assert_eq!(druid::Rect::new(2.0, 2.0, 4.0, 4.0), grown.area());
+        assert!(grown.is_full());
+
+        let clipped = grown.grow(2, druid::Rect::new(1.0, 1.0, 8.0, 8.0)).unwrap();
+        assert_eq!(druid::Rect::new(1.0, 1.0, 6.0, 6.0), clipped.area());
+
+        let shrunk = grown.shrink(1).unwrap();
+        assert_eq!(druid::Rect::new(3.0, 3.0, 3.0, 3.0), shrunk.area());
+        assert!(grown.shrink(2).is_none());
+    }
+
+    #[test]
+    fn it_trims() {
+        let mut mask = Mask::new(druid::Rect::new(1.0, 1.0, 4.0, 4.0));
+        mask.set_xy(2, 3, true);
+        mask.set_xy(3, 2, true);
+        let mask = mask.trim().unwrap();
+        assert_eq!(druid::Rect::new(2.0, 2.0, 3.0, 3.0), mask.area());
+        assert_eq!(vec![".#", "#."], rows(&mask));
+        assert!(Mask::new(mask.area()).trim().is_none());
+    }
+}
@@ -13,7 +13,16 @@
 // limitations under the License.
 
 pub mod app;
+pub mod color_picker;
+pub mod compositor;
 pub mod document;
+pub mod mask;
 pub mod mod_stack;
+pub mod new_image;
+pub mod palette;
 pub mod pixels;
+pub mod sheet;
+pub mod stroke;
 pub mod types;
+pub mod viewport;
+pub mod wand;
@@ -15,27 +15,62 @@
 use std::collections::VecDeque;
 use std::sync::Arc;
 
+use crate::common::constants;
+use crate::model::pixels::PixelHeader;
+use crate::model::pixels::PixelState;
 use crate::model::types::PixelBytes;
 
-/// Depth of the modification stack. This seems big, but remember that we're dealing
-/// with tiny little bitmaps, and we only record what's changed.
-const STACK_DEPTH: usize = 16;
-
-/// Modification record. This holds undo state.
+/// Modification record. This holds undo state for an area of a single cel, which is
+/// identified by its frame and layer. Records can be joined to the one beneath them on
+/// the stack, for changes that touch more than one cel.
 #[derive(Clone, druid::Data)]
 pub struct ModRecord {
+    frame: usize,
+    layer: usize,
     area: druid::Rect,
     bytes: PixelBytes,
+    header: Option<PixelHeader>,
+    joined: bool,
 }
 
 impl ModRecord {
-    pub fn new(area: druid::Rect, bytes: Vec<u8>) -> Self {
+    pub fn new(frame: usize, layer: usize, area: druid::Rect, bytes: Vec<u8>) -> Self {
         Self {
+            frame,
+            layer,
             area,
             bytes: Arc::new(bytes),
+            header: None,
+            joined: false,
+        }
+    }
+
+    /// Create a record for an entire cel, header and all. Use this when the dimensions of
+    /// the cel are about to change.
+    pub fn with_header(frame: usize, layer: usize, header: PixelHeader, bytes: Vec<u8>) -> Self {
+        let area = druid::Rect::new(
+            1.0,
+            1.0,
+            header.width() as f64 + 1.0,
+            header.height() as f64 + 1.0,
+        );
+
+        Self {
+            header: Some(header),
+            ..Self::new(frame, layer, area, bytes)
         }
     }
 
+    /// Get the index of the frame that was modified.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Get the index of the layer that was modified.
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
     pub fn area(&self) -> druid::Rect {
         self.area
     }
@@ -43,29 +78,163 @@ impl ModRecord {
     pub fn bytes(&self) -> &Vec<u8> {
         &*self.bytes
     }
+
+    /// Get the header to restore, if the record covers an entire cel.
+    pub fn header(&self) -> Option<&PixelHeader> {
+        self.header.as_ref()
+    }
+
+    /// Is the record undone along with the record beneath it?
+    pub fn joined(&self) -> bool {
+        self.joined
+    }
+
+    pub fn set_joined(&mut self, joined: bool) {
+        self.joined = joined;
+    }
 }
 
-/// Stack of modification records. Used for undo and redo.
+/// An undo transaction on a single cel. This holds on to the cel as it was when the
+/// transaction began, and grows to cover whatever has changed since. When it's done, it
+/// becomes a single record, so that a whole brush stroke can be undone in one go.
+#[derive(Clone, druid::Data)]
+pub struct Transaction {
+    frame: usize,
+    layer: usize,
+    pixels: PixelState,
+    area: Option<druid::Rect>,
+}
+
+impl Transaction {
+    pub fn new(frame: usize, layer: usize, pixels: PixelState) -> Self {
+        Self {
+            frame,
+            layer,
+            pixels,
+            area: None,
+        }
+    }
+
+    /// Get the index of the frame that's being modified.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Get the index of the layer that's being modified.
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
+    /// Get the number of bytes that the transaction holds on to.
+    pub fn size(&self) -> usize {
+        self.pixels.bytes().len()
+    }
+
+    /// Grow the transaction to cover the given area.
+    pub fn extend(&mut self, area: druid::Rect) {
+        self.area = Some(match self.area {
+            Some(old_area) => old_area.union(area),
+            None => area,
+        });
+    }
+
+    /// Turn the transaction into a record of everything it covers. Returns None if
+    /// nothing changed.
+    pub fn into_record(self) -> Option<ModRecord> {
+        let area = self.area?;
+        let bytes = self.pixels.read_area(area);
+        Some(ModRecord::new(self.frame, self.layer, area, bytes))
+    }
+}
+
+/// Stack of modification records. Used for undo and redo. The stack is limited to a
+/// number of steps, and to a memory budget; whichever runs out first. Either way, the
+/// oldest steps are discarded. A step that doesn't fit in the budget by itself is
+/// discarded too, along with everything before it, so it can't be undone.
 #[derive(Clone, druid::Data)]
 pub struct ModStack {
     q: Arc<VecDeque<ModRecord>>,
+    depth: usize,
+    budget: usize,
+    reserved: usize,
 }
 
 impl Default for ModStack {
     fn default() -> Self {
         Self {
-            q: Arc::new(VecDeque::with_capacity(STACK_DEPTH)),
+            q: Default::default(),
+            depth: constants::UNDO_DEPTH,
+            budget: constants::UNDO_BUDGET,
+            reserved: 0,
         }
     }
 }
 
 impl ModStack {
-    /// Push a modification record onto the stack. This will maintain the stack depth;
-    /// any items beyond the initial capacity are discarded.
+    /// Get the max number of steps on the stack.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Set the max number of steps on the stack. Any steps beyond it are discarded.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.truncate();
+    }
+
+    /// Get the max number of bytes that the stack can hold.
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Set the max number of bytes that the stack can hold. Any steps beyond it are
+    /// discarded.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.truncate();
+    }
+
+    /// Set the number of bytes of the budget that are in use elsewhere, like the copy of
+    /// the cel that a transaction hangs on to. Steps are discarded to make room.
+    pub fn set_reserved(&mut self, reserved: usize) {
+        self.reserved = reserved;
+        self.truncate();
+    }
+
+    /// Push a modification record onto the stack. This will maintain the stack depth and
+    /// budget. Joined records count as a single step, so they're kept or discarded
+    /// together.
     pub fn push(&mut self, record: ModRecord) {
         let q = Arc::make_mut(&mut self.q);
         q.push_front(record);
-        q.truncate(STACK_DEPTH);
+
+        self.truncate();
+    }
+
+    /// Discard the oldest steps, until the stack is within its depth and budget. Records
+    /// at the bottom that are joined to nothing are discarded too; they're what's left of
+    /// a step that was discarded while it was still being pushed.
+    fn truncate(&mut self) {
+        let mut steps = 0;
+        let mut size = self.reserved;
+        let mut keep = 0;
+
+        // Records go from newest to oldest, and a step ends with a record that isn't
+        // joined to the one beneath it.
+        for (idx, record) in self.q.iter().enumerate() {
+            size += record.bytes.len();
+            if !record.joined {
+                steps += 1;
+                if steps > self.depth || size > self.budget {
+                    break;
+                }
+                keep = idx + 1;
+            }
+        }
+
+        if keep < self.q.len() {
+            Arc::make_mut(&mut self.q).truncate(keep);
+        }
     }
 
     /// Pop a modification record from the stack.
@@ -78,9 +247,251 @@ impl ModStack {
         self.q.is_empty()
     }
 
+    /// A layer was inserted at the given index. Shift records for layers at or above it.
+    pub fn insert_layer(&mut self, idx: usize) {
+        self.remap(|frame, layer| Some((frame, shift_insert(layer, idx))));
+    }
+
+    /// The layer at the given index was removed. Drop its records, and shift the records
+    /// for the layers above it.
+    pub fn remove_layer(&mut self, idx: usize) {
+        self.remap(|frame, layer| shift_remove(layer, idx).map(|layer| (frame, layer)));
+    }
+
+    /// A frame was inserted at the given index. Shift records for frames at or after it.
+    pub fn insert_frame(&mut self, idx: usize) {
+        self.remap(|frame, layer| Some((shift_insert(frame, idx), layer)));
+    }
+
+    /// The frame at the given index was removed. Drop its records, and shift the records
+    /// for the frames after it.
+    pub fn remove_frame(&mut self, idx: usize) {
+        self.remap(|frame, layer| shift_remove(frame, idx).map(|frame| (frame, layer)));
+    }
+
+    /// A frame was moved from one index to another. Shift records to match.
+    pub fn move_frame(&mut self, from: usize, to: usize) {
+        self.remap(|frame, layer| {
+            let new_frame = if frame == from {
+                to
+            } else {
+                shift_insert(shift_remove(frame, from).unwrap(), to)
+            };
+            Some((new_frame, layer))
+        });
+    }
+
+    /// The palette of an indexed document was rearranged. Map the indices in every record
+    /// that holds indexed pixels to their new values. Records are in the color mode of the
+    /// document, until a record with a header says otherwise for those beneath it.
+    pub fn remap_indices(&mut self, map: &[u8], bytes_per_pixel: u8) {
+        let q = Arc::make_mut(&mut self.q);
+        let mut bytes_per_pixel = bytes_per_pixel;
+
+        for record in q.iter_mut() {
+            if let Some(header) = &record.header {
+                bytes_per_pixel = header.bytes_per_pixel();
+            }
+            if bytes_per_pixel == 1 {
+                let bytes = record.bytes.iter().map(|&idx| map[idx as usize]).collect();
+                record.bytes = Arc::new(bytes);
+            }
+        }
+    }
+
+    /// Fix up the frame and layer of every record. Records for which the given function
+    /// returns None are dropped. If the bottom record of a joined run is dropped, the next
+    /// one up takes its place, so that the run doesn't get joined to the one below it.
+    fn remap<F>(&mut self, f: F)
+    where
+        F: Fn(usize, usize) -> Option<(usize, usize)>,
+    {
+        let q = Arc::make_mut(&mut self.q);
+        let records: Vec<ModRecord> = q.drain(..).rev().collect();
+        let mut unjoin = false;
+
+        for mut record in records {
+            match f(record.frame, record.layer) {
+                Some((frame, layer)) => {
+                    record.frame = frame;
+                    record.layer = layer;
+                    if unjoin {
+                        record.joined = false;
+                        unjoin = false;
+                    }
+                    q.push_front(record);
+                }
+                None => unjoin = unjoin || !record.joined,
+            }
+        }
+    }
+
     /// Clear out the modification stack.
     pub fn clear(&mut self) {
         let q = Arc::make_mut(&mut self.q);
         q.clear();
     }
 }
+
+/// Where an index ends up after an item is inserted at `at`.
+fn shift_insert(idx: usize, at: usize) -> usize {
+    if idx >= at {
+        idx + 1
+    } else {
+        idx
+    }
+}
+
+/// Where an index ends up after the item at `at` is removed. None if it was removed.
+fn shift_remove(idx: usize, at: usize) -> Option<usize> {
+    match idx {
+        _ if idx == at => None,
+        _ if idx > at => Some(idx - 1),
+        _ => Some(idx),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack_of(cels: &[(usize, usize)]) -> ModStack {
+        let mut stack: ModStack = Default::default();
+        for &(frame, layer) in cels.iter().rev() {
+            stack.push(ModRecord::new(frame, layer, druid::Rect::ZERO, vec![]));
+        }
+        stack
+    }
+
+    fn cels_of(stack: &ModStack) -> Vec<(usize, usize)> {
+        stack.q.iter().map(|r| (r.frame(), r.layer())).collect()
+    }
+
+    #[test]
+    fn it_removes_layer_records() {
+        let mut stack = stack_of(&[(0, 0), (0, 1), (1, 2)]);
+        stack.remove_layer(1);
+        assert_eq!(vec![(0, 0), (1, 1)], cels_of(&stack));
+    }
+
+    #[test]
+    fn it_inserts_frame_records() {
+        let mut stack = stack_of(&[(0, 0), (1, 0), (2, 0)]);
+        stack.insert_frame(1);
+        assert_eq!(vec![(0, 0), (2, 0), (3, 0)], cels_of(&stack));
+    }
+
+    #[test]
+    fn it_truncates_whole_steps() {
+        let mut stack: ModStack = Default::default();
+        let depth = stack.depth();
+        for i in 0..depth + 1 {
+            for layer in 0..2 {
+                let mut record = ModRecord::new(i, layer, druid::Rect::ZERO, vec![]);
+                record.set_joined(layer > 0);
+                stack.push(record);
+            }
+        }
+        assert_eq!(depth * 2, stack.q.len());
+        assert!(!stack.q.back().unwrap().joined());
+        assert_eq!((1, 0), cels_of(&stack).pop().unwrap());
+    }
+
+    #[test]
+    fn it_truncates_to_depth() {
+        let mut stack = stack_of(&[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        stack.set_depth(2);
+        assert_eq!(vec![(0, 0), (1, 0)], cels_of(&stack));
+    }
+
+    #[test]
+    fn it_truncates_to_budget() {
+        let mut stack = ModStack {
+            budget: 10,
+            ..Default::default()
+        };
+        for i in 0..4 {
+            stack.push(ModRecord::new(i, 0, druid::Rect::ZERO, vec![0; 4]));
+        }
+        assert_eq!(vec![(3, 0), (2, 0)], cels_of(&stack));
+
+        // Room that's reserved for a transaction counts against the budget.
+        stack.set_reserved(4);
+        assert_eq!(vec![(3, 0)], cels_of(&stack));
+    }
+
+    #[test]
+    fn it_evicts_steps_before_an_oversized_step() {
+        let mut stack = ModStack {
+            budget: 10,
+            ..Default::default()
+        };
+        stack.push(ModRecord::new(0, 0, druid::Rect::ZERO, vec![0; 4]));
+
+        // A step that's too big for the budget can't be kept, and neither can the steps
+        // before it, because they'd no longer apply.
+        stack.push(ModRecord::new(1, 0, druid::Rect::ZERO, vec![0; 16]));
+        assert!(stack.is_empty());
+
+        // The rest of the step goes the same way.
+        let mut record = ModRecord::new(1, 1, druid::Rect::ZERO, vec![0; 4]);
+        record.set_joined(true);
+        stack.push(record);
+        assert!(stack.is_empty());
+
+        for i in 2..5 {
+            stack.push(ModRecord::new(i, 0, druid::Rect::ZERO, vec![0; 4]));
+        }
+        assert_eq!(vec![(4, 0), (3, 0)], cels_of(&stack));
+    }
+
+    #[test]
+    fn it_records_transactions() {
+        let header = PixelHeader::new(4, 4, 8, 4);
+        let mut transaction = Transaction::new(1, 2, PixelState::empty(header.clone()));
+        assert!(transaction.clone().into_record().is_none());
+
+        transaction.extend(druid::Rect::new(1.0, 1.0, 2.0, 2.0));
+        transaction.extend(druid::Rect::new(3.0, 2.0, 4.0, 3.0));
+        let record = transaction.into_record().unwrap();
+        assert_eq!((1, 2), (record.frame(), record.layer()));
+        assert_eq!(druid::Rect::new(1.0, 1.0, 4.0, 3.0), record.area());
+        assert_eq!(3 * 2 * 4, record.bytes().len());
+    }
+
+    #[test]
+    fn it_keeps_steps_when_removing_base_record() {
+        let mut stack: ModStack = Default::default();
+        for layer in 0..3 {
+            let mut record = ModRecord::new(0, layer, druid::Rect::ZERO, vec![]);
+            record.set_joined(layer > 0);
+            stack.push(record);
+        }
+        stack.remove_layer(0);
+        assert_eq!(vec![(0, 1), (0, 0)], cels_of(&stack));
+        assert!(stack.q[0].joined());
+        assert!(!stack.q[1].joined());
+    }
+
+    #[test]
+    fn it_moves_frame_records() {
+        let mut stack = stack_of(&[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        stack.move_frame(0, 2);
+        assert_eq!(vec![(2, 0), (0, 0), (1, 0), (3, 0)], cels_of(&stack));
+    }
+
+    #[test]
+    fn it_remaps_indexed_records() {
+        let mut stack: ModStack = Default::default();
+        let rgba = PixelHeader::new(1, 1, 8, 4);
+        stack.push(ModRecord::new(0, 0, druid::Rect::ZERO, vec![1, 2, 3, 4]));
+        stack.push(ModRecord::with_header(0, 0, rgba, vec![0, 1, 2, 3]));
+        stack.push(ModRecord::new(0, 0, druid::Rect::ZERO, vec![0, 1, 2]));
+
+        stack.remap_indices(&[0, 2, 1, 3], 1);
+        let bytes: Vec<&Vec<u8>> = stack.q.iter().map(|r| r.bytes()).collect();
+        assert_eq!(vec![0, 2, 1], *bytes[0]);
+        assert_eq!(vec![0, 1, 2, 3], *bytes[1]);
+        assert_eq!(vec![1, 2, 3, 4], *bytes[2]);
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::constants;
+use crate::model::document::Document;
+use crate::model::types::Background;
+
+/// Preset sizes for new images, as (label, width, height). NES and GB are the screen sizes
+/// of those consoles.
+pub const PRESETS: [(&str, usize, usize); 5] = [
+    ("8x8", 8, 8),
+    ("16x16", 16, 16),
+    ("32x32", 32, 32),
+    ("NES", 256, 240),
+    ("GB", 160, 144),
+];
+
+/// New image options. These stick around between new images, so that making a run of
+/// images the same size is painless.
+#[derive(Clone, druid::Data)]
+pub struct NewImageOptions {
+    width: usize,
+    height: usize,
+    background: Background,
+}
+
+impl NewImageOptions {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn set_width(&mut self, width: usize) {
+        self.width = width.max(1).min(constants::MAX_PIXEL_DIMS as usize);
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set_height(&mut self, height: usize) {
+        self.height = height.max(1).min(constants::MAX_PIXEL_DIMS as usize);
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /// Set the width and height in one go, like a preset does.
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        self.set_width(width);
+        self.set_height(height);
+    }
+
+    /// Get the color that fills the background. The brush color is only used if that's
+    /// the background that was picked.
+    pub fn background_color(&self, brush_color: &druid::Color) -> druid::Color {
+        match self.background {
+            Background::Transparent => druid::Color::rgba8(0, 0, 0, 0),
+            Background::White => druid::Color::WHITE,
+            Background::Black => druid::Color::BLACK,
+            Background::Brush => brush_color.clone(),
+        }
+    }
+
+    /// Create a new document with these options.
+    pub fn to_document(&self, brush_color: &druid::Color) -> Document {
+        let color = self.background_color(brush_color);
+        Document::new(self.width as u32, self.height as u32, &color)
+    }
+}
+
+impl Default for NewImageOptions {
+    fn default() -> Self {
+        Self {
+            width: constants::DEFAULT_PIXEL_DIMS as usize,
+            height: constants::DEFAULT_PIXEL_DIMS as usize,
+            background: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_fills_the_background() {
+        let brush_color = druid::Color::rgba8(10, 20, 30, 40);
+        let backgrounds = [
+            (Background::Transparent, [0, 0, 0, 0]),
+            (Background::White, [255, 255, 255, 255]),
+            (Background::Black, [0, 0, 0, 255]),
+            (Background::Brush, [10, 20, 30, 40]),
+        ];
+
+        for &(background, rgba) in backgrounds.iter() {
+            let mut options = NewImageOptions::default();
+            options.set_size(3, 2);
+            options.set_background(background);
+
+            let doc = options.to_document(&brush_color);
+            assert_eq!(3, doc.header().width());
+            assert_eq!(2, doc.header().height());
+            assert_eq!(4, doc.header().bytes_per_pixel());
+            assert_eq!(&rgba.repeat(6), doc.pixels().bytes());
+            assert!(!doc.dirty());
+        }
+    }
+
+    #[test]
+    fn it_sizes_from_presets() {
+        for &(_, width, height) in PRESETS.iter() {
+            let mut options = NewImageOptions::default();
+            options.set_size(width, height);
+
+            let doc = options.to_document(&druid::Color::BLACK);
+            assert_eq!(width, doc.header().width());
+            assert_eq!(height, doc.header().height());
+            assert_eq!(1, doc.frames().len());
+            assert_eq!(1, doc.layers().len());
+        }
+    }
+
+    #[test]
+    fn it_clamps_the_size() {
+        let mut options = NewImageOptions::default();
+        options.set_size(0, constants::MAX_PIXEL_DIMS as usize + 1);
+        assert_eq!(1, options.width());
+        assert_eq!(constants::MAX_PIXEL_DIMS as usize, options.height());
+    }
+}
@@ -0,0 +1,478 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::common::constants;
+use crate::model::pixels::PixelHeader;
+use crate::model::pixels::PixelState;
+use crate::model::types::PalettePreset;
+use crate::model::types::PaletteSort;
+use crate::transforms::util;
+
+/// The active palette. Each value is stored as a u32 representation of RGBA, with the
+/// alpha value in the least significant position. This matches what Color does internally.
+#[derive(Clone, druid::Data)]
+pub struct PaletteState {
+    values: Arc<Vec<u32>>,
+}
+
+impl PaletteState {
+    pub fn new(values: Vec<u32>) -> Self {
+        Self {
+            values: Arc::new(values),
+        }
+    }
+
+    /// Create a palette from raw byte values. The format is RGBA8, so four bytes per
+    /// value including the alpha in the least significant position [a, b, g, r].
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let values = bytes
+            .chunks_exact(4)
+            .map(|v| u32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+            .collect();
+
+        Self::new(values)
+    }
+
+    /// Create one of the built-in palettes.
+    pub fn from_preset(preset: PalettePreset) -> Self {
+        let values: &[u32] = match preset {
+            PalettePreset::Vga => return Self::default(),
+            PalettePreset::Pico8 => &PICO_8,
+            PalettePreset::Nes => &NES,
+            PalettePreset::GameBoy => &GAME_BOY,
+            PalettePreset::C64 => &C64,
+            PalettePreset::Cga => &CGA,
+            PalettePreset::Ega => &EGA,
+            PalettePreset::ZxSpectrum => &ZX_SPECTRUM,
+            PalettePreset::Sweetie16 => &SWEETIE_16,
+        };
+
+        Self::new(values.to_vec())
+    }
+
+    pub fn values(&self) -> &[u32] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Set the value at the given index.
+    pub fn set_value(&mut self, idx: usize, value: u32) {
+        Arc::make_mut(&mut self.values)[idx] = value;
+    }
+
+    /// Check whether indexed documents can use every color in the palette. Colors past
+    /// `MAX_INDEXED_COLORS` don't get an index of their own, which only matters if they
+    /// aren't repeats of colors that do.
+    pub fn indexable(&self) -> bool {
+        let (reachable, rest) = self
+            .values
+            .split_at(self.values.len().min(constants::MAX_INDEXED_COLORS));
+        rest.iter().all(|value| reachable.contains(value))
+    }
+
+    /// Add a value to the end of the palette. Returns false if the palette is full.
+    pub fn push(&mut self, value: u32) -> bool {
+        if self.values.len() >= constants::MAX_PALETTE_COLORS {
+            return false;
+        }
+
+        Arc::make_mut(&mut self.values).push(value);
+        true
+    }
+
+    /// Create a palette from the values at the given indices, in order. Values that aren't
+    /// in the order are dropped.
+    pub fn reorder(&self, order: &[usize]) -> Self {
+        Self::new(order.iter().map(|&idx| self.values[idx]).collect())
+    }
+
+    /// Get the order that sorts the palette. Usage is the number of pixels that use each
+    /// value, and it's only needed to sort by usage.
+    pub fn sort_order(&self, sort: PaletteSort, usage: &[usize]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.values.len()).collect();
+
+        // Sorts are stable, so values that compare equal keep their order.
+        match sort {
+            PaletteSort::Hue => order.sort_by(|&a, &b| {
+                let (ha, sa, la, _) = self.hsla(a);
+                let (hb, sb, lb, _) = self.hsla(b);
+                let gray_a = sa < 0.05;
+                let gray_b = sb < 0.05;
+                let by_hue = if gray_a && gray_b {
+                    std::cmp::Ordering::Equal
+                } else {
+                    ha.total_cmp(&hb)
+                };
+                gray_b.cmp(&gray_a).then(by_hue).then(la.total_cmp(&lb))
+            }),
+            PaletteSort::Luminance => order.sort_by_key(|&idx| self.luma(idx)),
+            PaletteSort::Usage => {
+                order.sort_by_key(|&idx| std::cmp::Reverse(usage.get(idx).copied()))
+            }
+        }
+
+        order
+    }
+
+    /// Get the value at the given index as HSLA.
+    fn hsla(&self, idx: usize) -> (f64, f64, f64, f64) {
+        let [r, g, b, a] = self.rgba(idx);
+        util::rgba8_to_hsla(r, g, b, a)
+    }
+
+    /// Get the perceived brightness of the value at the given index.
+    fn luma(&self, idx: usize) -> u32 {
+        let [r, g, b, _] = self.rgba(idx);
+        299 * r as u32 + 587 * g as u32 + 114 * b as u32
+    }
+
+    /// Get the value at the given index as RGBA8 bytes.
+    pub fn rgba(&self, idx: usize) -> [u8; 4] {
+        self.values[idx].to_be_bytes()
+    }
+
+    /// Find the index of the color closest to the given one, ignoring alpha. The index
+    /// to skip (if any) is typically reserved for transparency.
+    pub fn nearest(&self, rgba: [u8; 4], skip: Option<usize>) -> usize {
+        (0..self.values.len())
+            .filter(|idx| Some(*idx) != skip)
+            .min_by_key(|idx| distance(self.rgba(*idx), rgba))
+            .unwrap_or(0)
+    }
+
+    /// Get the RGBA8 bytes for a pixel, which is either a palette index (one byte) or
+    /// already RGBA8 (four bytes). The transparent index, and anything past the end of the
+    /// palette, comes out fully transparent.
+    pub fn to_rgba(&self, pixel: &[u8]) -> [u8; 4] {
+        match *pixel {
+            [idx] => match to_entry(idx) {
+                Some(entry) if entry < self.values.len() => self.rgba(entry),
+                _ => [0, 0, 0, 0],
+            },
+            _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        }
+    }
+
+    /// Get the palette index for the given RGBA8 bytes. Anything that's mostly transparent
+    /// gets the transparent index, and everything else gets the closest color that an
+    /// index can reach.
+    pub fn to_index(&self, rgba: [u8; 4]) -> u8 {
+        if rgba[3] < 128 {
+            return constants::TRANSPARENT_INDEX;
+        }
+
+        (0..self.values.len().min(constants::MAX_INDEXED_COLORS))
+            .min_by_key(|&entry| distance(self.rgba(entry), rgba))
+            .map_or(constants::TRANSPARENT_INDEX, from_entry)
+    }
+
+    /// Convert indexed pixels to RGBA8. Pixels that are already RGBA8 are left as they are.
+    pub fn resolve(&self, pixels: &PixelState) -> PixelState {
+        let header = pixels.header();
+        if header.bytes_per_pixel() == 4 {
+            return pixels.clone();
+        }
+
+        let bytes = pixels
+            .bytes()
+            .iter()
+            .flat_map(|&idx| self.to_rgba(&[idx]))
+            .collect();
+        let header = PixelHeader::new(header.width() as u32, header.height() as u32, 8, 4);
+        PixelState::new(header, bytes)
+    }
+
+    /// Convert RGBA8 pixels to indices into this palette. Pixels that are already indexed
+    /// are left as they are.
+    pub fn quantize(&self, pixels: &PixelState) -> PixelState {
+        let header = pixels.header();
+        if header.bytes_per_pixel() == 1 {
+            return pixels.clone();
+        }
+
+        let bytes = pixels
+            .bytes()
+            .chunks_exact(4)
+            .map(|p| self.to_index([p[0], p[1], p[2], p[3]]))
+            .collect();
+        let header = PixelHeader::new(header.width() as u32, header.height() as u32, 8, 1);
+        PixelState::new(header, bytes)
+    }
+
+    /// Convert RGBA8 pixels back to indices, after they were resolved from the given indexed
+    /// pixels and then changed. Pixels that still have the color they started with keep
+    /// their old index, so colors that appear more than once in the palette stay apart.
+    pub fn requantize(&self, old: &PixelState, pixels: &PixelState) -> PixelState {
+        let (header, size) = (old.header(), pixels.header());
+        if header.width() != size.width() || header.height() != size.height() {
+            return self.quantize(pixels);
+        }
+
+        let bytes = old
+            .bytes()
+            .iter()
+            .zip(pixels.bytes().chunks_exact(4))
+            .map(|(&idx, p)| {
+                let rgba = [p[0], p[1], p[2], p[3]];
+                if self.to_rgba(&[idx]) == rgba {
+                    idx
+                } else {
+                    self.to_index(rgba)
+                }
+            })
+            .collect();
+        PixelState::new(header.clone(), bytes)
+    }
+}
+
+/// PICO-8.
+const PICO_8: [u32; 16] = [
+    0x000000ff, 0x1d2b53ff, 0x7e2553ff, 0x008751ff, 0xab5236ff, 0x5f574fff, 0xc2c3c7ff, 0xfff1e8ff,
+    0xff004dff, 0xffa300ff, 0xffec27ff, 0x00e436ff, 0x29adffff, 0x83769cff, 0xff77a8ff, 0xffccaaff,
+];
+
+/// NES (2C02), in hardware order, so that indices match the palette numbers. The unused
+/// blacks in the last columns are kept to hold the order.
+const NES: [u32; 64] = [
+    0x7c7c7cff, 0x0000fcff, 0x0000bcff, 0x4428bcff, 0x940084ff, 0xa80020ff, 0xa81000ff, 0x881400ff,
+    0x503000ff, 0x007800ff, 0x006800ff, 0x005800ff, 0x004058ff, 0x000000ff, 0x000000ff, 0x000000ff,
+    0xbcbcbcff, 0x0078f8ff, 0x0058f8ff, 0x6844fcff, 0xd800ccff, 0xe40058ff, 0xf83800ff, 0xe45c10ff,
+    0xac7c00ff, 0x00b800ff, 0x00a800ff, 0x00a844ff, 0x008888ff, 0x000000ff, 0x000000ff, 0x000000ff,
+    0xf8f8f8ff, 0x3cbcfcff, 0x6888fcff, 0x9878f8ff, 0xf878f8ff, 0xf85898ff, 0xf87858ff, 0xfca044ff,
+    0xf8b800ff, 0xb8f818ff, 0x58d854ff, 0x58f898ff, 0x00e8d8ff, 0x787878ff, 0x000000ff, 0x000000ff,
+    0xfcfcfcff, 0xa4e4fcff, 0xb8b8f8ff, 0xd8b8f8ff, 0xf8b8f8ff, 0xf8a4c0ff, 0xf0d0b0ff, 0xfce0a8ff,
+    0xf8d878ff, 0xd8f878ff, 0xb8f8b8ff, 0xb8f8d8ff, 0x00fcfcff, 0xf8d8f8ff, 0x000000ff, 0x000000ff,
+];
+
+/// Game Boy (DMG), lightest first, matching the shades that the hardware numbers 0 to 3.
+const GAME_BOY: [u32; 4] = [0x9bbc0fff, 0x8bac0fff, 0x306230ff, 0x0f380fff];
+
+/// Commodore 64, as measured by Pepto.
+const C64: [u32; 16] = [
+    0x000000ff, 0xffffffff, 0x68372bff, 0x70a4b2ff, 0x6f3d86ff, 0x588d43ff, 0x352879ff, 0xb8c76fff,
+    0x6f4f25ff, 0x433900ff, 0x9a6759ff, 0x444444ff, 0x6c6c6cff, 0x9ad284ff, 0x6c5eb5ff, 0x959595ff,
+];
+
+/// CGA, with all 16 RGBI colors.
+const CGA: [u32; 16] = [
+    0x000000ff, 0x0000aaff, 0x00aa00ff, 0x00aaaaff, 0xaa0000ff, 0xaa00aaff, 0xaa5500ff, 0xaaaaaaff,
+    0x555555ff, 0x5555ffff, 0x55ff55ff, 0x55ffffff, 0xff5555ff, 0xff55ffff, 0xffff55ff, 0xffffffff,
+];
+
+/// EGA, with all 64 colors that it can pick its 16 from, in hardware order.
+const EGA: [u32; 64] = [
+    0x000000ff, 0x0000aaff, 0x00aa00ff, 0x00aaaaff, 0xaa0000ff, 0xaa00aaff, 0xaaaa00ff, 0xaaaaaaff,
+    0x000055ff, 0x0000ffff, 0x00aa55ff, 0x00aaffff, 0xaa0055ff, 0xaa00ffff, 0xaaaa55ff, 0xaaaaffff,
+    0x005500ff, 0x0055aaff, 0x00ff00ff, 0x00ffaaff, 0xaa5500ff, 0xaa55aaff, 0xaaff00ff, 0xaaffaaff,
+    0x005555ff, 0x0055ffff, 0x00ff55ff, 0x00ffffff, 0xaa5555ff, 0xaa55ffff, 0xaaff55ff, 0xaaffffff,
+    0x550000ff, 0x5500aaff, 0x55aa00ff, 0x55aaaaff, 0xff0000ff, 0xff00aaff, 0xffaa00ff, 0xffaaaaff,
+    0x550055ff, 0x5500ffff, 0x55aa55ff, 0x55aaffff, 0xff0055ff, 0xff00ffff, 0xffaa55ff, 0xffaaffff,
+    0x555500ff, 0x5555aaff, 0x55ff00ff, 0x55ffaaff, 0xff5500ff, 0xff55aaff, 0xffff00ff, 0xffffaaff,
+    0x555555ff, 0x5555ffff, 0x55ff55ff, 0x55ffffff, 0xff5555ff, 0xff55ffff, 0xffff55ff, 0xffffffff,
+];
+
+/// ZX Spectrum, with the normal colors followed by the bright ones. Black has no bright
+/// version, so it appears twice.
+const ZX_SPECTRUM: [u32; 16] = [
+    0x000000ff, 0x0000d7ff, 0xd70000ff, 0xd700d7ff, 0x00d700ff, 0x00d7d7ff, 0xd7d700ff, 0xd7d7d7ff,
+    0x000000ff, 0x0000ffff, 0xff0000ff, 0xff00ffff, 0x00ff00ff, 0x00ffffff, 0xffff00ff, 0xffffffff,
+];
+
+/// Sweetie-16, by GrafxKid.
+const SWEETIE_16: [u32; 16] = [
+    0x1a1c2cff, 0x5d275dff, 0xb13e53ff, 0xef7d57ff, 0xffcd75ff, 0xa7f070ff, 0x38b764ff, 0x257179ff,
+    0x29366fff, 0x3b5dc9ff, 0x41a6f6ff, 0x73eff7ff, 0xf4f4f4ff, 0x94b0c2ff, 0x566c86ff, 0x333c57ff,
+];
+
+/// Get the palette entry that an index refers to. The transparent index doesn't refer to
+/// one; it comes before the palette, so that every color in the palette can be drawn.
+pub fn to_entry(idx: u8) -> Option<usize> {
+    match idx {
+        constants::TRANSPARENT_INDEX => None,
+        _ => Some(idx as usize - 1),
+    }
+}
+
+/// Get the index that refers to the given palette entry, which must be one of the first
+/// `MAX_INDEXED_COLORS`.
+pub fn from_entry(entry: usize) -> u8 {
+    debug_assert!(entry < constants::MAX_INDEXED_COLORS);
+    (entry + 1) as u8
+}
+
+/// Squared distance between two colors, ignoring alpha.
+fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    (0..3)
+        .map(|i| {
+            let d = a[i] as i32 - b[i] as i32;
+            (d * d) as u32
+        })
+        .sum()
+}
+
+impl Default for PaletteState {
+    fn default() -> Self {
+        Self::from_bytes(include_bytes!("../assets/vga.pal"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> PaletteState {
+        PaletteState::new(vec![0x000000ff, 0xff0000ff, 0x00ff00ff, 0x0000ffff])
+    }
+
+    #[test]
+    fn it_quantizes_and_resolves() {
+        let header = PixelHeader::new(3, 1, 8, 4);
+        let bytes = vec![250, 10, 0, 255, 0, 0, 0, 0, 0, 0, 200, 255];
+        let pixels = palette().quantize(&PixelState::new(header, bytes));
+        assert_eq!(1, pixels.header().bytes_per_pixel());
+        assert_eq!(vec![2, constants::TRANSPARENT_INDEX, 4], *pixels.bytes());
+
+        let pixels = palette().resolve(&pixels);
+        assert_eq!(4, pixels.header().bytes_per_pixel());
+        assert_eq!(
+            vec![255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 255, 255],
+            *pixels.bytes()
+        );
+    }
+
+    #[test]
+    fn it_creates_presets() {
+        let pico_8 = PaletteState::from_preset(PalettePreset::Pico8);
+        assert_eq!(16, pico_8.len());
+        assert_eq!([0x1d, 0x2b, 0x53, 0xff], pico_8.rgba(1));
+        assert_eq!(4, PaletteState::from_preset(PalettePreset::GameBoy).len());
+        assert_eq!(64, PaletteState::from_preset(PalettePreset::Ega).len());
+        assert_eq!(256, PaletteState::from_preset(PalettePreset::Vga).len());
+    }
+
+    #[test]
+    fn it_round_trips_presets() {
+        let presets = [
+            PalettePreset::Vga,
+            PalettePreset::Pico8,
+            PalettePreset::Nes,
+            PalettePreset::GameBoy,
+            PalettePreset::C64,
+            PalettePreset::Cga,
+            PalettePreset::Ega,
+            PalettePreset::ZxSpectrum,
+            PalettePreset::Sweetie16,
+        ];
+
+        for &preset in presets.iter() {
+            let palette = PaletteState::from_preset(preset);
+            let reachable = palette.len().min(constants::MAX_INDEXED_COLORS);
+            for entry in 0..reachable {
+                let rgba = palette.rgba(entry);
+                assert_eq!(rgba, palette.to_rgba(&[palette.to_index(rgba)]));
+            }
+        }
+    }
+
+    #[test]
+    fn it_edits_values() {
+        let mut palette = palette();
+        palette.set_value(1, 0x123456ff);
+        assert!(palette.push(0xffffffff));
+        assert_eq!([0x12, 0x34, 0x56, 0xff], palette.rgba(1));
+        assert_eq!(5, palette.len());
+
+        let reordered = palette.reorder(&[4, 0]);
+        assert_eq!(&[0xffffffff, 0x000000ff], reordered.values());
+
+        let mut full = PaletteState::new(vec![0; constants::MAX_PALETTE_COLORS]);
+        assert!(!full.push(0xffffffff));
+    }
+
+    #[test]
+    fn it_sorts() {
+        let palette = PaletteState::new(vec![
+            0xffffffff, 0x0000ffff, 0x808080ff, 0xff0000ff, 0x00ff00ff, 0x000000ff,
+        ]);
+        let hue = palette.sort_order(PaletteSort::Hue, &[]);
+        assert_eq!(vec![5, 2, 0, 3, 4, 1], hue);
+
+        let luminance = palette.sort_order(PaletteSort::Luminance, &[]);
+        assert_eq!(vec![5, 1, 3, 2, 4, 0], luminance);
+
+        let usage = palette.sort_order(PaletteSort::Usage, &[0, 1, 5, 0, 5]);
+        assert_eq!(vec![2, 4, 1, 0, 3, 5], usage);
+    }
+
+    #[test]
+    fn it_only_indexes_colors_it_can_reach() {
+        let mut values = vec![0xff0000ff; constants::MAX_INDEXED_COLORS];
+        assert!(PaletteState::new(values.clone()).indexable());
+
+        // A repeat is fine, because the color it repeats has an index.
+        values.push(0xff0000ff);
+        assert!(PaletteState::new(values.clone()).indexable());
+        assert!(PaletteState::default().indexable());
+
+        values[constants::MAX_INDEXED_COLORS] = 0x00ff00ff;
+        assert!(!PaletteState::new(values).indexable());
+    }
+
+    #[test]
+    fn it_requantizes_only_what_changed() {
+        // Two blacks, and a red that's changed to blue.
+        let palette = PaletteState::new(vec![0x000000ff, 0xff0000ff, 0x000000ff, 0x0000ffff]);
+        let old = PixelState::new(PixelHeader::new(3, 1, 8, 1), vec![3, 2, 1]);
+        let mut bytes = palette.resolve(&old).bytes().to_vec();
+        bytes[4..8].copy_from_slice(&[0, 0, 255, 255]);
+
+        let header = PixelHeader::new(3, 1, 8, 4);
+        let pixels = palette.requantize(&old, &PixelState::new(header, bytes));
+        assert_eq!(vec![3, 4, 1], *pixels.bytes());
+    }
+
+    #[test]
+    fn it_round_trips_every_color() {
+        let header = PixelHeader::new(4, 1, 8, 4);
+        let bytes = palette()
+            .values()
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let pixels = PixelState::new(header, bytes);
+
+        let indexed = palette().quantize(&pixels);
+        assert!(!indexed.bytes().contains(&constants::TRANSPARENT_INDEX));
+        assert_eq!(pixels.bytes(), palette().resolve(&indexed).bytes());
+    }
+
+    #[test]
+    fn it_resolves_out_of_range_as_transparent() {
+        assert_eq!([0, 0, 0, 0], palette().to_rgba(&[9]));
+        assert_eq!(
+            [0, 0, 0, 0],
+            palette().to_rgba(&[constants::TRANSPARENT_INDEX])
+        );
+        assert_eq!([1, 2, 3, 4], palette().to_rgba(&[1, 2, 3, 4]));
+    }
+}
@@ -15,6 +15,7 @@
 use std::sync::Arc;
 
 use crate::common::constants;
+use crate::model::mask::Mask;
 use crate::model::types::PixelBytes;
 
 /// Generic pixel header.
@@ -46,6 +47,11 @@ impl PixelHeader {
         self.height as usize
     }
 
+    /// Get the bit depth.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
     /// Get the number of bytes per pixel.
     pub fn bytes_per_pixel(&self) -> u8 {
         self.bytes_per_pixel
@@ -73,6 +79,7 @@ pub struct PixelEnv {
     color: druid::Color,
     pos: druid::Point,
     bounds: druid::Rect,
+    mask: Option<Mask>,
     param: f64,
 }
 
@@ -82,6 +89,7 @@ impl PixelEnv {
             color,
             pos,
             bounds,
+            mask: None,
             param,
         }
     }
@@ -101,6 +109,21 @@ impl PixelEnv {
         self.bounds
     }
 
+    /// Set the selection mask, if the selection isn't rectangular.
+    pub fn set_mask(&mut self, mask: Option<Mask>) {
+        self.mask = mask;
+    }
+
+    /// Is the given point within the bounds, and selected?
+    pub fn contains(&self, p: druid::Point) -> bool {
+        let selected = match &self.mask {
+            Some(mask) => mask.contains(p),
+            None => true,
+        };
+
+        self.bounds.contains(p) && selected
+    }
+
     /// Get the parameter that was passed.
     pub fn param(&self) -> f64 {
         self.param
@@ -108,7 +131,9 @@ impl PixelEnv {
 }
 
 /// Pixel storage. Each value is stored as four contiguous bytes representing RGBA,
-/// respectively. We hold the values in an ARC, to avoid copying them.
+/// respectively, or as a single byte palette index for indexed documents. Reading and
+/// writing colors only works for RGBA; indexed pixels have to be resolved through the
+/// palette first. We hold the values in an ARC, to avoid copying them.
 #[derive(Clone, druid::Data)]
 pub struct PixelState {
     header: PixelHeader,
@@ -130,6 +155,18 @@ impl PixelState {
         }
     }
 
+    /// Create new, fully transparent pixel state with the given header.
+    pub fn empty(header: PixelHeader) -> Self {
+        let dim = header.width() * header.height();
+        let size = dim * header.bytes_per_pixel as usize;
+
+        Self {
+            header,
+            dirty: false,
+            bytes: Arc::new(vec![0; size]),
+        }
+    }
+
     pub fn clone_area(&self, area: druid::Rect) -> Self {
         let header = PixelHeader::new(
             area.width() as u32,
@@ -145,6 +182,25 @@ impl PixelState {
         }
     }
 
+    /// Find the smallest area that holds all of the non-transparent pixels. Returns None
+    /// if every pixel is transparent.
+    pub fn opaque_bounds(&self) -> Option<druid::Rect> {
+        let width = self.header.width();
+        let bpp = self.header.bytes_per_pixel as usize;
+        let mut bounds: Option<druid::Rect> = None;
+
+        for (idx, pixel) in self.bytes.chunks_exact(bpp).enumerate() {
+            if pixel[3] != 0 {
+                let x = (idx % width + 1) as f64;
+                let y = (idx / width + 1) as f64;
+                let rect = druid::Rect::new(x, y, x + 1.0, y + 1.0);
+                bounds = Some(bounds.map_or(rect, |bounds| bounds.union(rect)));
+            }
+        }
+
+        bounds
+    }
+
     /// Are pixels dirty?
     pub fn dirty(&self) -> bool {
         self.dirty
@@ -196,6 +252,23 @@ impl PixelState {
         self.xy_to_idx(x, y) * self.header.bytes_per_pixel as usize
     }
 
+    /// Read the raw bytes of a point in pixel storage: four for RGBA, or one for a palette
+    /// index. Will panic if outside bounds.
+    pub fn read_raw(&self, p: druid::Point) -> &[u8] {
+        let byte_idx = self.xy_to_byte_idx(p.x as usize, p.y as usize);
+        &self.bytes[byte_idx..byte_idx + self.header.bytes_per_pixel as usize]
+    }
+
+    /// Write the raw bytes of a point in pixel storage. Will panic if outside bounds.
+    pub fn write_raw(&mut self, p: druid::Point, raw: &[u8]) {
+        let byte_idx = self.xy_to_byte_idx(p.x as usize, p.y as usize);
+
+        let pixels = Arc::make_mut(&mut self.bytes);
+        pixels[byte_idx..byte_idx + raw.len()].copy_from_slice(raw);
+
+        self.dirty = true;
+    }
+
     /// Read from an xy point in pixel storage. Will panic if outside bounds.
     pub fn read_xy_unchecked(&self, x: usize, y: usize) -> druid::Color {
         let byte_idx = self.xy_to_byte_idx(x, y);
@@ -229,12 +302,14 @@ impl PixelState {
         let len = dim * self.header.bytes_per_pixel as usize;
         let mut dst_bytes = Vec::with_capacity(len);
 
+        let bpp = self.header.bytes_per_pixel as usize;
+
         for y in area.y0 as usize..area.y1 as usize {
             for x in area.x0 as usize..area.x1 as usize {
                 let idx = (y - 1) * self.header.width() + (x - 1);
-                let src_idx = idx * self.header.bytes_per_pixel as usize;
+                let src_idx = idx * bpp;
 
-                dst_bytes.extend_from_slice(&self.bytes[src_idx..src_idx + 4]);
+                dst_bytes.extend_from_slice(&self.bytes[src_idx..src_idx + bpp]);
             }
         }
 
@@ -255,8 +330,35 @@ impl PixelState {
         self.dirty = true;
     }
 
+    /// Write an area of storage, but only where the mask is selected. The source bytes
+    /// cover the whole area of the mask.
+    pub fn write_mask(&mut self, mask: &Mask, src_bytes: &[u8]) {
+        let area = mask.area();
+        let bpp = self.header.bytes_per_pixel as usize;
+        let dst_bytes = Arc::make_mut(&mut self.bytes);
+
+        let mut src_idx = 0;
+
+        for y in area.y0 as usize..area.y1 as usize + 1 {
+            for x in area.x0 as usize..area.x1 as usize + 1 {
+                if mask.contains_xy(x, y) {
+                    let idx = (y - 1) * self.header.width() + (x - 1);
+                    let dst_idx = idx * bpp;
+
+                    dst_bytes[dst_idx..dst_idx + bpp]
+                        .copy_from_slice(&src_bytes[src_idx..src_idx + bpp]);
+                }
+
+                src_idx += bpp;
+            }
+        }
+
+        self.dirty = true;
+    }
+
     /// Write an area of storage.
     pub fn write_area(&mut self, area: druid::Rect, src_bytes: &[u8]) {
+        let bpp = self.header.bytes_per_pixel as usize;
         let dst_bytes = Arc::make_mut(&mut self.bytes);
 
         let mut src_idx = 0;
@@ -264,14 +366,12 @@ impl PixelState {
         for y in area.y0 as usize..area.y1 as usize {
             for x in area.x0 as usize..area.x1 as usize {
                 let idx = (y - 1) * self.header.width() + (x - 1);
-                let dst_idx = idx * self.header.bytes_per_pixel as usize;
+                let dst_idx = idx * bpp;
 
-                dst_bytes[dst_idx] = src_bytes[src_idx];
-                dst_bytes[dst_idx + 1] = src_bytes[src_idx + 1];
-                dst_bytes[dst_idx + 2] = src_bytes[src_idx + 2];
-                dst_bytes[dst_idx + 3] = src_bytes[src_idx + 3];
+                dst_bytes[dst_idx..dst_idx + bpp]
+                    .copy_from_slice(&src_bytes[src_idx..src_idx + bpp]);
 
-                src_idx += 4;
+                src_idx += bpp;
             }
         }
 
@@ -281,15 +381,6 @@ impl PixelState {
 
 impl Default for PixelState {
     fn default() -> Self {
-        let header: PixelHeader = Default::default();
-
-        let dim = header.width() * header.height();
-        let size = dim * header.bytes_per_pixel as usize;
-
-        Self {
-            header,
-            dirty: false,
-            bytes: Arc::new(vec![0; size]),
-        }
+        Self::empty(Default::default())
     }
 }
@@ -0,0 +1,163 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// How frames are laid out in a sprite sheet.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum SheetLayout {
+    /// Every frame gets a cell of the same size, in rows and columns.
+    Grid,
+    /// Frames are trimmed to their opaque pixels, and packed as tightly as possible.
+    Packed,
+}
+
+/// Flavour of JSON atlas written next to a sprite sheet.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum AtlasFormat {
+    Aseprite,
+    TexturePacker,
+}
+
+/// Sprite sheet export options.
+#[derive(Clone, druid::Data)]
+pub struct SheetOptions {
+    layout: SheetLayout,
+    format: AtlasFormat,
+    padding: usize,
+    extrude: usize,
+    tagged_only: bool,
+}
+
+impl SheetOptions {
+    pub fn layout(&self) -> SheetLayout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: SheetLayout) {
+        self.layout = layout;
+    }
+
+    pub fn format(&self) -> AtlasFormat {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: AtlasFormat) {
+        self.format = format;
+    }
+
+    /// Get the number of empty pixels between sprites.
+    pub fn padding(&self) -> usize {
+        self.padding
+    }
+
+    pub fn set_padding(&mut self, padding: usize) {
+        self.padding = padding;
+    }
+
+    /// Get the number of pixels each sprite's edges are repeated outwards. This stops
+    /// neighbouring sprites bleeding in when the sheet is filtered.
+    pub fn extrude(&self) -> usize {
+        self.extrude
+    }
+
+    pub fn set_extrude(&mut self, extrude: usize) {
+        self.extrude = extrude;
+    }
+
+    /// Only export frames that have a tag?
+    pub fn tagged_only(&self) -> bool {
+        self.tagged_only
+    }
+
+    pub fn flip_tagged_only(&mut self) {
+        self.tagged_only = !self.tagged_only;
+    }
+}
+
+impl Default for SheetOptions {
+    fn default() -> Self {
+        Self {
+            layout: SheetLayout::Grid,
+            format: AtlasFormat::Aseprite,
+            padding: 0,
+            extrude: 0,
+            tagged_only: false,
+        }
+    }
+}
+
+/// Sprite sheet import options. Cells are laid out in rows and columns, with a margin around
+/// the edge of the sheet, and spacing between cells.
+#[derive(Clone, druid::Data)]
+pub struct SliceOptions {
+    path: Option<String>,
+    cell_width: usize,
+    cell_height: usize,
+    margin: usize,
+    spacing: usize,
+}
+
+impl SliceOptions {
+    /// Get the path of the sheet being sliced.
+    pub fn path(&self) -> Option<String> {
+        self.path.clone()
+    }
+
+    pub fn set_path(&mut self, path: String) {
+        self.path = Some(path);
+    }
+
+    pub fn cell_width(&self) -> usize {
+        self.cell_width
+    }
+
+    pub fn set_cell_width(&mut self, cell_width: usize) {
+        self.cell_width = cell_width.max(1);
+    }
+
+    pub fn cell_height(&self) -> usize {
+        self.cell_height
+    }
+
+    pub fn set_cell_height(&mut self, cell_height: usize) {
+        self.cell_height = cell_height.max(1);
+    }
+
+    pub fn margin(&self) -> usize {
+        self.margin
+    }
+
+    pub fn set_margin(&mut self, margin: usize) {
+        self.margin = margin;
+    }
+
+    pub fn spacing(&self) -> usize {
+        self.spacing
+    }
+
+    pub fn set_spacing(&mut self, spacing: usize) {
+        self.spacing = spacing;
+    }
+}
+
+impl Default for SliceOptions {
+    fn default() -> Self {
+        Self {
+            path: None,
+            cell_width: 16,
+            cell_height: 16,
+            margin: 0,
+            spacing: 0,
+        }
+    }
+}
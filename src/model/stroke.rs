@@ -0,0 +1,148 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::util::shapes;
+
+/// A brush stroke. This is the path of pixels painted since the mouse went down, along
+/// with the colors they had beforehand. Mouse samples can be far apart, so the path is
+/// joined up with lines. In pixel perfect mode, the path drops the pixel in the corner of
+/// any L-shaped step, which keeps lines one pixel wide.
+#[derive(Clone, druid::Data, Default)]
+pub struct Stroke {
+    pixel_perfect: bool,
+    points: Arc<Vec<(druid::Point, druid::Color)>>,
+}
+
+impl Stroke {
+    pub fn new(pixel_perfect: bool) -> Self {
+        Self {
+            pixel_perfect,
+            points: Default::default(),
+        }
+    }
+
+    /// Get the pixels along the path, in order.
+    pub fn points(&self) -> impl Iterator<Item = druid::Point> + '_ {
+        self.points.iter().map(|&(p, _)| p)
+    }
+
+    /// Extend the stroke to the given point. The reader gives the current color of a pixel,
+    /// so that it can be put back if the pixel is dropped later on. Returns the pixels to
+    /// paint, and the pixels to put back the way they were.
+    pub fn extend<F>(
+        &mut self,
+        p: druid::Point,
+        read: F,
+    ) -> (Vec<druid::Point>, Vec<(druid::Point, druid::Color)>)
+    where
+        F: Fn(druid::Point) -> druid::Color,
+    {
+        let segment = match self.points.last() {
+            Some(&(last, _)) if last == p => return (Vec::new(), Vec::new()),
+            Some(&(last, _)) => shapes::line(last, p).into_iter().skip(1).collect(),
+            None => vec![p],
+        };
+
+        let points = Arc::make_mut(&mut self.points);
+        let mut paint: Vec<druid::Point> = Vec::new();
+        let mut restore = Vec::new();
+
+        for q in segment {
+            let len = points.len();
+            if self.pixel_perfect && len > 1 && is_corner(points[len - 2].0, points[len - 1].0, q) {
+                let (corner, color) = points.pop().unwrap();
+                match paint.iter().position(|&p| p == corner) {
+                    Some(idx) => {
+                        paint.remove(idx);
+                    }
+
+                    // Only put it back if the stroke doesn't pass through it elsewhere.
+                    None if !points.iter().any(|&(p, _)| p == corner) => {
+                        restore.push((corner, color))
+                    }
+                    None => {}
+                }
+            }
+
+            points.push((q, read(q)));
+            paint.push(q);
+        }
+
+        (paint, restore)
+    }
+}
+
+/// Is b the corner of an L-shaped step from a to c?
+fn is_corner(a: druid::Point, b: druid::Point, c: druid::Point) -> bool {
+    let turns = (a.x == b.x && b.y == c.y) || (a.y == b.y && b.x == c.x);
+    turns && a.x != c.x && a.y != c.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f64, y: f64) -> druid::Point {
+        druid::Point::new(x, y)
+    }
+
+    fn transparent(_p: druid::Point) -> druid::Color {
+        druid::Color::rgba8(0, 0, 0, 0)
+    }
+
+    #[test]
+    fn it_fills_gaps() {
+        let mut stroke = Stroke::new(false);
+        assert_eq!(vec![p(1.0, 1.0)], stroke.extend(p(1.0, 1.0), transparent).0);
+
+        let (paint, restore) = stroke.extend(p(4.0, 1.0), transparent);
+        assert_eq!(vec![p(2.0, 1.0), p(3.0, 1.0), p(4.0, 1.0)], paint);
+        assert!(restore.is_empty());
+
+        assert!(stroke.extend(p(4.0, 1.0), transparent).0.is_empty());
+        assert_eq!(4, stroke.points().count());
+    }
+
+    #[test]
+    fn it_keeps_corners() {
+        let mut stroke = Stroke::new(false);
+        stroke.extend(p(1.0, 1.0), transparent);
+        stroke.extend(p(2.0, 1.0), transparent);
+        stroke.extend(p(2.0, 2.0), transparent);
+        assert_eq!(3, stroke.points().count());
+    }
+
+    #[test]
+    fn it_drops_corners_when_pixel_perfect() {
+        let mut stroke = Stroke::new(true);
+        stroke.extend(p(1.0, 1.0), transparent);
+        stroke.extend(p(2.0, 1.0), |_| druid::Color::WHITE);
+
+        // The corner was painted last time, so it has to go back to how it was.
+        let (paint, restore) = stroke.extend(p(2.0, 2.0), transparent);
+        assert_eq!(vec![p(2.0, 2.0)], paint);
+        assert_eq!(vec![(p(2.0, 1.0), druid::Color::WHITE)], restore);
+        assert_eq!(
+            vec![p(1.0, 1.0), p(2.0, 2.0)],
+            stroke.points().collect::<Vec<_>>()
+        );
+
+        // Straight lines are left alone.
+        let (paint, restore) = stroke.extend(p(2.0, 4.0), transparent);
+        assert_eq!(vec![p(2.0, 3.0), p(2.0, 4.0)], paint);
+        assert!(restore.is_empty());
+    }
+}
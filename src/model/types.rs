@@ -17,15 +17,116 @@ use std::sync::Arc;
 /// Shared reference to pixel bytes. We use this type a lot.
 pub type PixelBytes = Arc<Vec<u8>>;
 
+/// Layer blend modes. These decide how a layer is combined with the layers beneath it.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Add,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+/// What a new image is filled with.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum Background {
+    Transparent,
+    White,
+    Black,
+    Brush,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::Transparent
+    }
+}
+
+/// How pixels are stored. RGBA pixels hold their own color, while indexed pixels refer to
+/// a color in the document's palette.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum ColorMode {
+    Rgba,
+    Indexed,
+}
+
+impl Default for ColorMode {
+    fn default() -> Self {
+        Self::Rgba
+    }
+}
+
+/// Ways of flipping or rotating the image. Rotations are clockwise.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum Orientation {
+    FlipHorizontal,
+    FlipVertical,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// Built-in palettes. Apart from VGA, these are the colors of classic (and fantasy)
+/// hardware.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum PalettePreset {
+    Vga,
+    Pico8,
+    Nes,
+    GameBoy,
+    C64,
+    Cga,
+    Ega,
+    ZxSpectrum,
+    Sweetie16,
+}
+
+/// Ways of sorting the palette.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum PaletteSort {
+    /// Grays first, dark to light, then colors around the color wheel.
+    Hue,
+    /// Dark to light.
+    Luminance,
+    /// Most used in the document first.
+    Usage,
+}
+
+/// How a new selection is combined with the existing one.
+#[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+    Intersect,
+}
+
+impl Default for SelectionMode {
+    fn default() -> Self {
+        Self::Replace
+    }
+}
+
 /// Supported tool types.
 #[derive(Clone, Copy, druid::Data, Debug, PartialEq)]
 pub enum ToolType {
     Dropper,
+    Ellipse,
     Eraser,
     Fill,
+    Lasso,
+    Line,
+    MagicWand,
     Marquee,
     Move,
     Paint,
+    PolygonLasso,
+    Rectangle,
 }
 
 #[derive(Clone, Copy, druid::Data, PartialEq)]
@@ -44,6 +145,12 @@ pub enum WindowState {
     UnsavedSave,
 
     OpenFailed,
+
+    ColorPicker,
+    FrameTag,
+    NewImage,
+    PaletteColor,
+    SheetImport,
 }
 
 impl Default for WindowState {
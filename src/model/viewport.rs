@@ -0,0 +1,249 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::constants;
+use crate::model::pixels::PixelHeader;
+
+/// How the canvas shows the image. The zoom is the size of each pixel on screen, and the
+/// offset is where the top-left pixel ends up. Both are in the canvas's own coordinates.
+#[derive(Clone, druid::Data)]
+pub struct Viewport {
+    zoom: usize,
+    offset: druid::Vec2,
+}
+
+impl Viewport {
+    /// Get the zoom, i.e. the size of each pixel on screen.
+    pub fn zoom(&self) -> usize {
+        self.zoom
+    }
+
+    /// Get the offset of the top-left pixel.
+    pub fn offset(&self) -> druid::Vec2 {
+        self.offset
+    }
+
+    /// Translate from screen coordinates to canvas coordinates. The result may be outside
+    /// the image; it's up to the caller to check.
+    pub fn to_canvas(&self, pos: druid::Point) -> druid::Point {
+        let zoom = self.zoom as f64;
+        druid::Point::new(
+            ((pos.x - self.offset.x) / zoom).floor() + 1.0,
+            ((pos.y - self.offset.y) / zoom).floor() + 1.0,
+        )
+    }
+
+    /// Translate from canvas coordinates to screen coordinates. This is the top-left corner
+    /// of the pixel on screen.
+    pub fn to_screen(&self, x: f64, y: f64) -> druid::Point {
+        let zoom = self.zoom as f64;
+        druid::Point::new(
+            self.offset.x + (x - 1.0) * zoom,
+            self.offset.y + (y - 1.0) * zoom,
+        )
+    }
+
+    /// Set the zoom, keeping whatever is under the anchor point in place. The zoom is clamped
+    /// to the supported levels.
+    pub fn set_zoom(&mut self, zoom: usize, anchor: druid::Point) {
+        let min = constants::ZOOM_LEVELS[0];
+        let max = constants::ZOOM_LEVELS[constants::ZOOM_LEVELS.len() - 1];
+        let zoom = zoom.max(min).min(max);
+
+        let scale = zoom as f64 / self.zoom as f64;
+        let anchor = anchor.to_vec2();
+        self.offset = (anchor - (anchor - self.offset) * scale).round();
+        self.zoom = zoom;
+    }
+
+    /// Zoom in to the next level, around the anchor point.
+    pub fn zoom_in(&mut self, anchor: druid::Point) {
+        if let Some(&zoom) = constants::ZOOM_LEVELS.iter().find(|&&z| z > self.zoom) {
+            self.set_zoom(zoom, anchor);
+        }
+    }
+
+    /// Zoom out to the previous level, around the anchor point.
+    pub fn zoom_out(&mut self, anchor: druid::Point) {
+        if let Some(&zoom) = constants::ZOOM_LEVELS
+            .iter()
+            .rev()
+            .find(|&&z| z < self.zoom)
+        {
+            self.set_zoom(zoom, anchor);
+        }
+    }
+
+    /// Move the image by the given amount.
+    pub fn pan(&mut self, delta: druid::Vec2) {
+        self.offset += delta;
+    }
+
+    /// Get the area of the canvas that shows the image. It's just big enough for the whole
+    /// image at the current zoom, but no smaller than `min` and no bigger than `max`.
+    pub fn view_rect(
+        &self,
+        header: &PixelHeader,
+        min: druid::Size,
+        max: druid::Rect,
+    ) -> druid::Rect {
+        let size = druid::Size::new(
+            ((header.width() * self.zoom) as f64)
+                .max(min.width)
+                .min(max.width()),
+            ((header.height() * self.zoom) as f64)
+                .max(min.height)
+                .min(max.height()),
+        );
+        druid::Rect::from_origin_size(max.origin(), size)
+    }
+
+    /// Pick the biggest zoom level that fits the whole image in the biggest view, and center
+    /// it. Images too big to fit at any level are shown at the smallest level.
+    pub fn fit(&mut self, header: &PixelHeader, min: druid::Size, max: druid::Rect) {
+        let width = header.width();
+        let height = header.height();
+
+        self.zoom = constants::ZOOM_LEVELS
+            .iter()
+            .rev()
+            .copied()
+            .find(|&z| (width * z) as f64 <= max.width() && (height * z) as f64 <= max.height())
+            .unwrap_or(constants::ZOOM_LEVELS[0]);
+
+        self.offset = self.centered(header, min, max);
+    }
+
+    /// Center the image along either axis that it fits on. There's nothing to pan to there,
+    /// and the view has shrunk to fit the image, so the image mustn't wander out of it.
+    pub fn settle(&mut self, header: &PixelHeader, min: druid::Size, max: druid::Rect) {
+        let centered = self.centered(header, min, max);
+        if (header.width() * self.zoom) as f64 <= max.width() {
+            self.offset.x = centered.x;
+        }
+        if (header.height() * self.zoom) as f64 <= max.height() {
+            self.offset.y = centered.y;
+        }
+    }
+
+    fn centered(&self, header: &PixelHeader, min: druid::Size, max: druid::Rect) -> druid::Vec2 {
+        let view = self.view_rect(header, min, max);
+        let size = druid::Vec2::new(
+            (header.width() * self.zoom) as f64,
+            (header.height() * self.zoom) as f64,
+        );
+        let origin = view.origin().to_vec2();
+        (origin + (view.size().to_vec2() - size) / 2.0).floor()
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            zoom: 1,
+            offset: druid::Vec2::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_coords() {
+        let mut viewport: Viewport = Default::default();
+        viewport.set_zoom(4, druid::Point::ZERO);
+        viewport.pan(druid::Vec2::new(10.0, 20.0));
+
+        assert_eq!(druid::Point::new(10.0, 20.0), viewport.to_screen(1.0, 1.0));
+        assert_eq!(
+            druid::Point::new(1.0, 1.0),
+            viewport.to_canvas(druid::Point::new(13.0, 23.0))
+        );
+        assert_eq!(
+            druid::Point::new(2.0, 3.0),
+            viewport.to_canvas(druid::Point::new(14.0, 28.0))
+        );
+        assert_eq!(
+            druid::Point::new(0.0, 0.0),
+            viewport.to_canvas(druid::Point::new(9.0, 19.0))
+        );
+    }
+
+    #[test]
+    fn it_zooms_around_anchor() {
+        let mut viewport: Viewport = Default::default();
+        let anchor = druid::Point::new(100.0, 50.0);
+
+        for zoom in constants::ZOOM_LEVELS.iter() {
+            let before = viewport.to_canvas(anchor);
+            viewport.set_zoom(*zoom, anchor);
+            assert_eq!(before, viewport.to_canvas(anchor));
+        }
+
+        viewport.zoom_in(anchor);
+        assert_eq!(64, viewport.zoom());
+        viewport.zoom_out(anchor);
+        assert_eq!(48, viewport.zoom());
+    }
+
+    #[test]
+    fn it_fits() {
+        let mut viewport: Viewport = Default::default();
+        let min = druid::Size::new(128.0, 128.0);
+        let max = druid::Rect::new(1.0, 1.0, 769.0, 769.0);
+
+        viewport.fit(&PixelHeader::new(48, 32, 8, 4), min, max);
+        assert_eq!(16, viewport.zoom());
+        assert_eq!(druid::Vec2::new(1.0, 1.0), viewport.offset());
+
+        viewport.fit(&PixelHeader::new(1024, 1024, 8, 4), min, max);
+        assert_eq!(1, viewport.zoom());
+        assert_eq!(druid::Vec2::new(-127.0, -127.0), viewport.offset());
+    }
+
+    #[test]
+    fn it_sizes_the_view_to_the_image() {
+        let mut viewport: Viewport = Default::default();
+        let min = druid::Size::new(128.0, 128.0);
+        let max = druid::Rect::new(1.0, 1.0, 769.0, 769.0);
+        let header = PixelHeader::new(48, 32, 8, 4);
+
+        viewport.fit(&header, min, max);
+        assert_eq!(
+            druid::Rect::new(1.0, 1.0, 769.0, 513.0),
+            viewport.view_rect(&header, min, max)
+        );
+
+        // Too small, so it's centered in the smallest view.
+        viewport.set_zoom(2, druid::Point::ZERO);
+        viewport.settle(&header, min, max);
+        assert_eq!(
+            druid::Rect::new(1.0, 1.0, 129.0, 129.0),
+            viewport.view_rect(&header, min, max)
+        );
+        assert_eq!(druid::Vec2::new(17.0, 33.0), viewport.offset());
+
+        // Too wide, so it can be panned sideways but not up and down.
+        viewport.set_zoom(24, druid::Point::ZERO);
+        viewport.pan(druid::Vec2::new(-100.0, -100.0));
+        viewport.settle(&header, min, max);
+        assert_eq!(
+            druid::Rect::new(1.0, 1.0, 769.0, 769.0),
+            viewport.view_rect(&header, min, max)
+        );
+        assert_eq!(druid::Vec2::new(104.0, 1.0), viewport.offset());
+    }
+}
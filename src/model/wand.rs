@@ -0,0 +1,59 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+/// Magic wand options. The tolerance is how far each channel of a pixel can stray from
+/// the color that was clicked, and still be selected.
+#[derive(Clone, druid::Data)]
+pub struct WandOptions {
+    tolerance: u8,
+    contiguous: bool,
+    diagonal: bool,
+}
+
+impl WandOptions {
+    pub fn tolerance(&self) -> u8 {
+        self.tolerance
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: u8) {
+        self.tolerance = tolerance;
+    }
+
+    /// Only select pixels that are connected to the one that was clicked?
+    pub fn contiguous(&self) -> bool {
+        self.contiguous
+    }
+
+    pub fn flip_contiguous(&mut self) {
+        self.contiguous = !self.contiguous;
+    }
+
+    /// Are diagonal neighbours connected? That's 8-connectivity, as opposed to 4.
+    pub fn diagonal(&self) -> bool {
+        self.diagonal
+    }
+
+    pub fn flip_diagonal(&mut self) {
+        self.diagonal = !self.diagonal;
+    }
+}
+
+impl Default for WandOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: 0,
+            contiguous: true,
+            diagonal: false,
+        }
+    }
+}
@@ -14,14 +14,18 @@
 
 use std::error::Error;
 
-/// Wrap various storage-specific errors, like PNG encoding/decoding errors.
+/// Wrap various storage-specific errors, like PNG and GIF encoding/decoding errors.
 #[derive(Debug)]
 pub enum StorageError {
     BadBitDepth,
     BadColorType,
     BadDimensions,
+    BadPaletteFormat,
     FailedToDecode,
     FailedToEncode,
+    FailedToDecodeGif,
+    FailedToEncodeGif,
+    FailedToDecodePalette,
     SystemError,
 }
 
@@ -31,8 +35,12 @@ impl std::fmt::Display for StorageError {
             Self::BadBitDepth => "Unsupported bit depth",
             Self::BadColorType => "Unsupported color type",
             Self::BadDimensions => "Unsuppored image dimensions",
+            Self::BadPaletteFormat => "Unsupported palette format",
             Self::FailedToDecode => "Failed to decode",
             Self::FailedToEncode => "Failed to encode",
+            Self::FailedToDecodeGif => "Failed to decode GIF",
+            Self::FailedToEncodeGif => "Failed to encode GIF",
+            Self::FailedToDecodePalette => "Failed to decode palette",
             Self::SystemError => "System error",
         };
 
@@ -0,0 +1,417 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::result::Result;
+
+use super::error::StorageError;
+use crate::common::constants;
+use crate::model::document::Document;
+use crate::model::document::Frame;
+use crate::model::palette::PaletteState;
+use crate::model::pixels::PixelHeader;
+use crate::model::pixels::PixelState;
+
+/// Write a document to the given path as an animated GIF.
+pub fn write_path(path_str: &str, doc: &Document) -> Result<(), StorageError> {
+    let path = Path::new(path_str);
+    let file = File::create(path)?;
+    let buf_writer = &mut BufWriter::new(file);
+
+    write(buf_writer, doc)
+}
+
+/// Write a document to the given writer as an animated GIF. Every frame is flattened, and
+/// then quantized to the document's palette. GIFs top out at 256 colors, so any palette
+/// entries past that are ignored.
+pub fn write<W: Write>(writer: W, doc: &Document) -> Result<(), StorageError> {
+    let header = doc.header();
+    let palette = doc.palette();
+    let num_colors = palette.len().min(256);
+    if num_colors == 0 {
+        return Err(StorageError::FailedToEncodeGif);
+    }
+
+    let images: Vec<PixelState> = (0..doc.frames().len())
+        .map(|idx| doc.flatten_frame(idx))
+        .collect();
+
+    // GIF transparency is all or nothing, so we need to reserve a palette entry for it.
+    let transparent = pick_transparent(&images, palette, num_colors, doc.transparent_index());
+    let num_entries = match transparent {
+        Some(idx) => num_colors.max(idx as usize + 1),
+        None => num_colors,
+    };
+
+    let global_palette: Vec<u8> = (0..num_entries)
+        .flat_map(|idx| match idx {
+            _ if idx < num_colors => {
+                let rgba = palette.rgba(idx);
+                vec![rgba[0], rgba[1], rgba[2]]
+            }
+            _ => vec![0, 0, 0],
+        })
+        .collect();
+
+    let mut encoder = gif::Encoder::new(
+        writer,
+        header.width() as u16,
+        header.height() as u16,
+        &global_palette,
+    )?;
+
+    match doc.loop_count() {
+        0 => encoder.set_repeat(gif::Repeat::Infinite)?,
+        1 => {}
+        n => encoder.set_repeat(gif::Repeat::Finite(n - 1))?,
+    }
+
+    // Nearest color lookups are slow, and pixel art doesn't have many distinct colors.
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+
+    for (frame, image) in doc.frames().iter().zip(&images) {
+        let indices: Vec<u8> = image
+            .bytes()
+            .chunks_exact(4)
+            .map(|p| {
+                let rgba = [p[0], p[1], p[2], p[3]];
+                match transparent {
+                    Some(idx) if rgba[3] < 128 => idx,
+                    _ => *lookup.entry(rgba).or_insert_with(|| {
+                        let skip = transparent.map(|idx| idx as usize);
+                        palette.nearest(rgba, skip) as u8
+                    }),
+                }
+            })
+            .collect();
+
+        let mut gif_frame = gif::Frame::from_indexed_pixels(
+            header.width() as u16,
+            header.height() as u16,
+            indices,
+            transparent,
+        );
+
+        // Frames are always written in full, so clear them away afterwards. Otherwise
+        // transparent pixels would show whatever came before.
+        gif_frame.delay = ((frame.duration() + 5) / 10).min(u16::MAX as u32) as u16;
+        gif_frame.dispose = gif::DisposalMethod::Background;
+
+        encoder.write_frame(&gif_frame)?;
+    }
+
+    Ok(())
+}
+
+/// Pick the palette index to use for transparency. If the document came from a GIF, then
+/// its index is kept, as long as no opaque pixel needs that entry. Otherwise, if any of the
+/// images need one, it's an entry that no opaque pixel uses, so that nothing is lost. Failing
+/// that, it's a new entry on the end, unless the palette is full, in which case the last
+/// color has to be given up.
+fn pick_transparent(
+    images: &[PixelState],
+    palette: &PaletteState,
+    num_colors: usize,
+    preferred: Option<u8>,
+) -> Option<u8> {
+    let mut has_transparency = false;
+    let mut colors = HashSet::new();
+    for p in images
+        .iter()
+        .flat_map(|image| image.bytes().chunks_exact(4))
+    {
+        match p[3] {
+            a if a < 128 => has_transparency = true,
+            _ => {
+                colors.insert([p[0], p[1], p[2], p[3]]);
+            }
+        }
+    }
+
+    let used: HashSet<usize> = colors
+        .into_iter()
+        .map(|rgba| palette.nearest(rgba, None))
+        .collect();
+
+    match preferred {
+        Some(idx) if !used.contains(&(idx as usize)) => return Some(idx),
+        _ if !has_transparency => return None,
+        _ => {}
+    }
+
+    let unused = (0..num_colors).rev().find(|idx| !used.contains(idx));
+
+    let idx = match unused {
+        Some(idx) => idx,
+        None if num_colors < 256 => num_colors,
+        None => num_colors - 1,
+    };
+    Some(idx as u8)
+}
+
+/// Read a GIF from the given path into a document.
+pub fn read_path(path_str: &str) -> Result<Document, StorageError> {
+    let path = Path::new(path_str);
+    let file = File::open(path)?;
+
+    read(file)
+}
+
+/// Read a GIF from the given reader. Each frame in the GIF becomes a frame in the document,
+/// and the global color table becomes its palette. GIF frames can cover just part of the
+/// image, so they're drawn onto a running canvas, honoring their disposal methods, the same
+/// way a viewer would. The transparent index is kept, so that it's written back out the same.
+pub fn read<R: Read>(reader: R) -> Result<Document, StorageError> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(reader)?;
+
+    let width = decoder.width() as usize;
+    let height = decoder.height() as usize;
+    if width == 0
+        || height == 0
+        || width > constants::MAX_PIXEL_DIMS as usize
+        || height > constants::MAX_PIXEL_DIMS as usize
+    {
+        return Err(StorageError::BadDimensions);
+    }
+
+    let palette = decoder.global_palette().map(|rgb| {
+        let values = rgb
+            .chunks_exact(3)
+            .take(constants::MAX_PALETTE_COLORS)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], 0xff]))
+            .collect();
+        PaletteState::new(values)
+    });
+
+    let header = PixelHeader::new(width as u32, height as u32, 8, 4);
+    let mut canvas = vec![0; width * height * 4];
+    let mut frames = Vec::new();
+    let mut transparent_index = None;
+
+    while let Some(gif_frame) = decoder.read_next_frame()? {
+        let previous = canvas.clone();
+        if transparent_index.is_none() {
+            transparent_index = gif_frame.transparent;
+        }
+
+        // Frames can hang off the edge of the screen, but not lie outside it altogether.
+        let left = gif_frame.left as usize;
+        let top = gif_frame.top as usize;
+        if left >= width || top >= height {
+            return Err(StorageError::FailedToDecodeGif);
+        }
+        let right = (left + gif_frame.width as usize).min(width);
+        let bottom = (top + gif_frame.height as usize).min(height);
+
+        for y in top..bottom {
+            for x in left..right {
+                // Transparent pixels leave the canvas alone.
+                let src = ((y - top) * gif_frame.width as usize + (x - left)) * 4;
+                if gif_frame.buffer[src + 3] != 0 {
+                    let dst = (y * width + x) * 4;
+                    canvas[dst..dst + 4].copy_from_slice(&gif_frame.buffer[src..src + 4]);
+                }
+            }
+        }
+
+        let pixels = PixelState::new(header.clone(), canvas.clone());
+        frames.push(Frame::new(gif_frame.delay as u32 * 10, vec![pixels]));
+
+        match gif_frame.dispose {
+            gif::DisposalMethod::Background => {
+                for y in top..bottom {
+                    canvas[(y * width + left) * 4..(y * width + right) * 4].fill(0);
+                }
+            }
+            gif::DisposalMethod::Previous => canvas = previous,
+            _ => {}
+        }
+    }
+
+    if frames.is_empty() {
+        return Err(StorageError::FailedToDecodeGif);
+    }
+
+    let mut doc = Document::from_frames(frames);
+    doc.set_loop_count(loop_count(decoder.repeat()));
+    doc.set_transparent_index(transparent_index);
+    if let Some(palette) = palette.filter(|palette| !palette.is_empty()) {
+        doc.set_palette(palette);
+        doc.clear_dirty();
+    }
+
+    Ok(doc)
+}
+
+/// Convert the number of times a GIF repeats to the number of times a document plays. A
+/// GIF without a loop count plays once. Zero means forever for documents.
+fn loop_count(repeat: gif::Repeat) -> u16 {
+    match repeat {
+        gif::Repeat::Infinite => 0,
+        gif::Repeat::Finite(n) => n.saturating_add(1),
+    }
+}
+
+impl From<gif::EncodingError> for StorageError {
+    fn from(e: gif::EncodingError) -> Self {
+        match e {
+            gif::EncodingError::Io(_) => Self::SystemError,
+            _ => Self::FailedToEncodeGif,
+        }
+    }
+}
+
+impl From<gif::DecodingError> for StorageError {
+    fn from(e: gif::DecodingError) -> Self {
+        match e {
+            gif::DecodingError::Io(_) => Self::SystemError,
+            _ => Self::FailedToDecodeGif,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::palette::PaletteState;
+
+    fn doc_with_frames(loop_count: u16) -> Document {
+        let header = PixelHeader::new(2, 1, 8, 4);
+        let frames = vec![
+            Frame::new(
+                100,
+                vec![PixelState::new(
+                    header.clone(),
+                    vec![255, 0, 0, 255, 0, 0, 0, 0],
+                )],
+            ),
+            Frame::new(
+                250,
+                vec![PixelState::new(header, vec![0, 0, 0, 0, 0, 0, 255, 255])],
+            ),
+        ];
+
+        let mut doc = Document::from_frames(frames);
+        doc.set_loop_count(loop_count);
+        doc
+    }
+
+    fn round_trip(doc: &Document) -> Document {
+        let mut doc = doc.clone();
+        doc.set_palette(PaletteState::new(vec![0xff0000ff, 0x0000ffff, 0x000000ff]));
+        let mut bytes = Vec::new();
+        write(&mut bytes, &doc).unwrap();
+        read(bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn it_round_trips_frames() {
+        let doc = round_trip(&doc_with_frames(0));
+
+        assert_eq!(2, doc.frames().len());
+        assert_eq!(100, doc.frame(0).duration());
+        assert_eq!(250, doc.frame(1).duration());
+        assert_eq!(vec![255, 0, 0, 255, 0, 0, 0, 0], *doc.cel(0, 0).bytes());
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 255, 255], *doc.cel(1, 0).bytes());
+    }
+
+    #[test]
+    fn it_round_trips_read_and_write() {
+        // Every color in the palette is in use, and black is the last one.
+        let header = PixelHeader::new(4, 1, 8, 4);
+        let bytes = vec![255, 0, 0, 255, 0, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0, 0];
+        let doc = round_trip(&Document::from_pixels(PixelState::new(
+            header,
+            bytes.clone(),
+        )));
+        assert_eq!(bytes, *doc.cel(0, 0).bytes());
+
+        let mut gif = Vec::new();
+        write(&mut gif, &doc).unwrap();
+        let again = read(gif.as_slice()).unwrap();
+        assert_eq!(doc.palette().values(), again.palette().values());
+        assert_eq!(doc.transparent_index(), again.transparent_index());
+        assert_eq!(doc.cel(0, 0).bytes(), again.cel(0, 0).bytes());
+        assert!(!again.dirty());
+    }
+
+    #[test]
+    fn it_keeps_the_transparent_index() {
+        // A GIF from elsewhere, with transparency at index 1 of 3.
+        let mut gif = Vec::new();
+        {
+            let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+            let mut encoder = gif::Encoder::new(&mut gif, 3, 1, &palette).unwrap();
+            let frame = gif::Frame::from_indexed_pixels(3, 1, vec![0, 1, 2], Some(1));
+            encoder.write_frame(&frame).unwrap();
+        }
+
+        let doc = read(gif.as_slice()).unwrap();
+        assert_eq!(Some(1), doc.transparent_index());
+        assert_eq!(
+            vec![255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 255, 255],
+            *doc.cel(0, 0).bytes()
+        );
+
+        let mut bytes = Vec::new();
+        write(&mut bytes, &doc).unwrap();
+        let again = read(bytes.as_slice()).unwrap();
+        assert_eq!(Some(1), again.transparent_index());
+        assert_eq!(doc.palette().values(), again.palette().values());
+        assert_eq!(doc.cel(0, 0).bytes(), again.cel(0, 0).bytes());
+    }
+
+    #[test]
+    fn it_clips_frames_to_the_screen() {
+        let encode = |left: u16, top: u16| {
+            let mut gif = Vec::new();
+            {
+                let palette = [255, 0, 0, 0, 0, 255];
+                let mut encoder = gif::Encoder::new(&mut gif, 2, 2, &palette).unwrap();
+                let mut frame = gif::Frame::from_indexed_pixels(1000, 1, vec![1; 1000], None);
+                frame.left = left;
+                frame.top = top;
+                frame.dispose = gif::DisposalMethod::Background;
+                encoder.write_frame(&frame).unwrap();
+            }
+            gif
+        };
+
+        // Hanging off the edge is fine.
+        let doc = read(encode(1, 1).as_slice()).unwrap();
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255],
+            *doc.cel(0, 0).bytes()
+        );
+
+        // Way off the edge isn't.
+        assert!(read(encode(0, 65000).as_slice()).is_err());
+        assert!(read(encode(65000, 0).as_slice()).is_err());
+    }
+
+    #[test]
+    fn it_round_trips_loop_count() {
+        assert_eq!(0, round_trip(&doc_with_frames(0)).loop_count());
+        assert_eq!(1, round_trip(&doc_with_frames(1)).loop_count());
+        assert_eq!(3, round_trip(&doc_with_frames(3)).loop_count());
+    }
+}
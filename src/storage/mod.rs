@@ -13,4 +13,41 @@
 // limitations under the License.
 
 pub mod error;
+pub mod gif;
+pub mod palette;
 pub mod png;
+pub mod sheet;
+
+use std::path::Path;
+use std::result::Result;
+
+use self::error::StorageError;
+use crate::model::document::Document;
+
+/// Is the given path a GIF? Anything else is treated as a PNG.
+fn is_gif(path_str: &str) -> bool {
+    Path::new(path_str)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"))
+}
+
+/// Write a document to the given path. The format is picked from the file extension.
+pub fn write_path(path_str: &str, doc: &Document) -> Result<(), StorageError> {
+    if is_gif(path_str) {
+        gif::write_path(path_str, doc)
+    } else {
+        png::write_path(path_str, doc)
+    }
+}
+
+/// Read a document from the given path. The format is picked from the file extension.
+pub fn read_path(path_str: &str) -> Result<Document, StorageError> {
+    let mut doc = if is_gif(path_str) {
+        gif::read_path(path_str)?
+    } else {
+        png::read_path(path_str)?
+    };
+
+    doc.set_path(String::from(path_str));
+    Ok(doc)
+}
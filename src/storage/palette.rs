@@ -0,0 +1,346 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::result::Result;
+
+use super::error::StorageError;
+use crate::common::constants;
+use crate::model::palette::PaletteState;
+
+/// Palette file formats that we support. None of them have an alpha channel, so every
+/// color is read as opaque, and written without its alpha.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaletteFormat {
+    /// GIMP palette (.gpl). Plain text, one color per line, with an optional name.
+    Gpl,
+    /// JASC (Paint Shop Pro) palette (.pal). Plain text, with a count up front.
+    Jasc,
+    /// Adobe color table (.act). Binary, always 256 colors, with an optional count.
+    Act,
+    /// Hex (.hex), as used by Lospec. Plain text, one RRGGBB color per line.
+    Hex,
+}
+
+impl PaletteFormat {
+    /// Pick the format from the extension of the given path.
+    pub fn from_path(path_str: &str) -> Option<Self> {
+        let ext = Path::new(path_str)
+            .extension()?
+            .to_str()?
+            .to_ascii_lowercase();
+        match ext.as_str() {
+            "gpl" => Some(Self::Gpl),
+            "pal" => Some(Self::Jasc),
+            "act" => Some(Self::Act),
+            "hex" => Some(Self::Hex),
+            _ => None,
+        }
+    }
+}
+
+/// Read a palette from the given path. The format is picked from the file extension.
+pub fn read_path(path_str: &str) -> Result<PaletteState, StorageError> {
+    let format = PaletteFormat::from_path(path_str).ok_or(StorageError::BadPaletteFormat)?;
+    let file = File::open(Path::new(path_str))?;
+
+    read(file, format)
+}
+
+/// Read a palette in the given format. Anything past the first 256 colors is dropped,
+/// because that's as many as a palette can hold.
+pub fn read<R: Read>(mut reader: R, format: PaletteFormat) -> Result<PaletteState, StorageError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut colors = match format {
+        PaletteFormat::Act => read_act(&bytes),
+        _ => {
+            let text =
+                std::str::from_utf8(&bytes).map_err(|_| StorageError::FailedToDecodePalette)?;
+            match format {
+                PaletteFormat::Gpl => read_gpl(text),
+                PaletteFormat::Jasc => read_jasc(text),
+                _ => read_hex(text),
+            }
+        }
+    }?;
+
+    colors.truncate(constants::MAX_PALETTE_COLORS);
+    if colors.is_empty() {
+        return Err(StorageError::FailedToDecodePalette);
+    }
+
+    let values = colors
+        .into_iter()
+        .map(|[r, g, b]| u32::from_be_bytes([r, g, b, 0xff]))
+        .collect();
+    Ok(PaletteState::new(values))
+}
+
+/// Write a palette to the given path. The format is picked from the file extension.
+pub fn write_path(path_str: &str, palette: &PaletteState) -> Result<(), StorageError> {
+    let format = PaletteFormat::from_path(path_str).ok_or(StorageError::BadPaletteFormat)?;
+    let file = File::create(Path::new(path_str))?;
+    let buf_writer = &mut BufWriter::new(file);
+
+    write(buf_writer, palette, format)
+}
+
+/// Write a palette in the given format.
+pub fn write<W: Write>(
+    mut writer: W,
+    palette: &PaletteState,
+    format: PaletteFormat,
+) -> Result<(), StorageError> {
+    let colors: Vec<[u8; 3]> = (0..palette.len())
+        .map(|idx| {
+            let [r, g, b, _] = palette.rgba(idx);
+            [r, g, b]
+        })
+        .collect();
+
+    match format {
+        PaletteFormat::Gpl => {
+            writeln!(writer, "GIMP Palette")?;
+            writeln!(writer, "Name: pixwiz")?;
+            writeln!(writer, "Columns: 8")?;
+            writeln!(writer, "#")?;
+            for [r, g, b] in colors {
+                writeln!(writer, "{:3} {:3} {:3}\t{}", r, g, b, hex(r, g, b))?;
+            }
+        }
+
+        PaletteFormat::Jasc => {
+            writeln!(writer, "JASC-PAL")?;
+            writeln!(writer, "0100")?;
+            writeln!(writer, "{}", colors.len())?;
+            for [r, g, b] in colors {
+                writeln!(writer, "{} {} {}", r, g, b)?;
+            }
+        }
+
+        // The table always holds 256 colors. The count that follows says how many of them
+        // are in use, and there's no transparent color (0xffff).
+        PaletteFormat::Act => {
+            let count = colors.len().min(256);
+            let mut bytes = vec![0; 256 * 3];
+            for (dst, src) in bytes.chunks_exact_mut(3).zip(colors.iter()) {
+                dst.copy_from_slice(src);
+            }
+            bytes.extend_from_slice(&(count as u16).to_be_bytes());
+            bytes.extend_from_slice(&0xffffu16.to_be_bytes());
+            writer.write_all(&bytes)?;
+        }
+
+        PaletteFormat::Hex => {
+            for [r, g, b] in colors {
+                writeln!(writer, "{}", hex(r, g, b))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn hex(r: u8, g: u8, b: u8) -> String {
+    format!("{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Parse a number in the given radix. Unlike `from_str_radix`, this won't take a sign, so
+/// that a stray "+" doesn't sneak through.
+fn parse_number(value: &str, radix: u32) -> Result<u32, StorageError> {
+    if value.is_empty() || !value.chars().all(|c| c.is_digit(radix)) {
+        return Err(StorageError::FailedToDecodePalette);
+    }
+
+    u32::from_str_radix(value, radix).map_err(|_| StorageError::FailedToDecodePalette)
+}
+
+/// Parse the red, green and blue values at the start of a line. Anything after them (like
+/// a color name) is ignored.
+fn parse_rgb(line: &str) -> Result<[u8; 3], StorageError> {
+    let mut values = line
+        .split_whitespace()
+        .take(3)
+        .map(|v| match parse_number(v, 10)? {
+            value if value <= 255 => Ok(value as u8),
+            _ => Err(StorageError::FailedToDecodePalette),
+        });
+
+    let mut next = || {
+        values
+            .next()
+            .unwrap_or(Err(StorageError::FailedToDecodePalette))
+    };
+    Ok([next()?, next()?, next()?])
+}
+
+fn read_gpl(text: &str) -> Result<Vec<[u8; 3]>, StorageError> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err(StorageError::FailedToDecodePalette);
+    }
+
+    lines
+        .map(str::trim)
+        .filter(|line| {
+            !(line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:"))
+        })
+        .map(parse_rgb)
+        .collect()
+}
+
+fn read_jasc(text: &str) -> Result<Vec<[u8; 3]>, StorageError> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("JASC-PAL") || lines.next() != Some("0100") {
+        return Err(StorageError::FailedToDecodePalette);
+    }
+
+    let count = lines
+        .next()
+        .ok_or(StorageError::FailedToDecodePalette)
+        .and_then(|line| parse_number(line, 10))? as usize;
+
+    let colors = lines
+        .filter(|line| !line.is_empty())
+        .take(count)
+        .map(parse_rgb)
+        .collect::<Result<Vec<[u8; 3]>, StorageError>>()?;
+
+    if colors.len() != count {
+        return Err(StorageError::FailedToDecodePalette);
+    }
+
+    Ok(colors)
+}
+
+fn read_act(bytes: &[u8]) -> Result<Vec<[u8; 3]>, StorageError> {
+    if bytes.len() < 256 * 3 {
+        return Err(StorageError::FailedToDecodePalette);
+    }
+
+    // Newer tables have a count after the colors. Older ones don't, so they use all 256.
+    let count = match bytes.get(256 * 3..256 * 3 + 2) {
+        Some(&[hi, lo]) => match u16::from_be_bytes([hi, lo]) as usize {
+            count if count > 0 && count <= 256 => count,
+            _ => 256,
+        },
+        _ => 256,
+    };
+
+    Ok(bytes[..count * 3]
+        .chunks_exact(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect())
+}
+
+fn read_hex(text: &str) -> Result<Vec<[u8; 3]>, StorageError> {
+    text.lines()
+        .map(|line| line.trim().trim_start_matches('#'))
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            if line.len() != 6 {
+                return Err(StorageError::FailedToDecodePalette);
+            }
+            let [_, r, g, b] = parse_number(line, 16)?.to_be_bytes();
+            Ok([r, g, b])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> PaletteState {
+        PaletteState::new(vec![0x000000ff, 0xff8000ff, 0x1d2b53ff])
+    }
+
+    fn round_trip(format: PaletteFormat) -> PaletteState {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &palette(), format).unwrap();
+        read(bytes.as_slice(), format).unwrap()
+    }
+
+    #[test]
+    fn it_round_trips_every_format() {
+        for &format in [
+            PaletteFormat::Gpl,
+            PaletteFormat::Jasc,
+            PaletteFormat::Act,
+            PaletteFormat::Hex,
+        ]
+        .iter()
+        {
+            assert_eq!(palette().values(), round_trip(format).values());
+        }
+    }
+
+    #[test]
+    fn it_reads_gpl_with_comments_and_names() {
+        let text =
+            "GIMP Palette\nName: Test\nColumns: 4\n# comment\n\n255 0 0 Red\n  0  0 255\tBlue\n";
+        let palette = read(text.as_bytes(), PaletteFormat::Gpl).unwrap();
+        assert_eq!(&[0xff0000ff, 0x0000ffff], palette.values());
+    }
+
+    #[test]
+    fn it_reads_act_without_count() {
+        let mut bytes = vec![0; 256 * 3];
+        bytes[3..6].copy_from_slice(&[1, 2, 3]);
+        let palette = read(bytes.as_slice(), PaletteFormat::Act).unwrap();
+        assert_eq!(256, palette.len());
+        assert_eq!([1, 2, 3, 255], palette.rgba(1));
+    }
+
+    #[test]
+    fn it_rejects_bad_palettes() {
+        assert!(read("ff00zz\n".as_bytes(), PaletteFormat::Hex).is_err());
+        assert!(read("JASC-PAL\n0100\n2\n1 2 3\n".as_bytes(), PaletteFormat::Jasc).is_err());
+        assert!(read("".as_bytes(), PaletteFormat::Hex).is_err());
+        assert!(read("+fffff\n".as_bytes(), PaletteFormat::Hex).is_err());
+        assert!(read("GIMP Palette\n+1 2 3\n".as_bytes(), PaletteFormat::Gpl).is_err());
+        assert!(read(
+            "JASC-PAL\n0100\n+1\n1 2 3\n".as_bytes(),
+            PaletteFormat::Jasc
+        )
+        .is_err());
+        assert!(read("GIMP Palette\n256 0 0\n".as_bytes(), PaletteFormat::Gpl).is_err());
+    }
+
+    #[test]
+    fn it_keeps_the_first_color_usable() {
+        let palette = read("000000\nff8000\n".as_bytes(), PaletteFormat::Hex).unwrap();
+        let black = palette.rgba(0);
+        assert_eq!(black, palette.to_rgba(&[palette.to_index(black)]));
+    }
+
+    #[test]
+    fn it_picks_format_from_path() {
+        assert_eq!(
+            Some(PaletteFormat::Gpl),
+            PaletteFormat::from_path("a/b.GPL")
+        );
+        assert_eq!(Some(PaletteFormat::Hex), PaletteFormat::from_path("b.hex"));
+        assert_eq!(None, PaletteFormat::from_path("b.png"));
+    }
+}
@@ -21,28 +21,53 @@ use std::result::Result;
 
 use super::error::StorageError;
 use crate::common::constants;
+use crate::model::compositor;
+use crate::model::document::Document;
+use crate::model::document::Frame;
+use crate::model::palette::PaletteState;
 use crate::model::pixels::PixelHeader;
 use crate::model::pixels::PixelState;
+use crate::model::types::BlendMode;
 
-/// Write pixel state to the given path as a PNG.
-pub fn write_path(path_str: &str, pixels: &PixelState) -> Result<(), StorageError> {
+/// Keyword of the text chunk that holds the document's palette.
+const PALETTE_KEYWORD: &str = "pixwiz:palette";
+
+/// Write a document to the given path as a PNG. Documents with more than one frame are
+/// written as an animated PNG (APNG). Either way, the palette goes along with it.
+pub fn write_path(path_str: &str, doc: &Document) -> Result<(), StorageError> {
     let path = Path::new(path_str);
     let file = File::create(path)?;
     let buf_writer = &mut BufWriter::new(file);
 
-    write(buf_writer, pixels)
+    if doc.frames().len() > 1 {
+        write_animated(buf_writer, doc)
+    } else {
+        write_with_palette(buf_writer, &doc.flatten(), Some(doc.palette()))
+    }
 }
 
 /// Write pixel state to the given writer. This exists because I think we'll need to
 /// write a fully formed PNG to memory for the clipboard.
 pub fn write<W: Write>(writer: W, pixels: &PixelState) -> Result<(), StorageError> {
+    write_with_palette(writer, pixels, None)
+}
+
+/// Write pixel state to the given writer, along with a palette, if there is one.
+fn write_with_palette<W: Write>(
+    writer: W,
+    pixels: &PixelState,
+    palette: Option<&PaletteState>,
+) -> Result<(), StorageError> {
     let mut encoder = png::Encoder::new(
         writer,
         pixels.header().width() as u32,
         pixels.header().height() as u32,
     );
-    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_color(png::ColorType::Rgba);
     encoder.set_depth(png::BitDepth::Eight);
+    if let Some(palette) = palette {
+        encoder.add_text_chunk(PALETTE_KEYWORD.to_string(), write_palette(palette))?;
+    }
     let mut encode_writer = encoder.write_header()?;
 
     // Oof. If this is a file the user loaded, then we're dropping all the other fields.
@@ -55,25 +80,61 @@ pub fn write<W: Write>(writer: W, pixels: &PixelState) -> Result<(), StorageErro
     }
 }
 
-/// Read a PNG from the given path into pixel state.
-pub fn read_path(path_str: &str) -> Result<PixelState, StorageError> {
+/// Write every frame of a document to the given writer as an animated PNG. Each frame is
+/// flattened and written in full, so it simply replaces the one before it.
+pub fn write_animated<W: Write>(writer: W, doc: &Document) -> Result<(), StorageError> {
+    let header = doc.header();
+    let mut encoder = png::Encoder::new(writer, header.width() as u32, header.height() as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(doc.frames().len() as u32, doc.loop_count() as u32)?;
+    encoder.add_text_chunk(PALETTE_KEYWORD.to_string(), write_palette(doc.palette()))?;
+    let mut encode_writer = encoder.write_header()?;
+
+    for (idx, frame) in doc.frames().iter().enumerate() {
+        // Delays are a fraction of a second. Ours are in milliseconds.
+        let delay = frame.duration().min(u16::MAX as u32) as u16;
+        encode_writer.set_frame_delay(delay, 1000)?;
+        encode_writer.set_dispose_op(png::DisposeOp::None)?;
+        encode_writer.set_blend_op(png::BlendOp::Source)?;
+        encode_writer.write_image_data(doc.flatten_frame(idx).bytes())?;
+    }
+
+    encode_writer.finish()?;
+
+    Ok(())
+}
+
+/// Read a PNG from the given path into a document.
+pub fn read_path(path_str: &str) -> Result<Document, StorageError> {
     let path = Path::new(path_str);
     let file = File::open(path)?;
 
     read(file)
 }
 
-/// Read a PNG from the given reader.
-pub fn read<R: Read>(reader: R) -> Result<PixelState, StorageError> {
+/// Read a PNG from the given reader. Every frame of an animated PNG becomes a frame in the
+/// document. APNG frames can cover just part of the image, so they're drawn onto a running
+/// canvas, honoring their blend and dispose operations, the same way a viewer would. A plain
+/// PNG is a document with a single frame.
+pub fn read<R: Read>(reader: R) -> Result<Document, StorageError> {
     let decoder = png::Decoder::new(reader);
-    let (info, mut decode_reader) = decoder.read_info()?;
+    let mut decode_reader = decoder.read_info()?;
+    let info = decode_reader.info();
+
+    // Text chunks come before the image data, so they're already in the info.
+    let palette = info
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == PALETTE_KEYWORD)
+        .and_then(|chunk| read_palette(&chunk.text));
 
     // We support 8-bit PNGs in RGBA format for now. Let's at least be upfront about it.
     if info.bit_depth != png::BitDepth::Eight {
         return Err(StorageError::BadBitDepth);
     }
 
-    if info.color_type != png::ColorType::RGBA {
+    if info.color_type != png::ColorType::Rgba {
         return Err(StorageError::BadColorType);
     }
 
@@ -82,10 +143,6 @@ pub fn read<R: Read>(reader: R) -> Result<PixelState, StorageError> {
         return Err(StorageError::BadDimensions);
     }
 
-    let mut bytes = vec![0; info.buffer_size()];
-
-    decode_reader.next_frame(&mut bytes)?;
-
     let header = PixelHeader::new(
         info.width,
         info.height,
@@ -93,9 +150,173 @@ pub fn read<R: Read>(reader: R) -> Result<PixelState, StorageError> {
         4, // Ditto.
     );
 
-    let pixels = PixelState::new(header, bytes);
+    // If there's no frame control before the image data, then the default image isn't part
+    // of the animation. It's usually a fallback for viewers that don't know about APNG.
+    let (num_frames, num_plays, skip_default) = match info.animation_control {
+        Some(ac) => (ac.num_frames, ac.num_plays, info.frame_control.is_none()),
+        None => (1, 0, false),
+    };
+
+    let width = header.width();
+    let height = header.height();
+    let mut canvas = vec![0; width * height * 4];
+    let mut buf = vec![0; decode_reader.output_buffer_size()];
+    let mut frames = Vec::new();
+
+    if skip_default {
+        decode_reader.next_frame(&mut buf)?;
+    }
+
+    for _ in 0..num_frames {
+        decode_reader.next_frame(&mut buf)?;
+
+        // Plain PNGs don't have frame control, so treat them as one full frame.
+        let (fc, duration) = match decode_reader.info().frame_control {
+            Some(fc) => (fc, read_delay(&fc)),
+            None => (
+                png::FrameControl {
+                    width: width as u32,
+                    height: height as u32,
+                    ..Default::default()
+                },
+                constants::DEFAULT_FRAME_DURATION,
+            ),
+        };
+
+        let left = fc.x_offset as usize;
+        let top = fc.y_offset as usize;
+        let right = (left + fc.width as usize).min(width);
+        let bottom = (top + fc.height as usize).min(height);
+        let previous = canvas.clone();
+
+        for y in top..bottom {
+            for x in left..right {
+                let src = ((y - top) * fc.width as usize + (x - left)) * 4;
+                let dst = (y * width + x) * 4;
+                let rgba = [buf[src], buf[src + 1], buf[src + 2], buf[src + 3]];
+
+                let blended = match fc.blend_op {
+                    png::BlendOp::Source => rgba,
+                    png::BlendOp::Over => {
+                        let backdrop = [
+                            canvas[dst],
+                            canvas[dst + 1],
+                            canvas[dst + 2],
+                            canvas[dst + 3],
+                        ];
+                        compositor::blend(backdrop, rgba, 1.0, BlendMode::Normal)
+                    }
+                };
+
+                canvas[dst..dst + 4].copy_from_slice(&blended);
+            }
+        }
+
+        let pixels = PixelState::new(header.clone(), canvas.clone());
+        frames.push(Frame::new(duration, vec![pixels]));
+
+        match fc.dispose_op {
+            png::DisposeOp::Background => {
+                for y in top..bottom {
+                    let dst = (y * width + left) * 4;
+                    canvas[dst..dst + (right - left) * 4].fill(0);
+                }
+            }
+            png::DisposeOp::Previous => canvas = previous,
+            png::DisposeOp::None => {}
+        }
+    }
+
+    let mut doc = Document::from_frames(frames);
+    doc.set_loop_count(num_plays.min(u16::MAX as u32) as u16);
+    if let Some(palette) = palette {
+        doc.set_palette(palette);
+        doc.clear_dirty();
+    }
+
+    Ok(doc)
+}
 
-    Ok(pixels)
+/// Read the image from a PNG as RGBA, whatever its color type and bit depth. This is for
+/// PNGs that come from other apps, like the ones on the clipboard, so any animation (and
+/// our own palette chunk) is ignored.
+pub fn read_image<R: Read>(reader: R) -> Result<PixelState, StorageError> {
+    read_image_with_limit(reader, constants::MAX_PIXEL_DIMS)
+}
+
+/// Read the image from a PNG as RGBA, like read_image(), but with a custom limit on the
+/// pixel dimensions. This is for images that aren't edited directly, like sprite sheets
+/// that are about to be sliced.
+pub fn read_image_with_limit<R: Read>(
+    reader: R,
+    max_dims: u32,
+) -> Result<PixelState, StorageError> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut decode_reader = decoder.read_info()?;
+
+    let info = decode_reader.info();
+    if info.width > max_dims || info.height > max_dims {
+        return Err(StorageError::BadDimensions);
+    }
+
+    let mut buf = vec![0; decode_reader.output_buffer_size()];
+    let output = decode_reader.next_frame(&mut buf)?;
+    if output.bit_depth != png::BitDepth::Eight {
+        return Err(StorageError::BadBitDepth);
+    }
+
+    // Palettes have been expanded by now, so there's only gray or RGB, with or without alpha.
+    let bytes = &buf[..output.buffer_size()];
+    let rgba = match output.color_type {
+        png::ColorType::Rgba => bytes.to_vec(),
+        png::ColorType::Rgb => bytes
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => bytes
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => bytes.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err(StorageError::BadColorType),
+    };
+
+    let header = PixelHeader::new(output.width, output.height, 8, 4);
+    Ok(PixelState::new(header, rgba))
+}
+
+/// Write a palette as text, for its chunk. Each value is eight hex digits, RGBA.
+fn write_palette(palette: &PaletteState) -> String {
+    palette
+        .values()
+        .iter()
+        .map(|value| format!("{:08x}", value))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Read a palette from the text of its chunk. Returns None if it's malformed, in which
+/// case the document just gets the default palette.
+fn read_palette(text: &str) -> Option<PaletteState> {
+    let values = text
+        .split_whitespace()
+        .take(constants::MAX_PALETTE_COLORS)
+        .map(|value| u32::from_str_radix(value, 16).ok())
+        .collect::<Option<Vec<u32>>>()?;
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(PaletteState::new(values))
+    }
+}
+
+/// Convert a frame delay, which is a fraction of a second, to milliseconds.
+fn read_delay(fc: &png::FrameControl) -> u32 {
+    // A zero denominator means hundredths of a second.
+    let den = if fc.delay_den == 0 { 100 } else { fc.delay_den };
+    (fc.delay_num as u32 * 1000 + den as u32 / 2) / den as u32
 }
 
 impl From<png::EncodingError> for StorageError {
@@ -109,3 +330,132 @@ impl From<png::DecodingError> for StorageError {
         Self::FailedToDecode
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc_with_frames(count: usize) -> Document {
+        let header = PixelHeader::new(2, 1, 8, 4);
+        let frames = (0..count)
+            .map(|i| {
+                let bytes = vec![i as u8, 0, 0, 255, 0, 0, 0, 0];
+                Frame::new(
+                    50 * (i as u32 + 1),
+                    vec![PixelState::new(header.clone(), bytes)],
+                )
+            })
+            .collect();
+
+        let mut doc = Document::from_frames(frames);
+        doc.set_loop_count(2);
+        doc
+    }
+
+    #[test]
+    fn it_round_trips_animated() {
+        let mut bytes = Vec::new();
+        write_animated(&mut bytes, &doc_with_frames(3)).unwrap();
+        let doc = read(bytes.as_slice()).unwrap();
+
+        assert_eq!(3, doc.frames().len());
+        assert_eq!(2, doc.loop_count());
+        for i in 0..3 {
+            assert_eq!(50 * (i as u32 + 1), doc.frame(i).duration());
+            assert_eq!(vec![i as u8, 0, 0, 255, 0, 0, 0, 0], *doc.cel(i, 0).bytes());
+        }
+    }
+
+    #[test]
+    fn it_reads_plain_png() {
+        let doc = doc_with_frames(1);
+        let mut bytes = Vec::new();
+        write(&mut bytes, &doc.flatten()).unwrap();
+        let doc = read(bytes.as_slice()).unwrap();
+
+        assert_eq!(1, doc.frames().len());
+        assert_eq!(constants::DEFAULT_FRAME_DURATION, doc.frame(0).duration());
+        assert_eq!(vec![0, 0, 0, 255, 0, 0, 0, 0], *doc.cel(0, 0).bytes());
+    }
+
+    fn encode(color_type: png::ColorType, bit_depth: png::BitDepth, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+        encoder.set_color(color_type);
+        encoder.set_depth(bit_depth);
+        if color_type == png::ColorType::Indexed {
+            encoder.set_palette(vec![255, 0, 0, 0, 0, 255]);
+            encoder.set_trns(vec![255, 0]);
+        }
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(data)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn it_reads_images_from_other_apps() {
+        let rgb = encode(
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            &[1, 2, 3, 4, 5, 6],
+        );
+        let pixels = read_image(rgb.as_slice()).unwrap();
+        assert_eq!(vec![1, 2, 3, 255, 4, 5, 6, 255], *pixels.bytes());
+
+        let rgba16 = encode(
+            png::ColorType::Rgba,
+            png::BitDepth::Sixteen,
+            &[255, 0, 0, 0, 128, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0],
+        );
+        let pixels = read_image(rgba16.as_slice()).unwrap();
+        assert_eq!(vec![255, 0, 128, 255, 0, 0, 0, 0], *pixels.bytes());
+
+        let indexed = encode(png::ColorType::Indexed, png::BitDepth::Eight, &[0, 1]);
+        let pixels = read_image(indexed.as_slice()).unwrap();
+        assert_eq!(vec![255, 0, 0, 255, 0, 0, 255, 0], *pixels.bytes());
+
+        assert!(read_image(&b"not a png"[..]).is_err());
+    }
+
+    #[test]
+    fn it_round_trips_palette() {
+        let mut doc = doc_with_frames(1);
+        doc.set_palette(PaletteState::new(vec![0x102030ff, 0xffffff80]));
+        let mut bytes = Vec::new();
+        write_with_palette(&mut bytes, &doc.flatten(), Some(doc.palette())).unwrap();
+        let doc = read(bytes.as_slice()).unwrap();
+
+        assert_eq!(&[0x102030ff, 0xffffff80], doc.palette().values());
+        assert!(!doc.dirty());
+        assert!(read_palette("12 zz").is_none());
+    }
+
+    #[test]
+    fn it_reads_large_png() {
+        let header = PixelHeader::new(128, 128, 8, 4);
+        let pixels = PixelState::new(header, vec![255; 128 * 128 * 4]);
+        let mut bytes = Vec::new();
+        write(&mut bytes, &pixels).unwrap();
+        let doc = read(bytes.as_slice()).unwrap();
+
+        assert_eq!(128, doc.header().width());
+        assert_eq!(128, doc.header().height());
+    }
+
+    #[test]
+    fn it_rejects_oversized_png() {
+        let dims = constants::MAX_PIXEL_DIMS + 1;
+        let header = PixelHeader::new(dims, 1, 8, 4);
+        let pixels = PixelState::new(header, vec![0; dims as usize * 4]);
+        let mut bytes = Vec::new();
+        write(&mut bytes, &pixels).unwrap();
+
+        assert!(matches!(
+            read(bytes.as_slice()),
+            Err(StorageError::BadDimensions)
+        ));
+    }
+}
@@ -0,0 +1,560 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Read;
+use std::path::Path;
+use std::result::Result;
+
+use serde_json::json;
+
+use super::error::StorageError;
+use crate::common::constants;
+use crate::model::document::Document;
+use crate::model::pixels::PixelHeader;
+use crate::model::pixels::PixelState;
+use crate::model::sheet::AtlasFormat;
+use crate::model::sheet::SheetLayout;
+use crate::model::sheet::SheetOptions;
+use crate::model::sheet::SliceOptions;
+
+/// A rectangle in pixels, with the origin at the top left. Unlike canvas coords, these
+/// are zero-based, because that's what atlas consumers expect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SheetRect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl SheetRect {
+    fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        json!({ "x": self.x, "y": self.y, "w": self.width, "h": self.height })
+    }
+}
+
+/// A single sprite in the sheet.
+struct SheetEntry {
+    /// Index of the frame in the document.
+    frame: usize,
+    /// Where the sprite ended up in the sheet, not counting extrusion.
+    dest: SheetRect,
+    /// Which part of the frame the sprite holds. This is the whole frame unless the
+    /// sprite was trimmed.
+    source: SheetRect,
+}
+
+/// Export every frame (or every tagged frame) of a document to the given path as a sprite
+/// sheet PNG. The JSON atlas is written next to it, with the same name.
+pub fn write_path(
+    path_str: &str,
+    doc: &Document,
+    options: &SheetOptions,
+) -> Result<(), StorageError> {
+    let path = Path::new(path_str);
+    let (sheet, entries) = pack(doc, options);
+
+    let file = File::create(path)?;
+    super::png::write(&mut BufWriter::new(file), &sheet)?;
+
+    let image = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let atlas = atlas(doc, options, &entries, sheet.header(), &name, &image);
+
+    let json_file = File::create(path.with_extension("json"))?;
+    serde_json::to_writer_pretty(BufWriter::new(json_file), &atlas)?;
+
+    Ok(())
+}
+
+/// Lay out the frames, and draw them into a single image.
+fn pack(doc: &Document, options: &SheetOptions) -> (PixelState, Vec<SheetEntry>) {
+    let header = doc.header();
+    let images: Vec<(usize, PixelState)> = (0..doc.frames().len())
+        .filter(|idx| !options.tagged_only() || doc.frame(*idx).tag().is_some())
+        .map(|idx| (idx, doc.flatten_frame(idx)))
+        .collect();
+
+    let full = SheetRect::new(0, 0, header.width(), header.height());
+    let sources: Vec<SheetRect> = images
+        .iter()
+        .map(|(_, image)| match options.layout() {
+            SheetLayout::Grid => full,
+            SheetLayout::Packed => trim(image),
+        })
+        .collect();
+
+    // Each sprite takes up its own size, plus extrusion on every side, plus padding
+    // between it and the next one.
+    let extrude = options.extrude();
+    let padding = options.padding();
+    let cell = |source: &SheetRect| {
+        (
+            source.width + extrude * 2 + padding,
+            source.height + extrude * 2 + padding,
+        )
+    };
+
+    let positions = match options.layout() {
+        SheetLayout::Grid => layout_grid(&sources, cell),
+        SheetLayout::Packed => layout_packed(&sources, cell),
+    };
+
+    // The last row and column don't need padding after them.
+    let width = positions
+        .iter()
+        .zip(&sources)
+        .map(|(&(x, _), source)| x + cell(source).0 - padding)
+        .max()
+        .unwrap_or(1);
+    let height = positions
+        .iter()
+        .zip(&sources)
+        .map(|(&(_, y), source)| y + cell(source).1 - padding)
+        .max()
+        .unwrap_or(1);
+
+    let sheet_header = PixelHeader::new(width as u32, height as u32, 8, 4);
+    let mut bytes = vec![0; width * height * 4];
+    let mut entries = Vec::with_capacity(images.len());
+
+    for (((frame, image), source), (x, y)) in images.iter().zip(sources).zip(positions) {
+        let dest = SheetRect::new(x + extrude, y + extrude, source.width, source.height);
+        blit(image, &source, &mut bytes, width, &dest, extrude);
+
+        entries.push(SheetEntry {
+            frame: *frame,
+            dest,
+            source,
+        });
+    }
+
+    (PixelState::new(sheet_header, bytes), entries)
+}
+
+/// Find the smallest rectangle that holds all the opaque pixels of an image. An image
+/// with no opaque pixels at all gets a single pixel, because a sprite can't be empty.
+fn trim(image: &PixelState) -> SheetRect {
+    let width = image.header().width();
+    let height = image.header().height();
+    let (mut x0, mut y0, mut x1, mut y1) = (width, height, 0, 0);
+
+    for (i, pixel) in image.bytes().chunks_exact(4).enumerate() {
+        if pixel[3] != 0 {
+            let (x, y) = (i % width, i / width);
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + 1);
+            y1 = y1.max(y + 1);
+        }
+    }
+
+    if x0 >= x1 || y0 >= y1 {
+        SheetRect::new(0, 0, 1, 1)
+    } else {
+        SheetRect::new(x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+/// Place sprites in rows and columns, as close to square as we can get.
+fn layout_grid<F>(sources: &[SheetRect], cell: F) -> Vec<(usize, usize)>
+where
+    F: Fn(&SheetRect) -> (usize, usize),
+{
+    let cols = (sources.len() as f64).sqrt().ceil().max(1.0) as usize;
+
+    sources
+        .iter()
+        .enumerate()
+        .map(|(i, source)| {
+            let (cell_width, cell_height) = cell(source);
+            ((i % cols) * cell_width, (i / cols) * cell_height)
+        })
+        .collect()
+}
+
+/// Place sprites on shelves, tallest first. Each shelf is filled left to right until the
+/// next sprite doesn't fit, then we start a new one underneath. The shelf width is picked
+/// so that the sheet comes out roughly square.
+fn layout_packed<F>(sources: &[SheetRect], cell: F) -> Vec<(usize, usize)>
+where
+    F: Fn(&SheetRect) -> (usize, usize),
+{
+    let cells: Vec<(usize, usize)> = sources.iter().map(&cell).collect();
+    let area: usize = cells.iter().map(|(w, h)| w * h).sum();
+    let widest = cells.iter().map(|(w, _)| *w).max().unwrap_or(0);
+    let max_width = widest.max((area as f64).sqrt().ceil() as usize);
+
+    let mut order: Vec<usize> = (0..cells.len()).collect();
+    order.sort_by(|a, b| cells[*b].1.cmp(&cells[*a].1));
+
+    let mut positions = vec![(0, 0); cells.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+
+    for i in order {
+        let (w, h) = cells[i];
+        if x > 0 && x + w > max_width {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+
+        positions[i] = (x, y);
+        x += w;
+        shelf_height = shelf_height.max(h);
+    }
+
+    positions
+}
+
+/// Copy part of an image into the sheet. The edges are repeated outwards by the
+/// extrusion amount.
+fn blit(
+    image: &PixelState,
+    source: &SheetRect,
+    bytes: &mut [u8],
+    sheet_width: usize,
+    dest: &SheetRect,
+    extrude: usize,
+) {
+    let image_width = image.header().width();
+    let image_bytes = image.bytes();
+
+    for dy in 0..dest.height + extrude * 2 {
+        for dx in 0..dest.width + extrude * 2 {
+            let sx = source.x + dx.saturating_sub(extrude).min(source.width - 1);
+            let sy = source.y + dy.saturating_sub(extrude).min(source.height - 1);
+            let src = (sy * image_width + sx) * 4;
+
+            let x = dest.x + dx - extrude;
+            let y = dest.y + dy - extrude;
+            let dst = (y * sheet_width + x) * 4;
+
+            bytes[dst..dst + 4].copy_from_slice(&image_bytes[src..src + 4]);
+        }
+    }
+}
+
+/// Build the JSON atlas for the packed sprites.
+fn atlas(
+    doc: &Document,
+    options: &SheetOptions,
+    entries: &[SheetEntry],
+    sheet_header: &PixelHeader,
+    name: &str,
+    image: &str,
+) -> serde_json::Value {
+    let header = doc.header();
+    let size = json!({ "w": sheet_header.width(), "h": sheet_header.height() });
+    let source_size = json!({ "w": header.width(), "h": header.height() });
+
+    let frames: Vec<serde_json::Value> = entries
+        .iter()
+        .map(|entry| {
+            let frame = doc.frame(entry.frame);
+            let trimmed =
+                entry.source.width != header.width() || entry.source.height != header.height();
+
+            let mut value = json!({
+                "filename": format!("{} {}", name, entry.frame),
+                "frame": entry.dest.to_json(),
+                "rotated": false,
+                "trimmed": trimmed,
+                "spriteSourceSize": entry.source.to_json(),
+                "sourceSize": source_size,
+                "duration": frame.duration(),
+            });
+
+            // Aseprite keeps tags in the meta section, but TexturePacker doesn't have
+            // them at all, so they go on the frame.
+            if options.format() == AtlasFormat::TexturePacker {
+                value["pivot"] = json!({ "x": 0.5, "y": 0.5 });
+                value["tag"] = json!(frame.tag());
+            }
+
+            value
+        })
+        .collect();
+
+    let meta = match options.format() {
+        AtlasFormat::Aseprite => json!({
+            "app": "https://www.aseprite.org/",
+            "version": "1.2",
+            "image": image,
+            "format": "RGBA8888",
+            "size": size,
+            "scale": "1",
+            "frameTags": frame_tags(doc, entries),
+            "layers": [],
+            "slices": [],
+        }),
+        AtlasFormat::TexturePacker => json!({
+            "app": "https://www.codeandweb.com/texturepacker",
+            "version": "1.0",
+            "image": image,
+            "format": "RGBA8888",
+            "size": size,
+            "scale": "1",
+        }),
+    };
+
+    json!({ "frames": frames, "meta": meta })
+}
+
+/// Collect runs of sprites with the same tag, Aseprite style. The indices refer to sprites
+/// in the atlas, not frames in the document.
+fn frame_tags(doc: &Document, entries: &[SheetEntry]) -> Vec<serde_json::Value> {
+    let mut tags: Vec<(&str, usize, usize)> = Vec::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        if let Some(tag) = doc.frame(entry.frame).tag() {
+            match tags.last_mut() {
+                Some((name, _, to)) if *name == tag && *to + 1 == i => *to = i,
+                _ => tags.push((tag, i, i)),
+            }
+        }
+    }
+
+    tags.iter()
+        .map(|(name, from, to)| {
+            json!({ "name": name, "from": from, "to": to, "direction": "forward" })
+        })
+        .collect()
+}
+
+/// Read a sprite sheet PNG from the given path, and slice it into cells.
+pub fn read_path(path_str: &str, options: &SliceOptions) -> Result<Vec<PixelState>, StorageError> {
+    let path = Path::new(path_str);
+    let file = File::open(path)?;
+
+    read(file, options)
+}
+
+/// Read a sprite sheet PNG from the given reader, and slice it into cells. Cells are read
+/// left to right, top to bottom. Empty cells at the end of the sheet are dropped, because
+/// the last row is often only partly filled. Sheets come from all sorts of tools, so any
+/// color type or bit depth will do.
+pub fn read<R: Read>(reader: R, options: &SliceOptions) -> Result<Vec<PixelState>, StorageError> {
+    let sheet = super::png::read_image_with_limit(reader, constants::MAX_SHEET_DIMS)?;
+
+    slice(&sheet, options)
+}
+
+/// Slice a sprite sheet into cells.
+fn slice(sheet: &PixelState, options: &SliceOptions) -> Result<Vec<PixelState>, StorageError> {
+    let cell_width = options.cell_width();
+    let cell_height = options.cell_height();
+    if cell_width > constants::MAX_PIXEL_DIMS as usize
+        || cell_height > constants::MAX_PIXEL_DIMS as usize
+    {
+        return Err(StorageError::BadDimensions);
+    }
+
+    let count = |size: usize, cell: usize| {
+        let inner = size.saturating_sub(options.margin() * 2) + options.spacing();
+        inner / (cell + options.spacing())
+    };
+    let cols = count(sheet.header().width(), cell_width);
+    let rows = count(sheet.header().height(), cell_height);
+
+    let mut cells = Vec::with_capacity(cols * rows);
+    for row in 0..rows {
+        for col in 0..cols {
+            // Pixel storage is one-based.
+            let x = (options.margin() + col * (cell_width + options.spacing()) + 1) as f64;
+            let y = (options.margin() + row * (cell_height + options.spacing()) + 1) as f64;
+            let area = druid::Rect::new(x, y, x + cell_width as f64, y + cell_height as f64);
+            cells.push(sheet.clone_area(area));
+        }
+    }
+
+    while cells.len() > 1 && is_empty(cells.last().unwrap()) {
+        cells.pop();
+    }
+
+    if cells.is_empty() {
+        Err(StorageError::BadDimensions)
+    } else {
+        Ok(cells)
+    }
+}
+
+fn is_empty(pixels: &PixelState) -> bool {
+    pixels.bytes().chunks_exact(4).all(|p| p[3] == 0)
+}
+
+/// Write each cell to its own PNG, next to the sheet at the given path. They're numbered
+/// in order, so "sheet.png" becomes "sheet_0.png", "sheet_1.png", and so on.
+pub fn write_cells(path_str: &str, cells: &[PixelState]) -> Result<(), StorageError> {
+    let path = Path::new(path_str);
+    let stem = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    for (i, cell) in cells.iter().enumerate() {
+        let cell_path = path.with_file_name(format!("{}_{}.png", stem, i));
+        let file = File::create(cell_path)?;
+        super::png::write(&mut BufWriter::new(file), cell)?;
+    }
+
+    Ok(())
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(_: serde_json::Error) -> Self {
+        Self::FailedToEncode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::document::Frame;
+
+    fn doc_with_frames() -> Document {
+        // Two 4x4 frames. The first has a single opaque pixel at (1, 2), and the second
+        // is completely transparent.
+        let header = PixelHeader::new(4, 4, 8, 4);
+        let mut bytes = vec![0; 4 * 4 * 4];
+        bytes[(2 * 4 + 1) * 4..(2 * 4 + 2) * 4].copy_from_slice(&[255, 0, 0, 255]);
+
+        let frames = vec![
+            Frame::new(100, vec![PixelState::new(header.clone(), bytes)]),
+            Frame::new(200, vec![PixelState::empty(header)]),
+        ];
+
+        Document::from_frames(frames)
+    }
+
+    #[test]
+    fn it_packs_grid() {
+        let mut options = SheetOptions::default();
+        options.set_padding(2);
+        options.set_extrude(1);
+
+        let (sheet, entries) = pack(&doc_with_frames(), &options);
+
+        // Two columns of 4 + 2 extrusion, with 2 padding between them.
+        assert_eq!(14, sheet.header().width());
+        assert_eq!(6, sheet.header().height());
+        assert_eq!(SheetRect::new(1, 1, 4, 4), entries[0].dest);
+        assert_eq!(SheetRect::new(9, 1, 4, 4), entries[1].dest);
+    }
+
+    #[test]
+    fn it_packs_trimmed() {
+        let mut options = SheetOptions::default();
+        options.set_layout(SheetLayout::Packed);
+        options.set_extrude(1);
+
+        let (sheet, entries) = pack(&doc_with_frames(), &options);
+
+        assert_eq!(SheetRect::new(1, 2, 1, 1), entries[0].source);
+        assert_eq!(SheetRect::new(0, 0, 1, 1), entries[1].source);
+
+        // The single pixel is extruded into a 3x3 block.
+        let dest = entries[0].dest;
+        for y in dest.y - 1..=dest.y + 1 {
+            for x in dest.x - 1..=dest.x + 1 {
+                let i = (y * sheet.header().width() + x) * 4;
+                assert_eq!([255, 0, 0, 255], sheet.bytes()[i..i + 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn it_slices_cells() {
+        // A 7x4 sheet with a 1 pixel margin and spacing holds two 2x2 cells in a row.
+        // Only the second cell has anything in it.
+        let header = PixelHeader::new(7, 4, 8, 4);
+        let mut bytes = vec![0; 7 * 4 * 4];
+        bytes[(7 + 4) * 4..(7 + 5) * 4].copy_from_slice(&[0, 255, 0, 255]);
+        let sheet = PixelState::new(header, bytes);
+
+        let mut options = SliceOptions::default();
+        options.set_cell_width(2);
+        options.set_cell_height(2);
+        options.set_margin(1);
+        options.set_spacing(1);
+
+        let cells = slice(&sheet, &options).unwrap();
+        assert_eq!(2, cells.len());
+        assert!(is_empty(&cells[0]));
+        assert_eq!(vec![0, 255, 0, 255], cells[1].bytes()[0..4]);
+    }
+
+    #[test]
+    fn it_reads_indexed_sheets() {
+        // Two 2x1 cells, side by side: red and blue, then blue and transparent.
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 4, 1);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(vec![255, 0, 0, 0, 0, 255, 0, 0, 0]);
+        encoder.set_trns(vec![255, 255, 0]);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&[0, 1, 1, 2])
+            .unwrap();
+
+        let mut options = SliceOptions::default();
+        options.set_cell_width(2);
+        options.set_cell_height(1);
+
+        let cells = read(bytes.as_slice(), &options).unwrap();
+        assert_eq!(2, cells.len());
+        assert_eq!(vec![255, 0, 0, 255, 0, 0, 255, 255], *cells[0].bytes());
+        assert_eq!(vec![0, 0, 255, 255, 0, 0, 0, 0], *cells[1].bytes());
+    }
+
+    #[test]
+    fn it_writes_tags() {
+        let mut doc = doc_with_frames();
+        doc.set_frame_tag("idle");
+        doc.set_current_frame(1);
+        doc.set_frame_tag("idle");
+
+        let mut options = SheetOptions::default();
+        let (sheet, entries) = pack(&doc, &options);
+        let value = atlas(&doc, &options, &entries, sheet.header(), "test", "test.png");
+        assert_eq!(
+            json!([{ "name": "idle", "from": 0, "to": 1, "direction": "forward" }]),
+            value["meta"]["frameTags"]
+        );
+        assert_eq!(200, value["frames"][1]["duration"]);
+
+        options.set_format(AtlasFormat::TexturePacker);
+        let value = atlas(&doc, &options, &entries, sheet.header(), "test", "test.png");
+        assert_eq!("idle", value["frames"][0]["tag"]);
+    }
+}
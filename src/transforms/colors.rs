@@ -12,59 +12,49 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::VecDeque;
-
 use super::util;
 use crate::model::pixels::PixelEnv;
 use crate::model::pixels::PixelHeader;
 
 /// Convert pixels to black & white.
 pub fn black_and_white(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
-    let bounds = env.bounds();
-    for y in bounds.y0 as usize..bounds.y1 as usize {
-        for x in bounds.x0 as usize..bounds.x1 as usize {
-            let color = util::read(x, y, header, bytes);
-            let bw = util::black_and_white(&color, env.param());
-            util::write(x, y, header, bytes, &bw);
-        }
+    for (x, y) in util::points(env) {
+        let color = util::read(x, y, header, bytes);
+        let bw = util::black_and_white(&color, env.param());
+        util::write(x, y, header, bytes, &bw);
     }
 }
 
 pub fn brightness(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
-    let bounds = env.bounds();
-    for y in bounds.y0 as usize..bounds.y1 as usize {
-        for x in bounds.x0 as usize..bounds.x1 as usize {
-            let color = util::read(x, y, header, bytes);
-            let new_color = util::brightness(&color, env.param());
-            util::write(x, y, header, bytes, &new_color);
-        }
+    for (x, y) in util::points(env) {
+        let color = util::read(x, y, header, bytes);
+        let new_color = util::brightness(&color, env.param());
+        util::write(x, y, header, bytes, &new_color);
     }
 }
 
 /// Desaturate pixels (make them grayscale).
 pub fn desaturate(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
-    let bounds = env.bounds();
-    for y in bounds.y0 as usize..bounds.y1 as usize {
-        for x in bounds.x0 as usize..bounds.x1 as usize {
-            let color = util::read(x, y, header, bytes);
-            let gray = util::desaturate(&color);
-            util::write(x, y, header, bytes, &gray);
-        }
+    for (x, y) in util::points(env) {
+        let color = util::read(x, y, header, bytes);
+        let gray = util::desaturate(&color);
+        util::write(x, y, header, bytes, &gray);
     }
 }
 
 /// Fill the given pixels to the boundary.
 pub fn fill(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
-    let bounds = env.bounds();
-    for y in bounds.y0 as usize..bounds.y1 as usize {
-        for x in bounds.x0 as usize..bounds.x1 as usize {
-            util::write(x, y, header, bytes, &env.color());
-        }
+    for (x, y) in util::points(env) {
+        util::write(x, y, header, bytes, &env.color());
     }
 }
 
 /// Flood fill the given pixels starting from a seed position.
 pub fn flood_fill(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
+    if !env.contains(env.pos()) {
+        return;
+    }
+
     let x = env.pos().x as usize;
     let y = env.pos().y as usize;
     let start_color = util::read(x, y, header, bytes);
@@ -72,35 +62,14 @@ pub fn flood_fill(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
         return;
     }
 
-    let mut q: VecDeque<druid::Point> = VecDeque::new();
-    q.push_back(env.pos());
-    while !q.is_empty() {
-        let node = q.pop_front().unwrap();
-        let x = node.x as usize;
-        let y = node.y as usize;
+    util::flood(header, env, false, |x, y| {
         if util::read(x, y, header, bytes) == start_color {
             util::write(x, y, header, bytes, env.color());
-
-            let bounds = env.bounds();
-
-            let left = node - (1.0, 0.0);
-            if bounds.contains(left) {
-                q.push_back(left);
-            }
-            let right = node + (1.0, 0.0);
-            if bounds.contains(right) {
-                q.push_back(right);
-            }
-            let up = node - (0.0, 1.0);
-            if bounds.contains(up) {
-                q.push_back(up);
-            }
-            let down = node + (0.0, 1.0);
-            if bounds.contains(down) {
-                q.push_back(down);
-            }
+            true
+        } else {
+            false
         }
-    }
+    });
 }
 
 /// Dither pixels using Floyd–Steinberg.
@@ -134,7 +103,7 @@ pub fn dither_floyd(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
         bytes: &mut Vec<u8>,
     ) {
         let p = druid::Point::new(x as f64, y as f64);
-        if env.bounds().contains(p) {
+        if env.contains(p) {
             let oldpixel = util::read(x, y, header, bytes);
             let newpixel = apply_error(&oldpixel, quant_error, weight);
             util::write(x, y, header, bytes, &newpixel);
@@ -0,0 +1,184 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::util;
+use crate::model::pixels::PixelEnv;
+use crate::model::pixels::PixelHeader;
+use crate::model::types::Orientation;
+use crate::util::shapes;
+
+/// Get the area that the given (inclusive) area covers once it's transformed. Quarter
+/// turns swap the width and height, keeping the same centre as near as we can.
+pub fn transformed_area(orientation: Orientation, area: druid::Rect) -> druid::Rect {
+    match orientation {
+        Orientation::Rotate90 | Orientation::Rotate270 => {
+            let (w, h) = (area.width(), area.height());
+            let x0 = area.x0 + ((w - h) / 2.0).floor();
+            let y0 = area.y0 + ((h - w) / 2.0).floor();
+            druid::Rect::new(x0, y0, x0 + h, y0 + w)
+        }
+        _ => area,
+    }
+}
+
+/// Get where a point in the given (inclusive) area ends up once it's transformed. The
+/// result is relative to the origin, which is the top left of the transformed area.
+pub fn transform_point(
+    orientation: Orientation,
+    area: druid::Rect,
+    origin: druid::Point,
+    p: druid::Point,
+) -> druid::Point {
+    let (x, y) = (p.x - area.x0, p.y - area.y0);
+    let (w, h) = (area.width(), area.height());
+
+    let (x, y) = match orientation {
+        Orientation::FlipHorizontal => (w - x, y),
+        Orientation::FlipVertical => (x, h - y),
+        Orientation::Rotate90 => (h - y, x),
+        Orientation::Rotate180 => (w - x, h - y),
+        Orientation::Rotate270 => (y, w - x),
+    };
+
+    druid::Point::new(origin.x + x, origin.y + y)
+}
+
+/// Flip or rotate the selected pixels within the bounds. Anything that ends up outside
+/// the image is lost, and anything left behind is cleared.
+pub fn transform(
+    header: &PixelHeader,
+    env: &PixelEnv,
+    bytes: &mut Vec<u8>,
+    orientation: Orientation,
+) {
+    let bounds = env.bounds();
+    let area = druid::Rect::new(bounds.x0, bounds.y0, bounds.x1 - 1.0, bounds.y1 - 1.0);
+    let origin = transformed_area(orientation, area).origin();
+    let image = shapes::inflate_rect(header.bounds());
+
+    let src_bytes = bytes.clone();
+    let points: Vec<(usize, usize)> = util::points(env).collect();
+
+    for &(x, y) in points.iter() {
+        util::write(x, y, header, bytes, &druid::Color::rgba8(0, 0, 0, 0));
+    }
+
+    for (x, y) in points {
+        let p = druid::Point::new(x as f64, y as f64);
+        let q = transform_point(orientation, area, origin, p);
+        if image.contains(q) {
+            let color = util::read(x, y, header, &src_bytes);
+            util::write(q.x as usize, q.y as usize, header, bytes, &color);
+        }
+    }
+}
+
+/// Flip or rotate a whole image. Quarter turns swap the width and height, so this hands
+/// back a new header along with the bytes.
+pub fn transform_image(
+    header: &PixelHeader,
+    bytes: &[u8],
+    orientation: Orientation,
+) -> (PixelHeader, Vec<u8>) {
+    let (width, height) = match orientation {
+        Orientation::Rotate90 | Orientation::Rotate270 => (header.height(), header.width()),
+        _ => (header.width(), header.height()),
+    };
+    let dst_header = PixelHeader::new(
+        width as u32,
+        height as u32,
+        header.depth(),
+        header.bytes_per_pixel(),
+    );
+    let mut dst_bytes = vec![0; bytes.len()];
+
+    let area = header.bounds();
+    let origin = druid::Point::new(1.0, 1.0);
+    for y in 1..header.height() + 1 {
+        for x in 1..header.width() + 1 {
+            let p = druid::Point::new(x as f64, y as f64);
+            let q = transform_point(orientation, area, origin, p);
+            let color = util::read(x, y, header, bytes);
+            util::write(
+                q.x as usize,
+                q.y as usize,
+                &dst_header,
+                &mut dst_bytes,
+                &color,
+            );
+        }
+    }
+
+    (dst_header, dst_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [u8; 4] = [255, 0, 0, 255];
+    const B: [u8; 4] = [0, 255, 0, 255];
+    const C: [u8; 4] = [0, 0, 255, 255];
+    const D: [u8; 4] = [255, 255, 255, 255];
+    const E: [u8; 4] = [0, 0, 0, 0];
+
+    // A 3x2 image:
+    //   A B C
+    //   D E E
+    fn image() -> (PixelHeader, Vec<u8>) {
+        let header = PixelHeader::new(3, 2, 8, 4);
+        let bytes = [A, B, C, D, E, E].concat();
+        (header, bytes)
+    }
+
+    #[test]
+    fn it_flips_image() {
+        let (header, bytes) = image();
+
+        let (_, flipped) = transform_image(&header, &bytes, Orientation::FlipHorizontal);
+        assert_eq!([C, B, A, E, E, D].concat(), flipped);
+
+        let (_, flipped) = transform_image(&header, &bytes, Orientation::FlipVertical);
+        assert_eq!([D, E, E, A, B, C].concat(), flipped);
+    }
+
+    #[test]
+    fn it_rotates_image() {
+        let (header, bytes) = image();
+
+        let (rotated_header, rotated) = transform_image(&header, &bytes, Orientation::Rotate90);
+        assert_eq!((2, 3), (rotated_header.width(), rotated_header.height()));
+        assert_eq!([D, A, E, B, E, C].concat(), rotated);
+
+        let (_, rotated) = transform_image(&header, &bytes, Orientation::Rotate180);
+        assert_eq!([E, E, D, C, B, A].concat(), rotated);
+
+        let (rotated_header, rotated) = transform_image(&header, &bytes, Orientation::Rotate270);
+        assert_eq!((2, 3), (rotated_header.width(), rotated_header.height()));
+        assert_eq!([C, E, B, E, A, D].concat(), rotated);
+    }
+
+    #[test]
+    fn it_rotates_selection_about_its_centre() {
+        let (header, mut bytes) = image();
+
+        // Rotating the top row stands it on end, through the middle column.
+        let bounds = druid::Rect::new(1.0, 1.0, 4.0, 2.0);
+        let env = PixelEnv::new(druid::Color::BLACK, druid::Point::ZERO, bounds, 0.0);
+        transform(&header, &env, &mut bytes, Orientation::Rotate90);
+
+        // The first pixel goes off the top of the image.
+        assert_eq!([E, B, E, D, C, E].concat(), bytes);
+    }
+}
@@ -12,42 +12,234 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::common::constants;
 use crate::controller::undo;
 use crate::model::app::AppState;
 use crate::model::pixels::PixelEnv;
 use crate::model::pixels::PixelHeader;
+use crate::model::pixels::PixelState;
 
 pub mod colors;
+pub mod geometry;
+pub mod select;
 pub mod simple;
-mod util;
+pub mod util;
 
 pub fn apply<F>(data: &mut AppState, f: F, param: f64)
 where
     F: Fn(&PixelHeader, &PixelEnv, &mut Vec<u8>),
 {
+    let bounds = data.doc().bounds();
+    apply_over(data, bounds, f, param);
+}
+
+/// Apply a transform, like apply(), but with an undo record that covers the given area.
+/// This is for transforms that write outside the bounds they read from.
+pub fn apply_over<F>(data: &mut AppState, area: druid::Rect, f: F, param: f64)
+where
+    F: Fn(&PixelHeader, &PixelEnv, &mut Vec<u8>),
+{
+    // Leave locked and hidden layers alone.
+    if !data.doc().editable() {
+        return;
+    }
+
     // We have all the information we need for a mod record, so just create it here.
     // That way the caller, and the f() we're applying, don't need to worry about it.
-    let bounds = data.doc().bounds();
-    undo::push(data, bounds);
+    undo::push(data, area);
 
     apply_no_undo(data, f, param);
 }
 
+/// Apply a transform that only moves pixels about, like a flip or a rotation, with an undo
+/// record that covers the given area. Indexed pixels are moved as they are, rather than
+/// going through the palette, so that they keep their indices.
+pub fn apply_geometry_over<F>(data: &mut AppState, area: druid::Rect, f: F, param: f64)
+where
+    F: Fn(&PixelHeader, &PixelEnv, &mut Vec<u8>),
+{
+    if !data.doc().editable() {
+        return;
+    }
+
+    undo::push(data, area);
+
+    let pixels = data.doc().pixels().clone();
+    let pixels = match pixels.header().bytes_per_pixel() {
+        1 => unpack_indices(&transform(data, f, param, &pack_indices(&pixels))),
+        _ => transform(data, f, param, &pixels),
+    };
+    data.doc_mut()
+        .pixels_mut()
+        .set_bytes(pixels.bytes().to_vec());
+}
+
+/// Apply a transform to the whole image: every layer of every frame, locked or not. The
+/// transform can change the dimensions of the image, so it hands back a new header along
+/// with the bytes. The whole lot is a single undo step. The transform must only move pixels
+/// about, because indexed cels go through it index by index.
+pub fn apply_image<F>(data: &mut AppState, f: F)
+where
+    F: Fn(&PixelHeader, &[u8]) -> (PixelHeader, Vec<u8>),
+{
+    undo::push_cels(data);
+
+    data.doc_mut()
+        .map_cels(|cel| match cel.header().bytes_per_pixel() {
+            1 => {
+                let cel = pack_indices(cel);
+                let (header, bytes) = f(cel.header(), cel.bytes());
+                unpack_indices(&PixelState::new(header, bytes))
+            }
+            _ => {
+                let (header, bytes) = f(cel.header(), cel.bytes());
+                PixelState::new(header, bytes)
+            }
+        });
+}
+
 pub fn apply_no_undo<F>(data: &mut AppState, f: F, param: f64)
 where
     F: Fn(&PixelHeader, &PixelEnv, &mut Vec<u8>),
 {
-    // The transform function gets copies of the header and the bytes. We don't want
-    // it mucking directly with our pixels.
-    let header = data.doc().pixels().header().clone();
+    let pixels = data.doc().pixels().clone();
+    let rgba = transform(data, f, param, &data.doc().resolve(&pixels));
+
+    // Write back the modified pixels, in whatever mode the document is in.
+    let pixels = data.doc().requantize(&pixels, &rgba);
+    data.doc_mut()
+        .pixels_mut()
+        .set_bytes(pixels.bytes().to_vec());
+}
+
+/// Run a transform over a copy of the given RGBA pixels. We don't want it mucking directly
+/// with our pixels.
+fn transform<F>(data: &AppState, f: F, param: f64, pixels: &PixelState) -> PixelState
+where
+    F: Fn(&PixelHeader, &PixelEnv, &mut Vec<u8>),
+{
+    let header = pixels.header().clone();
     let brush_color = data.brush_color().clone();
     let current_pos = data.current_pos();
     let bounds = data.doc().bounds();
-    let env = PixelEnv::new(brush_color, current_pos, bounds, param);
-    let mut bytes = data.doc().pixels().bytes().to_vec();
+    let mut env = PixelEnv::new(brush_color, current_pos, bounds, param);
+    env.set_mask(data.doc().mask().cloned());
+    let mut bytes = pixels.bytes().to_vec();
 
     f(&header, &env, &mut bytes);
 
-    // Write back the modified pixels.
-    data.doc_mut().pixels_mut().set_bytes(bytes);
+    PixelState::new(header, bytes)
+}
+
+/// Pack indexed pixels into RGBA, with the index in the red channel, so that transforms
+/// that only move pixels about can move indices too.
+fn pack_indices(pixels: &PixelState) -> PixelState {
+    let header = pixels.header();
+    let bytes = pixels
+        .bytes()
+        .iter()
+        .flat_map(|&idx| match idx {
+            constants::TRANSPARENT_INDEX => [0, 0, 0, 0],
+            _ => [idx, 0, 0, 255],
+        })
+        .collect();
+    let header = PixelHeader::new(header.width() as u32, header.height() as u32, 8, 4);
+    PixelState::new(header, bytes)
+}
+
+/// Unpack indices that were packed with pack_indices(). Anything that was cleared on the
+/// way through is transparent.
+fn unpack_indices(pixels: &PixelState) -> PixelState {
+    let header = pixels.header();
+    let bytes = pixels
+        .bytes()
+        .chunks_exact(4)
+        .map(|p| match p[3] {
+            0 => constants::TRANSPARENT_INDEX,
+            _ => p[0],
+        })
+        .collect();
+    let header = PixelHeader::new(header.width() as u32, header.height() as u32, 8, 1);
+    PixelState::new(header, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::document::Document;
+    use crate::model::palette;
+    use crate::model::palette::PaletteState;
+    use crate::model::types::*;
+    use crate::util::shapes;
+
+    // A 2x2 indexed image, using both of the blacks in the palette:
+    //   black  white
+    //   black2 black2
+    fn app_state() -> AppState {
+        let mut data = AppState::new(druid::WindowId::next());
+        let header = PixelHeader::new(2, 2, 8, 4);
+        let mut doc = Document::from_pixels(PixelState::empty(header));
+        doc.set_palette(PaletteState::new(vec![0x000000ff, 0xffffffff, 0x000000ff]));
+        doc.set_color_mode(ColorMode::Indexed);
+
+        let (black, white, black2) = (
+            palette::from_entry(0),
+            palette::from_entry(1),
+            palette::from_entry(2),
+        );
+        doc.pixels_mut()
+            .set_bytes(vec![black, white, black2, black2]);
+        data.set_doc(doc);
+        data
+    }
+
+    #[test]
+    fn it_keeps_indices_it_leaves_alone() {
+        let mut data = app_state();
+        data.doc_mut()
+            .set_selection(druid::Rect::new(2.0, 1.0, 2.0, 1.0));
+        apply(&mut data, simple::clear, 0.0);
+
+        let black2 = palette::from_entry(2);
+        let expected = vec![
+            palette::from_entry(0),
+            constants::TRANSPARENT_INDEX,
+            black2,
+            black2,
+        ];
+        assert_eq!(&expected, data.doc().pixels().bytes());
+    }
+
+    #[test]
+    fn it_moves_indices_as_they_are() {
+        let mut data = app_state();
+        apply_image(&mut data, |header, bytes| {
+            geometry::transform_image(header, bytes, Orientation::FlipVertical)
+        });
+
+        let (black, white, black2) = (
+            palette::from_entry(0),
+            palette::from_entry(1),
+            palette::from_entry(2),
+        );
+        assert_eq!(
+            &vec![black2, black2, black, white],
+            data.doc().pixels().bytes()
+        );
+
+        // Moving a selection works the same way.
+        let selection = druid::Rect::new(1.0, 1.0, 1.0, 2.0);
+        data.doc_mut().set_selection(selection);
+        let area = shapes::inflate_rect(selection);
+        apply_geometry_over(
+            &mut data,
+            area,
+            |header, env, bytes| geometry::transform(header, env, bytes, Orientation::FlipVertical),
+            0.0,
+        );
+        assert_eq!(
+            &vec![black, black2, black2, white],
+            data.doc().pixels().bytes()
+        );
+    }
 }
@@ -0,0 +1,147 @@
+// Copyright 2021 Andy King
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::util;
+use crate::model::mask::Mask;
+use crate::model::pixels::PixelEnv;
+use crate::model::pixels::PixelHeader;
+use crate::model::wand::WandOptions;
+
+/// Select pixels that match the color at the seed position, give or take the tolerance.
+/// In contiguous mode, only pixels connected to the seed are selected; otherwise every
+/// match within the bounds is. Returns None if nothing is selected.
+pub fn magic_wand(
+    header: &PixelHeader,
+    env: &PixelEnv,
+    bytes: &[u8],
+    options: &WandOptions,
+) -> Option<Mask> {
+    if !env.contains(env.pos()) {
+        return None;
+    }
+
+    let x = env.pos().x as usize;
+    let y = env.pos().y as usize;
+    let start_color = util::read(x, y, header, bytes);
+
+    if !options.contiguous() {
+        return by_color(header, env, bytes, &start_color, options.tolerance());
+    }
+
+    let mut mask = empty(env);
+    util::flood(header, env, options.diagonal(), |x, y| {
+        let color = util::read(x, y, header, bytes);
+        let selected = util::similar(&color, &start_color, options.tolerance());
+        mask.set_xy(x, y, selected);
+        selected
+    });
+
+    mask.trim()
+}
+
+/// Select every pixel within the bounds that matches the given color, give or take the
+/// tolerance. Returns None if nothing is selected.
+pub fn by_color(
+    header: &PixelHeader,
+    env: &PixelEnv,
+    bytes: &[u8],
+    color: &druid::Color,
+    tolerance: u8,
+) -> Option<Mask> {
+    let mut mask = empty(env);
+    for (x, y) in util::points(env) {
+        let selected = util::similar(&util::read(x, y, header, bytes), color, tolerance);
+        mask.set_xy(x, y, selected);
+    }
+
+    mask.trim()
+}
+
+/// An empty mask covering the bounds of the environment.
+fn empty(env: &PixelEnv) -> Mask {
+    let bounds = env.bounds();
+    Mask::new(druid::Rect::new(
+        bounds.x0,
+        bounds.y0,
+        bounds.x1 - 1.0,
+        bounds.y1 - 1.0,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [u8; 4] = [255, 0, 0, 255];
+    const B: [u8; 4] = [250, 0, 0, 255];
+    const C: [u8; 4] = [0, 0, 255, 255];
+
+    // A 4x3 image. The top-left run is split from the bottom-right pixel by a column of C,
+    // but they still touch diagonally.
+    fn image() -> (PixelHeader, Vec<u8>) {
+        let header = PixelHeader::new(4, 3, 8, 4);
+        let bytes = [A, B, C, C, C, C, A, C, C, C, C, A].concat();
+        (header, bytes)
+    }
+
+    fn wand(options: &WandOptions) -> Vec<(usize, usize)> {
+        let (header, bytes) = image();
+        let bounds = druid::Rect::new(1.0, 1.0, 5.0, 4.0);
+        let env = PixelEnv::new(
+            druid::Color::BLACK,
+            druid::Point::new(1.0, 1.0),
+            bounds,
+            0.0,
+        );
+        let mask = magic_wand(&header, &env, &bytes, options).unwrap();
+
+        let mut points = Vec::new();
+        for y in 1..4 {
+            for x in 1..5 {
+                if mask.contains_xy(x, y) {
+                    points.push((x, y));
+                }
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn it_selects_contiguous() {
+        let options: WandOptions = Default::default();
+        assert_eq!(vec![(1, 1)], wand(&options));
+    }
+
+    #[test]
+    fn it_selects_within_tolerance() {
+        let mut options: WandOptions = Default::default();
+        options.set_tolerance(8);
+        assert_eq!(vec![(1, 1), (2, 1)], wand(&options));
+    }
+
+    #[test]
+    fn it_selects_diagonals() {
+        let mut options: WandOptions = Default::default();
+        options.set_tolerance(8);
+        options.flip_diagonal();
+        assert_eq!(vec![(1, 1), (2, 1), (3, 2), (4, 3)], wand(&options));
+    }
+
+    #[test]
+    fn it_selects_globally() {
+        let mut options: WandOptions = Default::default();
+        options.flip_contiguous();
+        assert_eq!(vec![(1, 1), (3, 2), (4, 3)], wand(&options));
+    }
+}
@@ -17,10 +17,7 @@ use crate::model::pixels::PixelEnv;
 use crate::model::pixels::PixelHeader;
 
 pub fn clear(header: &PixelHeader, env: &PixelEnv, bytes: &mut Vec<u8>) {
-    let bounds = env.bounds();
-    for y in bounds.y0 as usize..bounds.y1 as usize {
-        for x in bounds.x0 as usize..bounds.x1 as usize {
-            util::write(x, y, header, bytes, &druid::Color::rgba(0.0, 0.0, 0.0, 0.0));
-        }
+    for (x, y) in util::points(env) {
+        util::write(x, y, header, bytes, &druid::Color::rgba(0.0, 0.0, 0.0, 0.0));
     }
 }
@@ -12,8 +12,74 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::VecDeque;
+
+use crate::model::pixels::PixelEnv;
 use crate::model::pixels::PixelHeader;
 
+/// Get every selected point within the bounds, row by row. This respects the selection
+/// mask, so transforms should use it rather than walking the bounds themselves.
+pub fn points(env: &PixelEnv) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let bounds = env.bounds();
+    (bounds.y0 as usize..bounds.y1 as usize)
+        .flat_map(move |y| (bounds.x0 as usize..bounds.x1 as usize).map(move |x| (x, y)))
+        .filter(move |&(x, y)| env.contains(druid::Point::new(x as f64, y as f64)))
+}
+
+/// Offsets to the neighbours of a point. The first four are the edges, and the last four
+/// are the diagonals.
+const NEIGHBOURS: [(f64, f64); 8] = [
+    (-1.0, 0.0),
+    (1.0, 0.0),
+    (0.0, -1.0),
+    (0.0, 1.0),
+    (-1.0, -1.0),
+    (1.0, -1.0),
+    (-1.0, 1.0),
+    (1.0, 1.0),
+];
+
+/// Flood outwards from the seed position, breadth first. The callback says whether each
+/// point belongs to the region; if it does, we carry on to its neighbours. Neighbours are
+/// the four adjacent points, plus the diagonals if asked for. Every point is visited at
+/// most once, and only if it's selected.
+pub fn flood<F>(header: &PixelHeader, env: &PixelEnv, diagonal: bool, mut f: F)
+where
+    F: FnMut(usize, usize) -> bool,
+{
+    let idx = |p: druid::Point| (p.y as usize - 1) * header.width() + (p.x as usize - 1);
+    let neighbours = if diagonal { 8 } else { 4 };
+    let mut visited = vec![false; header.width() * header.height()];
+
+    let mut q: VecDeque<druid::Point> = VecDeque::new();
+    if env.contains(env.pos()) {
+        visited[idx(env.pos())] = true;
+        q.push_back(env.pos());
+    }
+
+    while let Some(node) = q.pop_front() {
+        if f(node.x as usize, node.y as usize) {
+            for &offset in NEIGHBOURS[..neighbours].iter() {
+                let p = node + offset;
+                if env.contains(p) && !visited[idx(p)] {
+                    visited[idx(p)] = true;
+                    q.push_back(p);
+                }
+            }
+        }
+    }
+}
+
+/// Are the given colors alike? Each channel can differ by up to the tolerance.
+pub fn similar(a: &druid::Color, b: &druid::Color, tolerance: u8) -> bool {
+    let (a_r, a_g, a_b, a_a) = a.as_rgba8();
+    let (b_r, b_g, b_b, b_a) = b.as_rgba8();
+
+    [(a_r, b_r), (a_g, b_g), (a_b, b_b), (a_a, b_a)]
+        .iter()
+        .all(|&(a, b)| (a as i16 - b as i16).abs() <= tolerance as i16)
+}
+
 /// Read RGBA from bytes. The underlying storage doesn't really matter: it can be a
 /// PixelState, or a copy thereof, or something else, as long as it's bytes.
 pub fn read(x: usize, y: usize, header: &PixelHeader, bytes: &[u8]) -> druid::Color {
@@ -70,7 +136,7 @@ pub fn brightness(color: &druid::Color, val: f64) -> druid::Color {
 }
 
 /// Convert RGBA bytes to HSLA.
-pub fn _rgba8_to_hsla(red: u8, green: u8, blue: u8, alpha: u8) -> (f64, f64, f64, f64) {
+pub fn rgba8_to_hsla(red: u8, green: u8, blue: u8, alpha: u8) -> (f64, f64, f64, f64) {
     rgba_to_hsla(
         red as f64 / 255.0,
         green as f64 / 255.0,
@@ -156,6 +222,30 @@ pub fn hsla_to_rgba(hue: f64, saturation: f64, luminance: f64, alpha: f64) -> (f
     (red, green, blue, alpha)
 }
 
+/// Convert HSLA to HSVA. The hue and alpha are the same in both.
+pub fn hsla_to_hsva(hue: f64, saturation: f64, luminance: f64, alpha: f64) -> (f64, f64, f64, f64) {
+    let value = luminance + saturation * f64::min(luminance, 1.0 - luminance);
+    let saturation = if f64_eq(value, 0.0) {
+        0.0
+    } else {
+        2.0 * (1.0 - luminance / value)
+    };
+
+    (hue, saturation, value, alpha)
+}
+
+/// Convert HSVA to HSLA. The hue and alpha are the same in both.
+pub fn hsva_to_hsla(hue: f64, saturation: f64, value: f64, alpha: f64) -> (f64, f64, f64, f64) {
+    let luminance = value * (1.0 - saturation / 2.0);
+    let saturation = if f64_eq(luminance, 0.0) || f64_eq(luminance, 1.0) {
+        0.0
+    } else {
+        (value - luminance) / f64::min(luminance, 1.0 - luminance)
+    };
+
+    (hue, saturation, luminance, alpha)
+}
+
 fn f64_max3(a: f64, b: f64, c: f64) -> f64 {
     f64::max(f64::max(a, b), c)
 }
@@ -186,14 +276,14 @@ mod tests {
     #[test]
     fn it_converts_rgba8_to_hsla() {
         let expected = (0.58, 0.47, 0.59, 1.0);
-        let got = _rgba8_to_hsla(100, 150, 200, 255);
+        let got = rgba8_to_hsla(100, 150, 200, 255);
         assert_approx_eq(expected, got);
     }
 
     #[test]
     fn it_converts_rgba8_to_hsla_gray() {
         let expected = (0.0, 0.0, 0.39, 1.0);
-        let got = _rgba8_to_hsla(100, 100, 100, 255);
+        let got = rgba8_to_hsla(100, 100, 100, 255);
         assert_approx_eq(expected, got);
     }
 
@@ -224,4 +314,17 @@ mod tests {
         let got = hsla_to_rgba(0.58, 0.0, 0.59, 1.0);
         assert_approx_eq(expected, got);
     }
+
+    #[test]
+    fn it_converts_hsla_to_hsva_and_back() {
+        let expected = (0.58, 0.5, 0.78, 1.0);
+        let got = hsla_to_hsva(0.58, 0.47, 0.59, 1.0);
+        assert_approx_eq(expected, got);
+        assert_approx_eq(
+            (0.58, 0.47, 0.59, 1.0),
+            hsva_to_hsla(got.0, got.1, got.2, got.3),
+        );
+        assert_approx_eq((0.0, 0.0, 0.0, 1.0), hsla_to_hsva(0.0, 1.0, 0.0, 1.0));
+        assert_approx_eq((0.0, 0.0, 1.0, 1.0), hsva_to_hsla(0.0, 0.0, 1.0, 1.0));
+    }
 }
@@ -21,6 +21,14 @@ pub fn enclosing_rect(a: druid::Point, b: druid::Point) -> druid::Rect {
     druid::Rect::new(x0, y0, x1, y1)
 }
 
+/// Get the smallest rectangle that holds all of the given points. Returns None if there
+/// aren't any points.
+pub fn bounding_rect(points: impl Iterator<Item = druid::Point>) -> Option<druid::Rect> {
+    points
+        .map(|p| druid::Rect::from_points(p, p))
+        .reduce(|a, b| a.union(b))
+}
+
 pub fn inflate_rect(rect: druid::Rect) -> druid::Rect {
     rect.with_size((rect.width() + 1.0, rect.height() + 1.0))
 }
@@ -60,3 +68,225 @@ pub fn constrain_rect(rect: druid::Rect, bounds: druid::Rect) -> druid::Rect {
 
     druid::Rect::from_points(tl, br)
 }
+
+/// Constrain the end of a line to the nearest horizontal, vertical or 45 degree angle.
+pub fn constrain_line(a: druid::Point, b: druid::Point) -> druid::Point {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+
+    if dy.abs() * 2.0 < dx.abs() {
+        druid::Point::new(b.x, a.y)
+    } else if dx.abs() * 2.0 < dy.abs() {
+        druid::Point::new(a.x, b.y)
+    } else {
+        let d = dx.abs().max(dy.abs());
+        druid::Point::new(a.x + d.copysign(dx), a.y + d.copysign(dy))
+    }
+}
+
+/// Constrain the corner of a rectangle so that it makes a square.
+pub fn constrain_square(a: druid::Point, b: druid::Point) -> druid::Point {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let d = dx.abs().max(dy.abs());
+    druid::Point::new(a.x + d.copysign(dx), a.y + d.copysign(dy))
+}
+
+/// Get the pixels along a line from a to b, both ends included. This is Bresenham's
+/// algorithm, so there are no gaps and no doubled up pixels.
+pub fn line(a: druid::Point, b: druid::Point) -> Vec<druid::Point> {
+    let (mut x0, mut y0) = (a.x as i64, a.y as i64);
+    let (x1, y1) = (b.x as i64, b.y as i64);
+
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+
+    let mut points = Vec::new();
+    loop {
+        points.push(druid::Point::new(x0 as f64, y0 as f64));
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y0 += sy;
+        }
+    }
+
+    points
+}
+
+/// Get the pixels of a rectangle, given two opposite corners.
+pub fn rectangle(a: druid::Point, b: druid::Point, filled: bool) -> Vec<druid::Point> {
+    let r = enclosing_rect(a, b);
+    let outline = [
+        line(druid::Point::new(r.x0, r.y0), druid::Point::new(r.x1, r.y0)),
+        line(druid::Point::new(r.x0, r.y1), druid::Point::new(r.x1, r.y1)),
+        line(druid::Point::new(r.x0, r.y0), druid::Point::new(r.x0, r.y1)),
+        line(druid::Point::new(r.x1, r.y0), druid::Point::new(r.x1, r.y1)),
+    ]
+    .concat();
+
+    finish(outline, filled)
+}
+
+/// Get the pixels of an ellipse that fits inside the rectangle with the given opposite
+/// corners. This is a midpoint algorithm that works on the bounding rectangle rather than
+/// the centre, so it copes with even widths and heights too.
+pub fn ellipse(a: druid::Point, b: druid::Point, filled: bool) -> Vec<druid::Point> {
+    let r = enclosing_rect(a, b);
+    let (mut x0, mut y0, mut x1, mut y1) = (r.x0 as i64, r.y0 as i64, r.x1 as i64, r.y1 as i64);
+
+    let mut outline = Vec::new();
+    let mut plot = |x: i64, y: i64| outline.push(druid::Point::new(x as f64, y as f64));
+
+    let (w, h) = (x1 - x0, y1 - y0);
+    let h1 = h & 1;
+    let mut dx = 4 * (1 - w) * h * h;
+    let mut dy = 4 * (h1 + 1) * w * w;
+    let mut err = dx + dy + h1 * w * w;
+
+    y0 += (h + 1) / 2;
+    y1 = y0 - h1;
+    let (step_x, step_y) = (8 * h * h, 8 * w * w);
+
+    loop {
+        plot(x1, y0);
+        plot(x0, y0);
+        plot(x0, y1);
+        plot(x1, y1);
+
+        let e2 = 2 * err;
+        if e2 <= dy {
+            y0 += 1;
+            y1 -= 1;
+            dy += step_y;
+            err += dy;
+        }
+        if e2 >= dx || 2 * err > dy {
+            x0 += 1;
+            x1 -= 1;
+            dx += step_x;
+            err += dx;
+        }
+        if x0 > x1 {
+            break;
+        }
+    }
+
+    // Very flat ellipses stop early, so finish off the tips.
+    while y0 - y1 < h {
+        plot(x0 - 1, y0);
+        plot(x1 + 1, y0);
+        y0 += 1;
+        plot(x0 - 1, y1);
+        plot(x1 + 1, y1);
+        y1 -= 1;
+    }
+
+    finish(outline, filled)
+}
+
+/// Sort the outline of a shape row by row, and drop any doubled up pixels. If the shape is
+/// filled, then each row is filled in from one side to the other.
+fn finish(mut points: Vec<druid::Point>, filled: bool) -> Vec<druid::Point> {
+    points.sort_by(|a, b| (a.y, a.x).partial_cmp(&(b.y, b.x)).unwrap());
+    points.dedup();
+
+    if !filled {
+        return points;
+    }
+
+    let mut filled_points = Vec::new();
+    let mut i = 0;
+    while i < points.len() {
+        let mut j = i;
+        while j + 1 < points.len() && points[j + 1].y == points[i].y {
+            j += 1;
+        }
+        for x in points[i].x as i64..points[j].x as i64 + 1 {
+            filled_points.push(druid::Point::new(x as f64, points[i].y));
+        }
+        i = j + 1;
+    }
+    filled_points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f64, y: f64) -> druid::Point {
+        druid::Point::new(x, y)
+    }
+
+    // Draw the points as rows of text, to make the tests easier to read.
+    fn rows(points: &[druid::Point], width: usize, height: usize) -> Vec<String> {
+        (1..height + 1)
+            .map(|y| {
+                (1..width + 1)
+                    .map(|x| {
+                        if points.contains(&p(x as f64, y as f64)) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_draws_lines() {
+        let points = line(p(1.0, 1.0), p(5.0, 3.0));
+        assert_eq!(vec!["#....", ".##..", "...##"], rows(&points, 5, 3));
+
+        let points = line(p(3.0, 3.0), p(1.0, 1.0));
+        assert_eq!(vec!["#..", ".#.", "..#"], rows(&points, 3, 3));
+        assert_eq!(1, line(p(2.0, 2.0), p(2.0, 2.0)).len());
+    }
+
+    #[test]
+    fn it_constrains() {
+        assert_eq!(p(9.0, 1.0), constrain_line(p(1.0, 1.0), p(9.0, 3.0)));
+        assert_eq!(p(1.0, 9.0), constrain_line(p(1.0, 1.0), p(3.0, 9.0)));
+        assert_eq!(p(-5.0, 7.0), constrain_line(p(1.0, 1.0), p(-5.0, 5.0)));
+        assert_eq!(p(5.0, -3.0), constrain_square(p(1.0, 1.0), p(3.0, -3.0)));
+    }
+
+    #[test]
+    fn it_draws_rectangles() {
+        let points = rectangle(p(4.0, 3.0), p(1.0, 1.0), false);
+        assert_eq!(vec!["####", "#..#", "####"], rows(&points, 4, 3));
+        assert_eq!(10, points.len());
+
+        let points = rectangle(p(1.0, 1.0), p(4.0, 3.0), true);
+        assert_eq!(12, points.len());
+    }
+
+    #[test]
+    fn it_draws_ellipses() {
+        let points = ellipse(p(1.0, 1.0), p(7.0, 5.0), false);
+        assert_eq!(
+            vec!["..###..", ".#...#.", "#.....#", ".#...#.", "..###.."],
+            rows(&points, 7, 5)
+        );
+
+        let points = ellipse(p(1.0, 1.0), p(6.0, 4.0), true);
+        assert_eq!(
+            vec![".####.", "######", "######", ".####."],
+            rows(&points, 6, 4)
+        );
+
+        let points = ellipse(p(1.0, 1.0), p(5.0, 1.0), false);
+        assert_eq!(vec!["#####"], rows(&points, 5, 1));
+    }
+}
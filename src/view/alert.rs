@@ -23,6 +23,10 @@ use super::theme;
 use crate::common::commands;
 use crate::common::constants;
 use crate::model::app::AppState;
+use crate::model::color_picker::ColorPicker;
+use crate::model::new_image;
+use crate::model::new_image::NewImageOptions;
+use crate::model::sheet::SliceOptions;
 use crate::model::types::*;
 
 pub fn open_failed(parent_pos: druid::Point, e: impl Error) -> druid::WindowDesc<AppState> {
@@ -98,6 +102,358 @@ pub fn unsaved_file(parent_pos: druid::Point) -> druid::WindowDesc<AppState> {
     build_alert(parent_pos, theme::UNSAVED_FILE_ALERT_SIZE, panel)
 }
 
+/// Build a color picker window. The sliders and the hex field edit the brush color directly,
+/// as RGB, HSL or HSV. Cancel puts back the color that the picker started with.
+pub fn color_picker(parent_pos: druid::Point) -> druid::WindowDesc<AppState> {
+    fn slider(
+        label: &str,
+        max: f64,
+        get: fn(&ColorPicker) -> f64,
+        put: fn(&mut ColorPicker, f64),
+    ) -> impl druid::Widget<AppState> {
+        let value = druid::lens::Map::new(
+            move |data: &AppState| get(data.color_picker()) * max,
+            move |data: &mut AppState, value: f64| {
+                data.modify_color_picker(|picker| put(picker, value / max))
+            },
+        );
+
+        let readout = druid::widget::Label::new(move |data: &AppState, _env: &_| {
+            format!("{:.0}", get(data.color_picker()) * max)
+        })
+        .with_text_color(druid::Color::BLACK)
+        .with_font(theme::ALERT_MESSAGE_FONT);
+
+        let slider = druid::widget::Slider::new()
+            .with_range(0.0, max)
+            .lens(value);
+
+        Flex::row()
+            .with_child(build_message(label, false).fix_width(theme::ALERT_LABEL_WIDTH))
+            .with_flex_child(slider.expand_width(), 1.0)
+            .with_child(readout.fix_width(theme::ALERT_LABEL_WIDTH * 2.0))
+    }
+
+    fn swatch(color: fn(&AppState) -> &druid::Color) -> impl druid::Widget<AppState> {
+        druid::widget::Painter::new(move |ctx, data: &AppState, _env| {
+            let rect = ctx.size().to_rect();
+            ctx.fill(rect, color(data));
+        })
+        .fix_size(theme::COLOR_WELL_SIZE.width, theme::COLOR_WELL_SIZE.height)
+        .border(theme::COLOR_WELL_STROKE, 1.0)
+    }
+
+    let message = build_message("Brush Color", true);
+
+    let swatches = Flex::row()
+        .with_child(swatch(|data| data.color_picker().original()))
+        .with_default_spacer()
+        .with_child(swatch(|data| data.brush_color()));
+
+    let hex = druid::lens::Map::new(
+        |data: &AppState| data.color_picker().rgba_u32(),
+        |data: &mut AppState, value: u32| {
+            // The text box only deals in RGB, so hang on to the alpha.
+            let old_value = data.color_picker().rgba_u32();
+            if value >> 8 != old_value >> 8 {
+                let value = (value & !0xff) | (old_value & 0xff);
+                data.modify_color_picker(|picker| picker.set_rgba_u32(value));
+            }
+        },
+    );
+    let hex_box = druid::widget::TextBox::new()
+        .with_formatter(HexFormatter)
+        .lens(hex);
+    let hex_row = Flex::row()
+        .with_flex_child(build_message("Hex", false).expand_width(), 1.0)
+        .with_default_spacer()
+        .with_child(hex_box.fix_width(theme::ALERT_FIELD_WIDTH));
+
+    let done = Button::new("Done", true).on_click(dismiss);
+
+    let cancel = Button::new("Cancel", false).on_click(|ctx, data, env| {
+        let original = data.color_picker().original().clone();
+        data.set_brush_color(original);
+        dismiss(ctx, data, env);
+    });
+
+    let panel = Flex::column()
+        .with_child(message)
+        .with_default_spacer()
+        .with_child(swatches)
+        .with_default_spacer()
+        .with_child(build_message("RGB", false))
+        .with_child(slider("R", 255.0, ColorPicker::red, ColorPicker::set_red))
+        .with_child(slider(
+            "G",
+            255.0,
+            ColorPicker::green,
+            ColorPicker::set_green,
+        ))
+        .with_child(slider("B", 255.0, ColorPicker::blue, ColorPicker::set_blue))
+        .with_default_spacer()
+        .with_child(build_message("HSL", false))
+        .with_child(slider("H", 360.0, ColorPicker::hue, ColorPicker::set_hue))
+        .with_child(slider(
+            "S",
+            100.0,
+            ColorPicker::hsl_saturation,
+            ColorPicker::set_hsl_saturation,
+        ))
+        .with_child(slider(
+            "L",
+            100.0,
+            ColorPicker::luminance,
+            ColorPicker::set_luminance,
+        ))
+        .with_default_spacer()
+        .with_child(build_message("HSV", false))
+        .with_child(slider("H", 360.0, ColorPicker::hue, ColorPicker::set_hue))
+        .with_child(slider(
+            "S",
+            100.0,
+            ColorPicker::hsv_saturation,
+            ColorPicker::set_hsv_saturation,
+        ))
+        .with_child(slider(
+            "V",
+            100.0,
+            ColorPicker::value,
+            ColorPicker::set_value,
+        ))
+        .with_default_spacer()
+        .with_child(slider(
+            "A",
+            255.0,
+            ColorPicker::alpha,
+            ColorPicker::set_alpha,
+        ))
+        .with_default_spacer()
+        .with_child(hex_row)
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(done.expand_width())
+        .with_default_spacer()
+        .with_child(cancel.expand_width());
+
+    build_alert(parent_pos, theme::COLOR_PICKER_ALERT_SIZE, panel)
+}
+
+/// Build a frame tag window. The text box edits the tag of the current frame directly, so
+/// there's nothing to apply when it's dismissed.
+pub fn frame_tag(parent_pos: druid::Point) -> druid::WindowDesc<AppState> {
+    let message = build_message("Frame Tag", true);
+    let sub_message = build_message("Frames with the same tag make up a sprite.", false);
+
+    let tag = druid::lens::Map::new(
+        |data: &AppState| {
+            let doc = data.doc();
+            let tag = doc.frame(doc.current_frame()).tag();
+            tag.unwrap_or_default().to_string()
+        },
+        |data: &mut AppState, tag: String| data.doc_mut().set_frame_tag(&tag),
+    );
+
+    let text_box = druid::widget::TextBox::new()
+        .with_placeholder("Untagged")
+        .lens(tag);
+
+    let done = Button::new("Done", true).on_click(dismiss);
+
+    let panel = Flex::column()
+        .with_child(message)
+        .with_default_spacer()
+        .with_child(sub_message)
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(text_box.expand_width())
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(done.expand_width());
+
+    build_alert(parent_pos, theme::FRAME_TAG_ALERT_SIZE, panel)
+}
+
+/// Build a palette color window. The text box edits the color at the given index directly,
+/// as hex, so there's nothing to apply when it's dismissed.
+pub fn palette_color(parent_pos: druid::Point, idx: usize) -> druid::WindowDesc<AppState> {
+    let message = build_message("Palette Color", true);
+    let sub_message = build_message("Enter the color as six hex digits (RRGGBB).", false);
+
+    let value = druid::lens::Map::new(
+        move |data: &AppState| {
+            let values = data.doc().palette().values();
+            values.get(idx).copied().unwrap_or_default()
+        },
+        move |data: &mut AppState, value: u32| {
+            // The text box only deals in RGB, so hang on to the alpha.
+            let old_value = data.doc().palette().values()[idx];
+            if value >> 8 != old_value >> 8 {
+                let value = (value & !0xff) | (old_value & 0xff);
+                data.doc_mut().set_palette_color(idx, value);
+            }
+        },
+    );
+
+    let text_box = druid::widget::TextBox::new()
+        .with_formatter(HexFormatter)
+        .lens(value);
+
+    let done = Button::new("Done", true).on_click(dismiss);
+
+    let panel = Flex::column()
+        .with_child(message)
+        .with_default_spacer()
+        .with_child(sub_message)
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(text_box.expand_width())
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(done.expand_width());
+
+    build_alert(parent_pos, theme::PALETTE_COLOR_ALERT_SIZE, panel)
+}
+
+/// Build a sprite sheet import window. The fields edit the slice options directly; the
+/// buttons decide what to do with the cells.
+pub fn sheet_import(parent_pos: druid::Point) -> druid::WindowDesc<AppState> {
+    fn field(
+        label: &str,
+        get: fn(&SliceOptions) -> usize,
+        put: fn(&mut SliceOptions, usize),
+    ) -> impl druid::Widget<AppState> {
+        let value = druid::lens::Map::new(
+            move |data: &AppState| get(data.slice_options()),
+            move |data: &mut AppState, value: usize| put(data.slice_options_mut(), value),
+        );
+
+        build_field(label, value)
+    }
+
+    let message = build_message("Import Sprite Sheet", true);
+    let sub_message = build_message("Cells are read left to right, top to bottom.", false);
+
+    let frames =
+        Button::new("Import as Frames", true).on_click(submit(commands::SHEET_IMPORT_FRAMES));
+    let files =
+        Button::new("Slice into Files", false).on_click(submit(commands::SHEET_IMPORT_FILES));
+    let cancel = Button::new("Cancel", false).on_click(dismiss);
+
+    let panel = Flex::column()
+        .with_child(message)
+        .with_default_spacer()
+        .with_child(sub_message)
+        .with_default_spacer()
+        .with_child(field(
+            "Cell Width",
+            SliceOptions::cell_width,
+            SliceOptions::set_cell_width,
+        ))
+        .with_default_spacer()
+        .with_child(field(
+            "Cell Height",
+            SliceOptions::cell_height,
+            SliceOptions::set_cell_height,
+        ))
+        .with_default_spacer()
+        .with_child(field(
+            "Margin",
+            SliceOptions::margin,
+            SliceOptions::set_margin,
+        ))
+        .with_default_spacer()
+        .with_child(field(
+            "Spacing",
+            SliceOptions::spacing,
+            SliceOptions::set_spacing,
+        ))
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(frames.expand_width())
+        .with_default_spacer()
+        .with_child(files.expand_width())
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(cancel.expand_width());
+
+    build_alert(parent_pos, theme::SHEET_IMPORT_ALERT_SIZE, panel)
+}
+
+/// Build a new image window. Presets fill in the width and height, which can then be
+/// tweaked by hand.
+pub fn new_image(parent_pos: druid::Point) -> druid::WindowDesc<AppState> {
+    fn field(
+        label: &str,
+        get: fn(&NewImageOptions) -> usize,
+        put: fn(&mut NewImageOptions, usize),
+    ) -> impl druid::Widget<AppState> {
+        let value = druid::lens::Map::new(
+            move |data: &AppState| get(data.new_image_options()),
+            move |data: &mut AppState, value: usize| put(data.new_image_options_mut(), value),
+        );
+
+        build_field(label, value)
+    }
+
+    let message = build_message("New Image", true);
+
+    let mut presets = Flex::row();
+    for &(label, width, height) in new_image::PRESETS.iter() {
+        let preset = Button::new(label, false).on_click(move |_ctx, data, _env| {
+            data.new_image_options_mut().set_size(width, height);
+        });
+        presets.add_flex_child(preset.expand_width(), 1.0);
+    }
+
+    let background = druid::lens::Map::new(
+        |data: &AppState| data.new_image_options().background(),
+        |data: &mut AppState, background: Background| {
+            data.new_image_options_mut().set_background(background)
+        },
+    );
+    let backgrounds = druid::widget::RadioGroup::new(vec![
+        ("Transparent", Background::Transparent),
+        ("White", Background::White),
+        ("Black", Background::Black),
+        ("Brush Color", Background::Brush),
+    ])
+    .env_scope(|env, _data| env.set(druid::theme::LABEL_COLOR, druid::Color::BLACK))
+    .lens(background);
+
+    let create = Button::new("Create", true).on_click(submit(commands::NEW_FILE_INTERNAL));
+    let cancel = Button::new("Cancel", false).on_click(dismiss);
+
+    let panel = Flex::column()
+        .with_child(message)
+        .with_default_spacer()
+        .with_child(presets)
+        .with_default_spacer()
+        .with_child(field(
+            "Width",
+            NewImageOptions::width,
+            NewImageOptions::set_width,
+        ))
+        .with_default_spacer()
+        .with_child(field(
+            "Height",
+            NewImageOptions::height,
+            NewImageOptions::set_height,
+        ))
+        .with_default_spacer()
+        .with_child(build_message("Background", false))
+        .with_default_spacer()
+        .with_child(backgrounds)
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(create.expand_width())
+        .with_default_spacer()
+        .with_default_spacer()
+        .with_child(cancel.expand_width());
+
+    build_alert(parent_pos, theme::NEW_IMAGE_ALERT_SIZE, panel)
+}
+
 fn build_alert(
     parent_pos: druid::Point,
     size: druid::Size,
@@ -127,6 +483,21 @@ fn build_warning_icon() -> druid::widget::SizedBox<AppState> {
     druid::widget::Image::new(data).fix_size(width, height)
 }
 
+/// Build a labelled number field.
+fn build_field(
+    label: &str,
+    value: impl druid::Lens<AppState, usize> + 'static,
+) -> impl druid::Widget<AppState> {
+    let text_box = druid::widget::TextBox::new()
+        .with_formatter(druid::text::format::ParseFormatter::new())
+        .lens(value);
+
+    Flex::row()
+        .with_flex_child(build_message(label, false).expand_width(), 1.0)
+        .with_default_spacer()
+        .with_child(text_box.fix_width(theme::ALERT_FIELD_WIDTH))
+}
+
 fn build_message(message: &str, bold: bool) -> druid::widget::Label<AppState> {
     let font = if bold {
         theme::ALERT_MESSAGE_FONT_BOLD
@@ -150,6 +521,58 @@ fn center(parent_pos: druid::Point, parent_size: druid::Size, size: druid::Size)
     druid::Point::new(center.x - size.width / 2.0, center.y - size.width / 2.0)
 }
 
+/// Formats palette and color picker values as RRGGBB hex. The alpha isn't shown, and it
+/// always comes back as opaque.
+struct HexFormatter;
+
+impl druid::text::format::Formatter<u32> for HexFormatter {
+    fn format(&self, value: &u32) -> String {
+        format!("{:06x}", value >> 8)
+    }
+
+    fn validate_partial_input(
+        &self,
+        input: &str,
+        _sel: &druid::text::Selection,
+    ) -> druid::text::format::Validation {
+        let digits = input.trim_start_matches('#');
+        if digits.len() <= 6 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            druid::text::format::Validation::success()
+        } else {
+            druid::text::format::Validation::failure(BadHexColor)
+        }
+    }
+
+    fn value(&self, input: &str) -> Result<u32, druid::text::format::ValidationError> {
+        let digits = input.trim_start_matches('#');
+        match u32::from_str_radix(digits, 16) {
+            Ok(rgb) if digits.len() == 6 => Ok((rgb << 8) | 0xff),
+            _ => Err(druid::text::format::ValidationError::new(BadHexColor)),
+        }
+    }
+}
+
+/// Hex colors need six digits, and nothing else.
+#[derive(Debug)]
+struct BadHexColor;
+
+impl std::fmt::Display for BadHexColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Expected six hex digits")
+    }
+}
+
+impl Error for BadHexColor {}
+
+/// Close the alert, then send the given command to the app window.
+fn submit(cmd: druid::Selector) -> impl Fn(&mut EventCtx, &mut AppState, &Env) {
+    move |ctx, data, _env| {
+        data.reset_window_state();
+        ctx.submit_command(druid::commands::CLOSE_WINDOW);
+        ctx.submit_command(cmd.to(data.window_id()));
+    }
+}
+
 fn dismiss(ctx: &mut EventCtx, data: &mut AppState, _env: &Env) {
     data.reset_window_state();
     ctx.submit_command(druid::commands::CLOSE_WINDOW);
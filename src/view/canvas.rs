@@ -16,67 +16,71 @@ use druid::widget::prelude::*;
 
 use crate::common::commands;
 use crate::model::app::AppState;
+use crate::model::compositor;
+use crate::model::pixels::PixelState;
 use crate::model::types::*;
 use crate::util::shapes;
 use crate::view::theme;
 
-/// A canvas that allows for the display and modification of pixels. The size is currently
-/// fixed at 32x32.
+/// A canvas that allows for the display and modification of pixels. The canvas grows and
+/// shrinks to fit the image at the current zoom, up to a maximum size; images bigger than
+/// that can be panned within it.
 pub struct Canvas {
-    long_dash: [druid::piet::StrokeStyle; 2],
     short_dash: [druid::piet::StrokeStyle; 2],
+    space_down: bool,
+    pan_pos: Option<druid::Point>,
+    wheel_delta: f64,
+    ants_offset: f64,
+    ants_timer: druid::TimerToken,
+    lasso: Vec<druid::Point>,
+    constrain: bool,
+    flattened: Option<druid::piet::PietImage>,
+    backdrop: Option<Vec<u8>>,
 }
 
 impl Canvas {
     /// Create an empty canvas.
     pub fn new() -> Self {
         Self {
-            long_dash: [
-                druid::piet::StrokeStyle::new().dash(vec![4.0], 0.0),
-                druid::piet::StrokeStyle::new().dash(vec![4.0], 4.0),
-            ],
             short_dash: [
                 druid::piet::StrokeStyle::new().dash(vec![1.0], 0.0),
                 druid::piet::StrokeStyle::new().dash(vec![1.0], 1.0),
             ],
+            space_down: false,
+            pan_pos: None,
+            wheel_delta: 0.0,
+            ants_offset: 0.0,
+            ants_timer: druid::TimerToken::INVALID,
+            lasso: Vec::new(),
+            constrain: false,
+            flattened: None,
+            backdrop: None,
         }
     }
 
     /// Translate from screen coordinates (typically the mouse position) to canvas coordinates.
-    fn screen_coords_to_canvas_coords(pos: druid::Point) -> Option<druid::Point> {
-        if pos.x < 1.0 || pos.y < 1.0 {
+    fn screen_coords_to_canvas_coords(data: &AppState, pos: druid::Point) -> Option<druid::Point> {
+        if !view_rect(data).contains(pos) {
             return None;
         }
 
-        let x = pos.x as usize / (theme::CANVAS_PIXEL_SIZE as usize) + 1;
-        let y = pos.y as usize / (theme::CANVAS_PIXEL_SIZE as usize) + 1;
-        if x > theme::CANVAS_DIMS || y > theme::CANVAS_DIMS {
+        let p = data.viewport().to_canvas(pos);
+        let header = data.doc().header();
+        if p.x < 1.0 || p.y < 1.0 || p.x > header.width() as f64 || p.y > header.height() as f64 {
             return None;
         }
 
-        Some(druid::Point::new(x as f64, y as f64))
+        Some(p)
     }
 
     /// Translate from canvas coordinates to screen coordinates.
-    fn canvas_coords_to_screen_coords(x: usize, y: usize) -> druid::Point {
-        Self::canvas_coords_to_screen_coords_f64(x as f64, y as f64)
+    fn canvas_coords_to_screen_coords(data: &AppState, x: usize, y: usize) -> druid::Point {
+        Self::canvas_coords_to_screen_coords_f64(data, x as f64, y as f64)
     }
 
-    fn canvas_coords_to_screen_coords_f64(x: f64, y: f64) -> druid::Point {
+    fn canvas_coords_to_screen_coords_f64(data: &AppState, x: f64, y: f64) -> druid::Point {
         assert!(x > 0.0 && y > 0.0);
-        druid::Point::new(
-            1.0 + ((x - 1.0) * theme::CANVAS_PIXEL_SIZE),
-            1.0 + ((y - 1.0) * theme::CANVAS_PIXEL_SIZE),
-        )
-    }
-
-    /// Convert an index within the canvas storage to a rectanble in screen coordinates.
-    fn screen_rect(x: usize, y: usize) -> druid::Rect {
-        let fx = x as f64;
-        let fy = y as f64;
-        let origin = Self::canvas_coords_to_screen_coords_f64(fx, fy);
-
-        druid::Rect::from_origin_size(origin, (theme::CANVAS_PIXEL_SIZE, theme::CANVAS_PIXEL_SIZE))
+        data.viewport().to_screen(x, y)
     }
 
     /// Paint border. The canvas does this internally instead of via border() because the
@@ -88,74 +92,192 @@ impl Canvas {
         ctx.stroke(rect, &color, 1.0);
     }
 
-    fn paint_pixel(ctx: &mut PaintCtx, x: usize, y: usize, color: &druid::Color) {
-        let rect = Self::screen_rect(x, y);
-
-        let (_, _, _, a) = color.as_rgba8();
-        if a != 255 {
-            let fill_color = if (x + y) % 2 == 0 {
-                theme::CANVAS_FILL_DARK
-            } else {
-                theme::CANVAS_FILL_LIGHT
-            };
-            ctx.fill(rect, &fill_color);
-        }
+    /// Flatten the document with the floating pixels dropped into it, so that they get
+    /// composited along with everything else. The copy is cheap, because the layers are
+    /// shared until we write to them.
+    fn flatten_moving(data: &AppState) -> PixelState {
+        let mut doc = data.doc().clone();
+        let move_info = doc.move_info().unwrap().to_owned();
+        doc.write_selection(move_info.pixels().bytes());
+        doc.flatten()
+    }
 
-        ctx.fill(rect, color);
+    /// Make an image of flattened pixels, i.e. all the layers composited together, on top
+    /// of the backdrop. Everything is composited up front, so that we only have to draw a
+    /// single image, however big it is.
+    fn make_flattened(
+        &mut self,
+        ctx: &mut PaintCtx,
+        data: &AppState,
+        pixels: &PixelState,
+    ) -> druid::piet::PietImage {
+        let mut bytes = self
+            .backdrop
+            .get_or_insert_with(|| Self::make_backdrop(data))
+            .clone();
+
+        Self::composite(&mut bytes, pixels, |src| Some((src, 1.0)));
+
+        let header = pixels.header();
+        ctx.make_image(
+            header.width(),
+            header.height(),
+            &bytes,
+            druid::piet::ImageFormat::RgbaSeparate,
+        )
+        .unwrap()
     }
 
-    fn paint_pixels_moving(&self, ctx: &mut PaintCtx, data: &AppState) {
-        let header = data.doc().pixels().header();
+    /// Make the backdrop: the checkerboard, with the onion skin (if enabled) on top. It only
+    /// depends on the other frames, so it's kept while painting the current one.
+    fn make_backdrop(data: &AppState) -> Vec<u8> {
+        let header = data.doc().header();
         let height = header.height();
         let width = header.width();
 
-        let move_info = data.doc().move_info().unwrap();
-        let selection = shapes::inflate_rect(data.doc().selection().unwrap());
-
+        let mut bytes = Vec::with_capacity(width * height * 4);
         for y in 1..height + 1 {
             for x in 1..width + 1 {
-                let p = druid::Point::new(x as f64, y as f64);
-
-                let color = if selection.contains(p) {
-                    let off_x = (x - selection.x0 as usize) + 1;
-                    let off_y = (y - selection.y0 as usize) + 1;
-                    move_info.pixels().read_xy_unchecked(off_x, off_y)
+                let fill_color = if (x + y) % 2 == 0 {
+                    theme::CANVAS_FILL_DARK
                 } else {
-                    data.doc().pixels().read_xy_unchecked(x, y)
+                    theme::CANVAS_FILL_LIGHT
                 };
-
-                Self::paint_pixel(ctx, x, y, &color);
+                let (r, g, b, a) = fill_color.as_rgba8();
+                bytes.extend_from_slice(&[r, g, b, a]);
             }
         }
+
+        Self::paint_onion_skin(&mut bytes, data);
+        bytes
     }
 
-    fn paint_pixels_static(&self, ctx: &mut PaintCtx, data: &AppState) {
-        let header = data.doc().pixels().header();
-        let height = header.height();
-        let width = header.width();
+    /// Has anything that the backdrop depends on changed? That's the onion skin frames, and
+    /// anything that affects how they flatten.
+    fn backdrop_changed(old_data: &AppState, data: &AppState) -> bool {
+        let (old, new) = (old_data.doc(), data.doc());
+        let frames = data.onion_skin_frames();
+        if frames != old_data.onion_skin_frames()
+            || old.current_frame() != new.current_frame()
+            || !old.header().same(new.header())
+            || !old.palette().same(new.palette())
+            || old.layers().len() != new.layers().len()
+            || old
+                .layers()
+                .iter()
+                .zip(new.layers())
+                .any(|(a, b)| !a.same(b))
+        {
+            return true;
+        }
 
-        for y in 1..height + 1 {
-            for x in 1..width + 1 {
-                let color = data.doc().pixels().read_xy_unchecked(x, y);
-                Self::paint_pixel(ctx, x, y, &color);
+        let current = new.current_frame() as isize;
+        frames.iter().any(|offset| {
+            let idx = (current + offset) as usize;
+            match old.frames().get(idx) {
+                Some(frame) => !frame.same(new.frame(idx)),
+                None => true,
+            }
+        })
+    }
+
+    /// Composite pixels onto the given backdrop. The callback can adjust each source pixel,
+    /// and its opacity, or skip it entirely.
+    fn composite<F>(bytes: &mut [u8], pixels: &PixelState, f: F)
+    where
+        F: Fn([u8; 4]) -> Option<([u8; 4], f64)>,
+    {
+        let src_bytes = pixels.bytes();
+
+        for (dst, src) in bytes.chunks_exact_mut(4).zip(src_bytes.chunks_exact(4)) {
+            if let Some((color, opacity)) = f([src[0], src[1], src[2], src[3]]) {
+                let blended = compositor::blend(
+                    [dst[0], dst[1], dst[2], dst[3]],
+                    color,
+                    opacity,
+                    BlendMode::Normal,
+                );
+                dst.copy_from_slice(&blended);
             }
         }
     }
 
+    /// Paint neighbouring frames, so that animators can line up motion between frames.
+    /// Each frame gets its own tint, and fades out the further away it is. We paint the
+    /// most distant frames first, so that the nearest ones end up on top.
+    fn paint_onion_skin(bytes: &mut [u8], data: &AppState) {
+        let doc = data.doc();
+        let current = doc.current_frame() as isize;
+
+        for offset in data.onion_skin_frames() {
+            let distance = offset.unsigned_abs();
+            let tint = if offset < 0 {
+                &theme::CANVAS_ONION_SKIN_BEFORE[distance - 1]
+            } else {
+                &theme::CANVAS_ONION_SKIN_AFTER[distance - 1]
+            };
+            let pixels = doc.flatten_frame((current + offset) as usize);
+            Self::paint_onion_skin_frame(bytes, &pixels, tint, distance);
+        }
+    }
+
+    fn paint_onion_skin_frame(
+        bytes: &mut [u8],
+        pixels: &PixelState,
+        tint: &druid::Color,
+        distance: usize,
+    ) {
+        fn mix(a: u8, b: u8) -> u8 {
+            ((a as u16 + b as u16) / 2) as u8
+        }
+
+        let opacity = theme::CANVAS_ONION_SKIN_OPACITY / distance as f64;
+        let (tint_r, tint_g, tint_b, _) = tint.as_rgba8();
+
+        Self::composite(bytes, pixels, |[r, g, b, a]| {
+            if a > 0 {
+                let color = [mix(r, tint_r), mix(g, tint_g), mix(b, tint_b), a];
+                Some((color, opacity))
+            } else {
+                None
+            }
+        });
+    }
+
     /// Paint pixels from storage onto the given render context. This will paint
-    /// on top of the checkboard. Pixel transparency is via alpha value.
-    fn paint_pixels(&self, ctx: &mut PaintCtx, data: &AppState) {
-        if data.doc().is_moving() {
-            self.paint_pixels_moving(ctx, data);
-        } else {
-            self.paint_pixels_static(ctx, data);
+    /// on top of the checkboard. Pixel transparency is via alpha value. The image is kept
+    /// until the pixels change, so that the marching ants don't flatten it all over again.
+    fn paint_pixels(&mut self, ctx: &mut PaintCtx, data: &AppState) {
+        if self.flattened.is_none() {
+            let pixels = if data.doc().is_moving() {
+                Self::flatten_moving(data)
+            } else {
+                data.doc().flatten()
+            };
+            self.flattened = Some(self.make_flattened(ctx, data, &pixels));
+        }
+
+        let header = data.doc().header();
+        let zoom = data.viewport().zoom() as f64;
+        let rect = druid::Rect::from_origin_size(
+            Self::canvas_coords_to_screen_coords(data, 1, 1),
+            (header.width() as f64 * zoom, header.height() as f64 * zoom),
+        );
+        if let Some(image) = &self.flattened {
+            ctx.draw_image(image, rect, druid::piet::InterpolationMode::NearestNeighbor);
         }
     }
 
     /// Paint a grid line onto the given render context.
-    fn paint_grid_line(&self, ctx: &mut PaintCtx, x0: usize, y0: usize, x1: usize, y1: usize) {
-        let a = Self::canvas_coords_to_screen_coords(x0, y0);
-        let b = Self::canvas_coords_to_screen_coords(x1, y1);
+    fn paint_grid_line(
+        &self,
+        ctx: &mut PaintCtx,
+        data: &AppState,
+        (x0, y0): (usize, usize),
+        (x1, y1): (usize, usize),
+    ) {
+        let a = Self::canvas_coords_to_screen_coords(data, x0, y0);
+        let b = Self::canvas_coords_to_screen_coords(data, x1, y1);
         let line = druid::kurbo::Line::new(a, b);
         ctx.stroke_styled(
             line,
@@ -174,44 +296,202 @@ impl Canvas {
     /// Paint the grid onto the given render context.
     fn paint_grid(&self, ctx: &mut PaintCtx, data: &AppState) {
         if data.show_grid() {
-            let header = data.doc().pixels().header();
+            let header = data.doc().header();
             let height = header.height();
             let width = header.width();
 
-            let num_lines = width / 8 + 1;
-            for i in 1..num_lines {
+            for i in 1..(width + 7) / 8 {
+                let offset = 1 + i * 8;
+                self.paint_grid_line(ctx, data, (offset, 1), (offset, height + 1));
+            }
+            for i in 1..(height + 7) / 8 {
                 let offset = 1 + i * 8;
-                self.paint_grid_line(ctx, offset, 1, offset, width + 1);
-                self.paint_grid_line(ctx, 1, offset, height + 1, offset);
+                self.paint_grid_line(ctx, data, (1, offset), (width + 1, offset));
             }
         }
     }
 
-    /// Paint the currently selected area onto the given render context.
+    /// Paint marching ants along the given shape. The dashes shift a little every time the
+    /// timer fires, which is what makes them march.
+    fn paint_ants(&self, ctx: &mut PaintCtx, shape: impl druid::kurbo::Shape) {
+        let dash = theme::CANVAS_ANTS_DASH;
+        let dark = druid::piet::StrokeStyle::new().dash(vec![dash], self.ants_offset);
+        let light = druid::piet::StrokeStyle::new().dash(vec![dash], self.ants_offset + dash);
+
+        ctx.stroke_styled(&shape, &theme::CANVAS_STROKE_SELECTED_DARK, 2.0, &dark);
+        ctx.stroke_styled(&shape, &theme::CANVAS_STROKE_SELECTED_LIGHT, 2.0, &light);
+    }
+
+    /// Paint the currently selected area onto the given render context. A rectangular
+    /// selection is outlined as such; otherwise we outline every edge of the mask.
     fn paint_selection(&self, ctx: &mut PaintCtx, data: &AppState) {
+        match (data.doc().selection(), data.doc().mask()) {
+            (Some(_), Some(mask)) => {
+                let area = mask.area();
+                let mut path = druid::kurbo::BezPath::new();
+                let mut edge = |x0: usize, y0: usize, x1: usize, y1: usize| {
+                    path.move_to(Self::canvas_coords_to_screen_coords(data, x0, y0));
+                    path.line_to(Self::canvas_coords_to_screen_coords(data, x1, y1));
+                };
+
+                for y in area.y0 as usize..area.y1 as usize + 1 {
+                    for x in area.x0 as usize..area.x1 as usize + 1 {
+                        if !mask.contains_xy(x, y) {
+                            continue;
+                        }
+                        if !mask.contains_xy(x, y - 1) {
+                            edge(x, y, x + 1, y);
+                        }
+                        if !mask.contains_xy(x, y + 1) {
+                            edge(x, y + 1, x + 1, y + 1);
+                        }
+                        if !mask.contains_xy(x - 1, y) {
+                            edge(x, y, x, y + 1);
+                        }
+                        if !mask.contains_xy(x + 1, y) {
+                            edge(x + 1, y, x + 1, y + 1);
+                        }
+                    }
+                }
+
+                self.paint_ants(ctx, path);
+            }
+
+            (Some(s), None) => self.paint_ants(ctx, Self::selection_rect(data, s)),
+
+            _ => {}
+        }
+    }
+
+    /// Get the screen rectangle that outlines the given selection rectangle.
+    fn selection_rect(data: &AppState, s: druid::Rect) -> druid::Rect {
+        let tl = Self::canvas_coords_to_screen_coords_f64(data, s.x0, s.y0);
+        let br = Self::canvas_coords_to_screen_coords_f64(data, s.x1, s.y1);
+
+        let zoom = data.viewport().zoom() as f64;
+        druid::Rect::new(tl.x, tl.y, br.x + zoom, br.y + zoom)
+    }
+
+    /// Get the screen area covered by marching ants, if there are any. That's all we need
+    /// to repaint when they march.
+    fn ants_rect(&self, data: &AppState) -> Option<druid::Rect> {
+        let mut points = self.lasso.clone();
         if let Some(s) = data.doc().selection() {
-            let tl = Self::canvas_coords_to_screen_coords_f64(s.x0, s.y0);
-            let br = Self::canvas_coords_to_screen_coords_f64(s.x1, s.y1);
-
-            let rect = druid::Rect::new(
-                tl.x,
-                tl.y,
-                br.x + theme::CANVAS_PIXEL_SIZE,
-                br.y + theme::CANVAS_PIXEL_SIZE,
-            );
-
-            ctx.stroke_styled(
-                rect,
-                &theme::CANVAS_STROKE_SELECTED_DARK,
-                2.0,
-                &self.long_dash[0],
-            );
-            ctx.stroke_styled(
-                rect,
-                &theme::CANVAS_STROKE_SELECTED_LIGHT,
-                2.0,
-                &self.long_dash[1],
-            );
+            points.push(s.origin());
+            points.push(druid::Point::new(s.x1, s.y1));
+        }
+        if points.is_empty() {
+            return None;
+        }
+
+        // The lasso's rubber band and the marquee both follow the mouse.
+        let start_pos = data.start_pos();
+        let current_pos = data.current_pos();
+        if current_pos != druid::Point::ZERO {
+            points.push(current_pos);
+        }
+        if data.tool_type() == ToolType::Marquee && start_pos != druid::Point::ZERO {
+            points.push(start_pos);
+        }
+
+        let first = druid::Rect::from_points(points[0], points[0]);
+        let bounds = points.iter().fold(first, |r, p| r.union_pt(*p));
+        Some(Self::selection_rect(data, bounds).inflate(2.0, 2.0))
+    }
+
+    /// Paint the marquee while it's being dragged. When replacing the selection, the
+    /// selection itself follows the mouse; otherwise it only changes on release, so we
+    /// need to show where it's going.
+    fn paint_marquee(&self, ctx: &mut PaintCtx, data: &AppState) {
+        let start_pos = data.start_pos();
+        if ctx.is_active()
+            && data.tool_type() == ToolType::Marquee
+            && data.selection_mode() != SelectionMode::Replace
+            && start_pos != druid::Point::ZERO
+        {
+            let rect = druid::Rect::from_points(start_pos, data.current_pos());
+            self.paint_ants(ctx, Self::selection_rect(data, rect));
+        }
+    }
+
+    /// Paint the lasso while it's being drawn. The polygonal lasso also gets a rubber band
+    /// out to the mouse.
+    fn paint_lasso(&self, ctx: &mut PaintCtx, data: &AppState) {
+        if self.lasso.is_empty() {
+            return;
+        }
+
+        let half = data.viewport().zoom() as f64 / 2.0;
+        let center = |p: &druid::Point| {
+            Self::canvas_coords_to_screen_coords_f64(data, p.x, p.y) + (half, half)
+        };
+
+        let mut path = druid::kurbo::BezPath::new();
+        path.move_to(center(&self.lasso[0]));
+        for p in self.lasso.iter().skip(1) {
+            path.line_to(center(p));
+        }
+
+        let current_pos = data.current_pos();
+        if data.tool_type() == ToolType::PolygonLasso && current_pos != druid::Point::ZERO {
+            path.line_to(center(&current_pos));
+        }
+
+        self.paint_ants(ctx, path);
+    }
+
+    /// Get the pixels of the shape being dragged out by the line, rectangle or ellipse
+    /// tools. Holding shift constrains lines to 45 degree angles, and rectangles and
+    /// ellipses to squares and circles.
+    fn shape(&self, data: &AppState) -> Vec<druid::Point> {
+        let start_pos = data.start_pos();
+        let current_pos = data.current_pos();
+        let filled = data.fill_shapes();
+
+        match data.tool_type() {
+            ToolType::Ellipse if self.constrain => shapes::ellipse(
+                start_pos,
+                shapes::constrain_square(start_pos, current_pos),
+                filled,
+            ),
+            ToolType::Ellipse => shapes::ellipse(start_pos, current_pos, filled),
+            ToolType::Line if self.constrain => {
+                shapes::line(start_pos, shapes::constrain_line(start_pos, current_pos))
+            }
+            ToolType::Line => shapes::line(start_pos, current_pos),
+            ToolType::Rectangle if self.constrain => shapes::rectangle(
+                start_pos,
+                shapes::constrain_square(start_pos, current_pos),
+                filled,
+            ),
+            ToolType::Rectangle => shapes::rectangle(start_pos, current_pos, filled),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Paint the shape while it's being dragged out. Nothing is written to the image until
+    /// the mouse is released.
+    fn paint_shape(&self, ctx: &mut PaintCtx, data: &AppState) {
+        if !ctx.is_active() || data.start_pos() == druid::Point::ZERO {
+            return;
+        }
+
+        let bounds = data.doc().bounds();
+        let zoom = data.viewport().zoom() as f64;
+        for p in self.shape(data) {
+            if bounds.contains(p) && data.doc().is_selected(p) {
+                let tl = Self::canvas_coords_to_screen_coords_f64(data, p.x, p.y);
+                let rect = druid::Rect::new(tl.x, tl.y, tl.x + zoom, tl.y + zoom);
+                ctx.fill(rect, data.brush_color());
+            }
+        }
+    }
+
+    /// Close the lasso, and turn it into a selection.
+    fn close_lasso(&mut self, ctx: &mut EventCtx) {
+        if !self.lasso.is_empty() {
+            let points = std::mem::take(&mut self.lasso);
+            ctx.submit_command(commands::IMAGE_LASSO.with(points));
         }
     }
 
@@ -221,26 +501,48 @@ impl Canvas {
         match data.tool_type() {
             ToolType::Dropper => {
                 let current_pos = data.current_pos();
-                let color = data.doc().pixels().read(current_pos);
+                let color = data.doc().read(current_pos);
 
                 data.set_brush_color(color);
             }
 
+            ToolType::Ellipse | ToolType::Line | ToolType::Rectangle => {
+                if state == ToolState::End && data.start_pos() != druid::Point::ZERO {
+                    ctx.submit_command(commands::IMAGE_SHAPE.with(self.shape(data)));
+                }
+            }
+
+            // The whole stroke goes through, even the parts outside the selection. It's up
+            // to the stroke to stay inside the lines.
             ToolType::Eraser => {
+                ctx.submit_command(commands::IMAGE_ERASER.with(state));
+            }
+
+            ToolType::Fill => {
                 let current_pos = data.current_pos();
                 let bounds = data.doc().bounds();
 
                 if bounds.contains(current_pos) {
-                    ctx.submit_command(commands::IMAGE_ERASER.with(state));
+                    ctx.submit_command(commands::IMAGE_FILL.with(true));
                 }
             }
 
-            ToolType::Fill => {
+            ToolType::Lasso => {
                 let current_pos = data.current_pos();
-                let bounds = data.doc().bounds();
 
-                if bounds.contains(current_pos) {
-                    ctx.submit_command(commands::IMAGE_FILL.with(true));
+                match state {
+                    ToolState::Start => self.lasso.clear(),
+                    ToolState::Move => {}
+                    ToolState::End => self.close_lasso(ctx),
+                }
+                if state != ToolState::End && current_pos != druid::Point::ZERO {
+                    self.lasso.push(current_pos);
+                }
+            }
+
+            ToolType::MagicWand => {
+                if state == ToolState::Start && data.current_pos() != druid::Point::ZERO {
+                    ctx.submit_command(commands::SELECT_MAGIC_WAND);
                 }
             }
 
@@ -253,11 +555,30 @@ impl Canvas {
             }
 
             ToolType::Paint => {
+                ctx.submit_command(commands::IMAGE_PAINT.with(state));
+            }
+
+            // Each click adds a corner; dragging moves the corner that was just added.
+            // Clicking on the first corner closes the shape.
+            ToolType::PolygonLasso => {
                 let current_pos = data.current_pos();
-                let bounds = data.doc().bounds();
 
-                if bounds.contains(current_pos) {
-                    ctx.submit_command(commands::IMAGE_PAINT.with(state));
+                match state {
+                    ToolState::Start
+                        if self.lasso.len() > 2 && self.lasso.first() == Some(&current_pos) =>
+                    {
+                        self.close_lasso(ctx);
+                    }
+                    ToolState::Start if current_pos != druid::Point::ZERO => {
+                        self.lasso.push(current_pos);
+                    }
+                    ToolState::Start => {}
+                    ToolState::Move => {
+                        if let Some(last) = self.lasso.last_mut() {
+                            *last = current_pos;
+                        }
+                    }
+                    ToolState::End => {}
                 }
             }
         }
@@ -267,20 +588,66 @@ impl Canvas {
 impl druid::Widget<AppState> for Canvas {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
         match event {
+            Event::KeyDown(e) if e.code == druid::Code::Space => {
+                if !self.space_down {
+                    self.space_down = true;
+                    ctx.set_cursor(&druid::Cursor::OpenHand);
+                }
+            }
+
             Event::KeyUp(e) => match e.code {
+                druid::Code::Space => {
+                    self.space_down = false;
+                    ctx.clear_cursor();
+                }
+
                 druid::Code::Delete | druid::Code::Backspace => {
                     ctx.submit_command(commands::IMAGE_CLEAR);
                 }
 
+                druid::Code::Enter => {
+                    self.close_lasso(ctx);
+                }
+
+                druid::Code::Escape if !self.lasso.is_empty() => {
+                    self.lasso.clear();
+                    ctx.request_paint();
+                }
+
                 druid::Code::Escape => {
                     ctx.submit_command(commands::EDIT_DESELECT);
                 }
                 _ => {}
             },
 
+            Event::MouseDown(e)
+                if e.button.is_middle() || (self.space_down && e.button.is_left()) =>
+            {
+                self.pan_pos = Some(e.pos);
+                ctx.set_active(true);
+            }
+
+            // A double click closes the polygonal lasso.
+            Event::MouseDown(e) if e.count > 1 && data.tool_type() == ToolType::PolygonLasso => {
+                self.close_lasso(ctx);
+            }
+
             Event::MouseDown(e) => {
                 if !e.focus {
-                    match Self::screen_coords_to_canvas_coords(e.pos) {
+                    // Modifiers decide how a new selection is combined with the old one. A
+                    // polygonal lasso keeps whatever it started with.
+                    if self.lasso.is_empty() {
+                        let mode = match (e.mods.shift(), e.mods.alt()) {
+                            (true, true) => SelectionMode::Intersect,
+                            (true, false) => SelectionMode::Add,
+                            (false, true) => SelectionMode::Subtract,
+                            (false, false) => SelectionMode::Replace,
+                        };
+                        data.set_selection_mode(mode);
+                    }
+                    self.constrain = e.mods.shift();
+
+                    match Self::screen_coords_to_canvas_coords(data, e.pos) {
                         Some(p) => {
                             data.set_start_pos(p);
                             data.set_current_pos(p);
@@ -295,16 +662,34 @@ impl druid::Widget<AppState> for Canvas {
                 }
             }
 
+            Event::MouseMove(e) if self.pan_pos.is_some() => {
+                let pan_pos = self.pan_pos.replace(e.pos).unwrap();
+                data.viewport_mut().pan(e.pos - pan_pos);
+                settle(data);
+            }
+
             Event::MouseMove(e) => {
+                if self.constrain != e.mods.shift() {
+                    self.constrain = e.mods.shift();
+                    ctx.request_paint();
+                }
+
                 let cursor = match data.tool_type() {
-                    ToolType::Marquee => druid::Cursor::Crosshair,
+                    _ if self.space_down => druid::Cursor::OpenHand,
+                    ToolType::Ellipse
+                    | ToolType::Lasso
+                    | ToolType::Line
+                    | ToolType::MagicWand
+                    | ToolType::Marquee
+                    | ToolType::PolygonLasso
+                    | ToolType::Rectangle => druid::Cursor::Crosshair,
                     _ => druid::Cursor::Arrow,
                 };
                 ctx.set_cursor(&cursor);
 
                 let mut moved = false;
 
-                match Self::screen_coords_to_canvas_coords(e.pos) {
+                match Self::screen_coords_to_canvas_coords(data, e.pos) {
                     Some(p) => {
                         // The screen coords might have changed, but that doesn't mean the
                         // canvas coords have changed (because of how big our pixels are).
@@ -312,7 +697,7 @@ impl druid::Widget<AppState> for Canvas {
                         if p != data.current_pos() {
                             moved = true;
 
-                            let color = data.doc().pixels().read(p);
+                            let color = data.doc().read(p);
 
                             data.set_pos_color(color);
                             data.set_current_pos(p);
@@ -333,25 +718,82 @@ impl druid::Widget<AppState> for Canvas {
                 }
             }
 
+            Event::MouseUp(_e) if self.pan_pos.is_some() => {
+                self.pan_pos = None;
+                ctx.set_active(false);
+            }
+
             Event::MouseUp(_e) if ctx.is_active() => {
                 ctx.set_active(false);
                 self.tool(ctx, data, ToolState::End);
             }
 
+            Event::Timer(token) if *token == self.ants_timer => {
+                self.ants_offset = (self.ants_offset + 1.0) % (theme::CANVAS_ANTS_DASH * 2.0);
+                if let Some(rect) = self.ants_rect(data) {
+                    ctx.request_paint_rect(rect);
+                }
+                self.ants_timer = ctx.request_timer(theme::CANVAS_ANTS_INTERVAL);
+            }
+
+            // Zoom around the cursor. Trackpads send lots of small deltas, so we save them
+            // up until they're worth a whole step.
+            Event::Wheel(e) => {
+                self.wheel_delta += e.wheel_delta.y;
+                while self.wheel_delta.abs() >= theme::CANVAS_WHEEL_STEP {
+                    if self.wheel_delta < 0.0 {
+                        data.viewport_mut().zoom_in(e.pos);
+                        self.wheel_delta += theme::CANVAS_WHEEL_STEP;
+                    } else {
+                        data.viewport_mut().zoom_out(e.pos);
+                        self.wheel_delta -= theme::CANVAS_WHEEL_STEP;
+                    }
+                }
+                settle(data);
+                ctx.set_handled();
+            }
+
             _ => {}
         }
     }
 
     fn lifecycle(
         &mut self,
-        _ctx: &mut LifeCycleCtx,
-        _event: &LifeCycle,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
         _data: &AppState,
         _env: &Env,
     ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.ants_timer = ctx.request_timer(theme::CANVAS_ANTS_INTERVAL);
+        }
     }
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, _env: &Env) {
+        // Switching tools abandons the lasso.
+        if old_data.tool_type() != data.tool_type() {
+            self.lasso.clear();
+        }
+
+        // A different size of image needs a different zoom.
+        if !old_data.doc().header().same(data.doc().header()) {
+            ctx.submit_command(commands::VIEW_ZOOM_FIT);
+        }
+
+        // The canvas is sized to fit the image at the current zoom.
+        if view_rect(old_data) != view_rect(data) {
+            ctx.request_layout();
+        }
+
+        // Only flatten the pixels again if they've changed. Painting only changes the
+        // current frame, so the onion skin behind it can usually stay as it is.
+        if Self::backdrop_changed(old_data, data) {
+            self.backdrop = None;
+            self.flattened = None;
+        } else if !old_data.doc().same(data.doc()) {
+            self.flattened = None;
+        }
+
         if !old_data.same(data) {
             ctx.request_paint();
         }
@@ -361,22 +803,49 @@ impl druid::Widget<AppState> for Canvas {
         &mut self,
         _layout_ctx: &mut LayoutCtx,
         bc: &BoxConstraints,
-        _data: &AppState,
+        data: &AppState,
         _env: &Env,
     ) -> Size {
-        let rect = Self::screen_rect(theme::CANVAS_DIMS, theme::CANVAS_DIMS);
+        let rect = view_rect(data);
         let size = Size::new(rect.x1 + 1.0, rect.y1 + 1.0);
         bc.constrain(size)
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
         self.paint_border(ctx, data);
-        self.paint_pixels(ctx, data);
-        self.paint_grid(ctx, data);
-        self.paint_selection(ctx, data);
+
+        // Keep the image inside the border, however far it's zoomed or panned.
+        ctx.with_save(|ctx| {
+            ctx.clip(view_rect(data));
+            self.paint_pixels(ctx, data);
+            self.paint_shape(ctx, data);
+            self.paint_grid(ctx, data);
+            self.paint_selection(ctx, data);
+            self.paint_lasso(ctx, data);
+            self.paint_marquee(ctx, data);
+        });
     }
 }
 
+/// Get the area of the canvas that shows the image, inside the border.
+pub fn view_rect(data: &AppState) -> druid::Rect {
+    data.viewport().view_rect(
+        data.doc().header(),
+        theme::CANVAS_MIN_VIEW_SIZE,
+        theme::CANVAS_MAX_VIEW_RECT,
+    )
+}
+
+/// Keep the image within the canvas after zooming or panning. See `Viewport::settle()`.
+pub fn settle(data: &mut AppState) {
+    let header = data.doc().header().clone();
+    data.viewport_mut().settle(
+        &header,
+        theme::CANVAS_MIN_VIEW_SIZE,
+        theme::CANVAS_MAX_VIEW_RECT,
+    );
+}
+
 /// A controller one level up from the canvas. We use this to steal the focus when the
 /// app starts, so that key events go to the canvas.
 pub struct CanvasController;
@@ -392,6 +861,7 @@ impl<W: Widget<AppState>> druid::widget::Controller<AppState, W> for CanvasContr
     ) {
         if let Event::WindowConnected = event {
             ctx.request_focus();
+            ctx.submit_command(commands::VIEW_ZOOM_FIT);
         }
 
         child.event(ctx, event, data, env);
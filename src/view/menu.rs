@@ -18,6 +18,13 @@ use druid::widget::prelude::*;
 
 use crate::common::commands;
 use crate::common::constants;
+use crate::model::sheet::AtlasFormat;
+use crate::model::sheet::SheetLayout;
+use crate::model::types::BlendMode;
+use crate::model::types::ColorMode;
+use crate::model::types::Orientation;
+use crate::model::types::PalettePreset;
+use crate::model::types::PaletteSort;
 
 pub const COMMON_MENU_FILE_SAVE: &str = "common-menu-file-save";
 pub const COMMON_MENU_CUT: &str = "common-menu-cut";
@@ -25,7 +32,156 @@ pub const COMMON_MENU_COPY: &str = "common-menu-copy";
 pub const COMMON_MENU_UNDO: &str = "common-menu-undo";
 pub const COMMON_MENU_REDO: &str = "common-menu-redo";
 pub const EDIT_MENU_DESELECT: &str = "edit-menu-deselect";
+pub const EDIT_MENU_PASTE_NEW: &str = "edit-menu-paste-new";
+pub const MENU_FRAME_DELETE: &str = "menu-frame-delete";
+pub const MENU_FRAME_MOVE_EARLIER: &str = "menu-frame-move-earlier";
+pub const MENU_FRAME_MOVE_LATER: &str = "menu-frame-move-later";
+pub const MENU_IMAGE_CROP: &str = "menu-image-crop";
+pub const MENU_IMAGE_FILL_SHAPES: &str = "menu-image-fill-shapes";
+pub const MENU_IMAGE_MODE_RGBA: &str = "menu-image-mode-rgba";
+pub const MENU_IMAGE_MODE_INDEXED: &str = "menu-image-mode-indexed";
+pub const MENU_IMAGE_PIXEL_PERFECT: &str = "menu-image-pixel-perfect";
+pub const MENU_LAYER_DELETE: &str = "menu-layer-delete";
+pub const MENU_LAYER_SELECT_ABOVE: &str = "menu-layer-select-above";
+pub const MENU_LAYER_SELECT_BELOW: &str = "menu-layer-select-below";
+pub const MENU_LAYER_VISIBLE: &str = "menu-layer-visible";
+pub const MENU_LAYER_LOCKED: &str = "menu-layer-locked";
+pub const MENU_LAYER_BLEND_NORMAL: &str = "menu-layer-blend-normal";
+pub const MENU_LAYER_BLEND_MULTIPLY: &str = "menu-layer-blend-multiply";
+pub const MENU_LAYER_BLEND_SCREEN: &str = "menu-layer-blend-screen";
+pub const MENU_LAYER_BLEND_ADD: &str = "menu-layer-blend-add";
+pub const MENU_LAYER_OPACITY_100: &str = "menu-layer-opacity-100";
+pub const MENU_LAYER_OPACITY_75: &str = "menu-layer-opacity-75";
+pub const MENU_LAYER_OPACITY_50: &str = "menu-layer-opacity-50";
+pub const MENU_LAYER_OPACITY_25: &str = "menu-layer-opacity-25";
+pub const MENU_PALETTE_ADD_COLOR: &str = "menu-palette-add-color";
+pub const MENU_SELECT_WAND_CONTIGUOUS: &str = "menu-select-wand-contiguous";
+pub const MENU_SELECT_WAND_DIAGONAL: &str = "menu-select-wand-diagonal";
+pub const MENU_SHEET_IMPORT: &str = "menu-sheet-import";
+pub const MENU_SHEET_LAYOUT_GRID: &str = "menu-sheet-layout-grid";
+pub const MENU_SHEET_LAYOUT_PACKED: &str = "menu-sheet-layout-packed";
+pub const MENU_SHEET_FORMAT_ASEPRITE: &str = "menu-sheet-format-aseprite";
+pub const MENU_SHEET_FORMAT_TEXTURE_PACKER: &str = "menu-sheet-format-texture-packer";
+pub const MENU_SHEET_TAGGED_ONLY: &str = "menu-sheet-tagged-only";
 pub const MENU_VIEW_SHOW_GRID: &str = "menu-view-show-grid";
+pub const MENU_VIEW_SHOW_ONION_SKIN: &str = "menu-view-show-onion-skin";
+
+/// Undo history depths that can be picked from the menu, in steps.
+pub const MENU_EDIT_UNDO_DEPTHS: [(&str, usize); 4] = [
+    ("menu-edit-undo-depth-16", 16),
+    ("menu-edit-undo-depth-64", 64),
+    ("menu-edit-undo-depth-256", 256),
+    ("menu-edit-undo-depth-1024", 1024),
+];
+
+/// Undo history budgets that can be picked from the menu, in bytes.
+pub const MENU_EDIT_UNDO_BUDGETS: [(&str, usize); 4] = [
+    ("menu-edit-undo-budget-16", 16 * 1024 * 1024),
+    ("menu-edit-undo-budget-64", 64 * 1024 * 1024),
+    ("menu-edit-undo-budget-256", 256 * 1024 * 1024),
+    ("menu-edit-undo-budget-1024", 1024 * 1024 * 1024),
+];
+
+/// Frame durations that can be picked from the menu, in milliseconds.
+pub const MENU_FRAME_DURATIONS: [(&str, u32); 6] = [
+    ("menu-frame-duration-50", 50),
+    ("menu-frame-duration-100", 100),
+    ("menu-frame-duration-150", 150),
+    ("menu-frame-duration-200", 200),
+    ("menu-frame-duration-250", 250),
+    ("menu-frame-duration-500", 500),
+];
+
+/// Magic wand tolerances that can be picked from the menu. Each channel of a pixel can
+/// differ by this much from the one that was clicked.
+pub const MENU_SELECT_WAND_TOLERANCES: [(&str, u8); 6] = [
+    ("menu-select-wand-tolerance-0", 0),
+    ("menu-select-wand-tolerance-8", 8),
+    ("menu-select-wand-tolerance-16", 16),
+    ("menu-select-wand-tolerance-32", 32),
+    ("menu-select-wand-tolerance-64", 64),
+    ("menu-select-wand-tolerance-128", 128),
+];
+
+/// Amounts that a selection can be grown or shrunk by from the menu, in pixels.
+pub const MENU_SELECT_GROWS: [(&str, usize); 4] = [
+    ("menu-select-grow-1", 1),
+    ("menu-select-grow-2", 2),
+    ("menu-select-grow-4", 4),
+    ("menu-select-grow-8", 8),
+];
+pub const MENU_SELECT_SHRINKS: [(&str, usize); 4] = [
+    ("menu-select-shrink-1", 1),
+    ("menu-select-shrink-2", 2),
+    ("menu-select-shrink-4", 4),
+    ("menu-select-shrink-8", 8),
+];
+
+/// Built-in palettes that can be picked from the menu.
+pub const MENU_PALETTE_PRESETS: [(&str, &str, PalettePreset); 9] = [
+    ("menu-palette-preset-vga", "VGA", PalettePreset::Vga),
+    ("menu-palette-preset-pico-8", "PICO-8", PalettePreset::Pico8),
+    ("menu-palette-preset-nes", "NES", PalettePreset::Nes),
+    (
+        "menu-palette-preset-game-boy",
+        "Game Boy (DMG)",
+        PalettePreset::GameBoy,
+    ),
+    (
+        "menu-palette-preset-c64",
+        "Commodore 64",
+        PalettePreset::C64,
+    ),
+    ("menu-palette-preset-cga", "CGA", PalettePreset::Cga),
+    ("menu-palette-preset-ega", "EGA", PalettePreset::Ega),
+    (
+        "menu-palette-preset-zx-spectrum",
+        "ZX Spectrum",
+        PalettePreset::ZxSpectrum,
+    ),
+    (
+        "menu-palette-preset-sweetie-16",
+        "Sweetie-16",
+        PalettePreset::Sweetie16,
+    ),
+];
+
+/// Ways of sorting the palette.
+pub const MENU_PALETTE_SORTS: [(&str, &str, PaletteSort); 3] = [
+    ("menu-palette-sort-hue", "Hue", PaletteSort::Hue),
+    (
+        "menu-palette-sort-luminance",
+        "Luminance",
+        PaletteSort::Luminance,
+    ),
+    ("menu-palette-sort-usage", "Usage", PaletteSort::Usage),
+];
+
+/// Sprite sheet padding and extrusion that can be picked from the menu, in pixels.
+pub const MENU_SHEET_PADDINGS: [(&str, usize); 4] = [
+    ("menu-sheet-padding-0", 0),
+    ("menu-sheet-padding-1", 1),
+    ("menu-sheet-padding-2", 2),
+    ("menu-sheet-padding-4", 4),
+];
+pub const MENU_SHEET_EXTRUSIONS: [(&str, usize); 3] = [
+    ("menu-sheet-extrude-0", 0),
+    ("menu-sheet-extrude-1", 1),
+    ("menu-sheet-extrude-2", 2),
+];
+
+/// Number of onion skin frames that can be picked from the menu, before and after
+/// the current frame.
+pub const MENU_VIEW_ONION_SKIN_BEFORE: [(&str, usize); constants::MAX_ONION_SKIN_FRAMES] = [
+    ("menu-view-onion-skin-before-1", 1),
+    ("menu-view-onion-skin-before-2", 2),
+    ("menu-view-onion-skin-before-3", 3),
+];
+pub const MENU_VIEW_ONION_SKIN_AFTER: [(&str, usize); constants::MAX_ONION_SKIN_FRAMES] = [
+    ("menu-view-onion-skin-after-1", 1),
+    ("menu-view-onion-skin-after-2", 2),
+    ("menu-view-onion-skin-after-3", 3),
+];
 
 /// Druid menus are immutable, so if you want to update a menu item at runtime, you have to
 /// reconstruct the menu bar from scratch. Use a map to make it easier to tell the menu
@@ -78,13 +234,60 @@ impl Default for MenuOpts {
         // Undo/redo are disabled until you actually make a change.
         disabled.insert(COMMON_MENU_UNDO, true);
         disabled.insert(COMMON_MENU_REDO, true);
+        for &(key, value) in MENU_EDIT_UNDO_DEPTHS.iter() {
+            selected.insert(key, value == constants::UNDO_DEPTH);
+        }
+        for &(key, value) in MENU_EDIT_UNDO_BUDGETS.iter() {
+            selected.insert(key, value == constants::UNDO_BUDGET);
+        }
 
-        // Deselect is disabled until there's a selection.
+        // Deselect, crop, grow and shrink are disabled until there's a selection.
         disabled.insert(EDIT_MENU_DESELECT, true);
+        disabled.insert(MENU_IMAGE_CROP, true);
+        for &(key, _) in MENU_SELECT_GROWS.iter().chain(MENU_SELECT_SHRINKS.iter()) {
+            disabled.insert(key, true);
+        }
+
+        // New documents are RGBA, with the VGA palette.
+        selected.insert(MENU_IMAGE_MODE_RGBA, true);
+        selected.insert(MENU_PALETTE_PRESETS[0].0, true);
+
+        // New documents have a single, visible, opaque layer with normal blending.
+        // You can't delete the only layer, and there's nothing above or below it.
+        disabled.insert(MENU_LAYER_DELETE, true);
+        disabled.insert(MENU_LAYER_SELECT_ABOVE, true);
+        disabled.insert(MENU_LAYER_SELECT_BELOW, true);
+        selected.insert(MENU_LAYER_VISIBLE, true);
+        selected.insert(MENU_LAYER_BLEND_NORMAL, true);
+        selected.insert(MENU_LAYER_OPACITY_100, true);
+
+        // Same goes for the single frame, which has the default duration.
+        disabled.insert(MENU_FRAME_DELETE, true);
+        disabled.insert(MENU_FRAME_MOVE_EARLIER, true);
+        disabled.insert(MENU_FRAME_MOVE_LATER, true);
+        for &(key, value) in MENU_FRAME_DURATIONS.iter() {
+            selected.insert(key, value == constants::DEFAULT_FRAME_DURATION);
+        }
+
+        // The magic wand picks exact matches that touch along an edge.
+        selected.insert(MENU_SELECT_WAND_CONTIGUOUS, true);
+        selected.insert(MENU_SELECT_WAND_TOLERANCES[0].0, true);
+
+        // Sprite sheets are a plain grid with an Aseprite atlas by default, with every
+        // frame packed edge to edge.
+        selected.insert(MENU_SHEET_LAYOUT_GRID, true);
+        selected.insert(MENU_SHEET_FORMAT_ASEPRITE, true);
+        selected.insert(MENU_SHEET_PADDINGS[0].0, true);
+        selected.insert(MENU_SHEET_EXTRUSIONS[0].0, true);
 
         // We show the canvas grid by default.
         selected.insert(MENU_VIEW_SHOW_GRID, true);
 
+        // The onion skin is off by default, but shows one frame either side when it's on.
+        selected.insert(MENU_VIEW_SHOW_ONION_SKIN, false);
+        selected.insert(MENU_VIEW_ONION_SKIN_BEFORE[0].0, true);
+        selected.insert(MENU_VIEW_ONION_SKIN_AFTER[0].0, true);
+
         Self { disabled, selected }
     }
 }
@@ -94,10 +297,35 @@ pub fn menu_bar<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
         .append(druid::platform_menus::mac::application::default())
         .append(build_file_menu(menu_opts))
         .append(build_edit_menu(menu_opts))
-        .append(build_image_menu())
+        .append(build_select_menu(menu_opts))
+        .append(build_image_menu(menu_opts))
+        .append(build_layer_menu(menu_opts))
+        .append(build_frame_menu(menu_opts))
+        .append(build_palette_menu(menu_opts))
         .append(build_view_menu(menu_opts))
 }
 
+/// Build a submenu that picks a number of pixels. Each item can be disabled or selected
+/// through its key.
+fn pixels_menu<T: Data>(
+    menu_opts: &MenuOpts,
+    title: druid::LocalizedString<T>,
+    items: &[(&'static str, usize)],
+    selector: druid::Selector<usize>,
+) -> druid::MenuDesc<T> {
+    let mut menu = druid::MenuDesc::new(title);
+    for &(key, value) in items.iter() {
+        let disabled = menu_opts.disabled_or(key, false);
+        let selected = menu_opts.selected_or(key, false);
+        let item = druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(format!("{} px", value)),
+            selector.with(value),
+        );
+        menu = menu.append(item.disabled_if(|| disabled).selected_if(|| selected));
+    }
+    menu
+}
+
 fn build_file_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
     fn file_dialog_opts() -> druid::FileDialogOptions {
         druid::FileDialogOptions::default().allowed_types(constants::ALLOWED_FILE_TYPES.to_vec())
@@ -128,6 +356,118 @@ fn build_file_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
         .append(druid::platform_menus::mac::file::close())
         .append(druid::platform_menus::mac::file::save().disabled_if(|| save_disabled))
         .append(save_as())
+        .append_separator()
+        .append(build_sheet_menu(menu_opts))
+}
+
+fn build_sheet_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
+    fn export<T: Data>() -> druid::MenuItem<T> {
+        let opts = druid::FileDialogOptions::default()
+            .allowed_types(vec![druid::FileSpec::PNG])
+            .accept_command(commands::SHEET_EXPORT);
+
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-sheet-export")
+                .with_placeholder("Export Sprite Sheet..."),
+            druid::commands::SHOW_SAVE_PANEL.with(opts),
+        )
+        .hotkey(druid::SysMods::AltCmd, "e")
+    }
+
+    fn import<T: Data>() -> druid::MenuItem<T> {
+        let opts = druid::FileDialogOptions::default()
+            .allowed_types(vec![druid::FileSpec::PNG])
+            .accept_command(commands::SHEET_IMPORT_OPEN);
+
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_SHEET_IMPORT)
+                .with_placeholder("Import Sprite Sheet..."),
+            druid::commands::SHOW_OPEN_PANEL.with(opts),
+        )
+    }
+
+    fn layout<T: Data>(
+        key: &'static str,
+        placeholder: &'static str,
+        layout: SheetLayout,
+    ) -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::SHEET_LAYOUT.with(layout),
+        )
+    }
+
+    fn format<T: Data>(
+        key: &'static str,
+        placeholder: &'static str,
+        format: AtlasFormat,
+    ) -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::SHEET_FORMAT.with(format),
+        )
+    }
+
+    fn tagged_only<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_SHEET_TAGGED_ONLY)
+                .with_placeholder("Tagged Frames Only"),
+            commands::SHEET_TAGGED_ONLY,
+        )
+    }
+
+    let import_disabled = menu_opts.disabled_or(MENU_SHEET_IMPORT, false);
+    let grid_selected = menu_opts.selected_or(MENU_SHEET_LAYOUT_GRID, false);
+    let packed_selected = menu_opts.selected_or(MENU_SHEET_LAYOUT_PACKED, false);
+    let aseprite_selected = menu_opts.selected_or(MENU_SHEET_FORMAT_ASEPRITE, false);
+    let texture_packer_selected = menu_opts.selected_or(MENU_SHEET_FORMAT_TEXTURE_PACKER, false);
+    let tagged_only_selected = menu_opts.selected_or(MENU_SHEET_TAGGED_ONLY, false);
+
+    let padding_menu = pixels_menu(
+        menu_opts,
+        druid::LocalizedString::new("menu-sheet-padding-menu").with_placeholder("Padding"),
+        &MENU_SHEET_PADDINGS,
+        commands::SHEET_PADDING,
+    );
+    let extrude_menu = pixels_menu(
+        menu_opts,
+        druid::LocalizedString::new("menu-sheet-extrude-menu").with_placeholder("Extrusion"),
+        &MENU_SHEET_EXTRUSIONS,
+        commands::SHEET_EXTRUDE,
+    );
+
+    druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-sheet-menu").with_placeholder("Sprite Sheet"),
+    )
+    .append(export())
+    .append(import().disabled_if(|| import_disabled))
+    .append_separator()
+    .append(layout(MENU_SHEET_LAYOUT_GRID, "Grid", SheetLayout::Grid).selected_if(|| grid_selected))
+    .append(
+        layout(MENU_SHEET_LAYOUT_PACKED, "Packed", SheetLayout::Packed)
+            .selected_if(|| packed_selected),
+    )
+    .append(padding_menu)
+    .append(extrude_menu)
+    .append_separator()
+    .append(
+        format(
+            MENU_SHEET_FORMAT_ASEPRITE,
+            "Aseprite Atlas",
+            AtlasFormat::Aseprite,
+        )
+        .selected_if(|| aseprite_selected),
+    )
+    .append(
+        format(
+            MENU_SHEET_FORMAT_TEXTURE_PACKER,
+            "TexturePacker Atlas",
+            AtlasFormat::TexturePacker,
+        )
+        .selected_if(|| texture_packer_selected),
+    )
+    .append_separator()
+    .append(tagged_only().selected_if(|| tagged_only_selected))
 }
 
 fn build_edit_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
@@ -138,6 +478,13 @@ fn build_edit_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
         )
         .hotkey(druid::SysMods::Cmd, "a")
     }
+    fn edit_menu_paste_new<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(EDIT_MENU_PASTE_NEW).with_placeholder("Paste as New Image"),
+            commands::EDIT_PASTE_NEW,
+        )
+        .hotkey(druid::SysMods::AltCmd, "v")
+    }
     fn edit_menu_deselect<T: Data>() -> druid::MenuItem<T> {
         druid::MenuItem::new(
             druid::LocalizedString::new("menu-edit-deselect").with_placeholder("Deselect"),
@@ -145,25 +492,130 @@ fn build_edit_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
         )
     }
 
+    let mut undo_depth_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-edit-undo-depth-menu").with_placeholder("Undo History"),
+    );
+    for &(key, value) in MENU_EDIT_UNDO_DEPTHS.iter() {
+        let selected = menu_opts.selected_or(key, false);
+        let item = druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(format!("{} Steps", value)),
+            commands::EDIT_UNDO_DEPTH.with(value),
+        );
+        undo_depth_menu = undo_depth_menu.append(item.selected_if(|| selected));
+    }
+
+    let mut undo_budget_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-edit-undo-budget-menu").with_placeholder("Undo Memory"),
+    );
+    for &(key, value) in MENU_EDIT_UNDO_BUDGETS.iter() {
+        let selected = menu_opts.selected_or(key, false);
+        let item = druid::MenuItem::new(
+            druid::LocalizedString::new(key)
+                .with_placeholder(format!("{} MiB", value / (1024 * 1024))),
+            commands::EDIT_UNDO_BUDGET.with(value),
+        );
+        undo_budget_menu = undo_budget_menu.append(item.selected_if(|| selected));
+    }
+
     let undo_disabled = menu_opts.disabled_or(COMMON_MENU_UNDO, false);
     let redo_disabled = menu_opts.disabled_or(COMMON_MENU_REDO, false);
     let cut_disabled = menu_opts.disabled_or(COMMON_MENU_CUT, false);
     let copy_disabled = menu_opts.disabled_or(COMMON_MENU_COPY, false);
+    let paste_new_disabled = menu_opts.disabled_or(EDIT_MENU_PASTE_NEW, false);
     let deselect = menu_opts.disabled_or(EDIT_MENU_DESELECT, false);
 
     druid::MenuDesc::new(druid::LocalizedString::new("common-menu-edit-menu"))
         .append(druid::platform_menus::common::undo().disabled_if(|| undo_disabled))
         .append(druid::platform_menus::common::redo().disabled_if(|| redo_disabled))
+        .append(undo_depth_menu)
+        .append(undo_budget_menu)
         .append_separator()
         .append(druid::platform_menus::common::cut().disabled_if(|| cut_disabled))
         .append(druid::platform_menus::common::copy().disabled_if(|| copy_disabled))
         .append(druid::platform_menus::common::paste())
+        .append(edit_menu_paste_new().disabled_if(|| paste_new_disabled))
         .append_separator()
         .append(edit_menu_select_all())
         .append(edit_menu_deselect().disabled_if(|| deselect))
 }
 
-fn build_image_menu<T: Data>() -> druid::MenuDesc<T> {
+fn build_select_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
+    fn invert<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-select-invert").with_placeholder("Invert Selection"),
+            commands::SELECT_INVERT,
+        )
+        .hotkey(druid::SysMods::CmdShift, "i")
+    }
+
+    fn by_color<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-select-by-color").with_placeholder("Select by Color"),
+            commands::SELECT_BY_COLOR,
+        )
+    }
+
+    fn wand_contiguous<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_SELECT_WAND_CONTIGUOUS).with_placeholder("Contiguous"),
+            commands::SELECT_WAND_CONTIGUOUS,
+        )
+    }
+
+    fn wand_diagonal<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_SELECT_WAND_DIAGONAL)
+                .with_placeholder("Include Diagonals"),
+            commands::SELECT_WAND_DIAGONAL,
+        )
+    }
+
+    let contiguous_selected = menu_opts.selected_or(MENU_SELECT_WAND_CONTIGUOUS, false);
+    let diagonal_selected = menu_opts.selected_or(MENU_SELECT_WAND_DIAGONAL, false);
+
+    let mut tolerance_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-select-wand-tolerance-menu")
+            .with_placeholder("Tolerance"),
+    );
+    for &(key, value) in MENU_SELECT_WAND_TOLERANCES.iter() {
+        let selected = menu_opts.selected_or(key, false);
+        let item = druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(value.to_string()),
+            commands::SELECT_WAND_TOLERANCE.with(value),
+        );
+        tolerance_menu = tolerance_menu.append(item.selected_if(|| selected));
+    }
+
+    let wand_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-select-wand-menu").with_placeholder("Magic Wand"),
+    )
+    .append(tolerance_menu)
+    .append(wand_contiguous().selected_if(|| contiguous_selected))
+    .append(wand_diagonal().selected_if(|| diagonal_selected));
+
+    let grow_menu = pixels_menu(
+        menu_opts,
+        druid::LocalizedString::new("menu-select-grow-menu").with_placeholder("Grow"),
+        &MENU_SELECT_GROWS,
+        commands::SELECT_GROW,
+    );
+    let shrink_menu = pixels_menu(
+        menu_opts,
+        druid::LocalizedString::new("menu-select-shrink-menu").with_placeholder("Shrink"),
+        &MENU_SELECT_SHRINKS,
+        commands::SELECT_SHRINK,
+    );
+
+    druid::MenuDesc::new(druid::LocalizedString::new("menu-select-menu").with_placeholder("Select"))
+        .append(invert())
+        .append(grow_menu)
+        .append(shrink_menu)
+        .append(by_color())
+        .append_separator()
+        .append(wand_menu)
+}
+
+fn build_image_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
     fn fill<T: Data>() -> druid::MenuItem<T> {
         druid::MenuItem::new(
             druid::LocalizedString::new("menu-image-fill").with_placeholder("Fill"),
@@ -172,6 +624,57 @@ fn build_image_menu<T: Data>() -> druid::MenuDesc<T> {
         .hotkey(druid::SysMods::AltCmd, "f")
     }
 
+    fn fill_shapes<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_IMAGE_FILL_SHAPES).with_placeholder("Fill Shapes"),
+            commands::IMAGE_FILL_SHAPES,
+        )
+    }
+
+    fn pixel_perfect<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_IMAGE_PIXEL_PERFECT)
+                .with_placeholder("Pixel Perfect Strokes"),
+            commands::IMAGE_PIXEL_PERFECT,
+        )
+    }
+
+    fn color_mode<T: Data>(
+        key: &'static str,
+        placeholder: &'static str,
+        color_mode: ColorMode,
+    ) -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::IMAGE_COLOR_MODE.with(color_mode),
+        )
+    }
+
+    fn crop<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-image-crop").with_placeholder("Crop to Selection"),
+            commands::IMAGE_CROP,
+        )
+    }
+
+    fn trim<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-image-trim").with_placeholder("Trim"),
+            commands::IMAGE_TRIM,
+        )
+    }
+
+    fn orient<T: Data>(
+        key: &'static str,
+        placeholder: &'static str,
+        orientation: Orientation,
+    ) -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::IMAGE_ORIENT.with(orientation),
+        )
+    }
+
     fn brighten<T: Data>() -> druid::MenuItem<T> {
         druid::MenuItem::new(
             druid::LocalizedString::new("menu-image-brighten").with_placeholder("Brighten"),
@@ -209,8 +712,58 @@ fn build_image_menu<T: Data>() -> druid::MenuDesc<T> {
         )
     }
 
+    let crop_disabled = menu_opts.disabled_or(MENU_IMAGE_CROP, false);
+    let fill_shapes_selected = menu_opts.selected_or(MENU_IMAGE_FILL_SHAPES, false);
+    let pixel_perfect_selected = menu_opts.selected_or(MENU_IMAGE_PIXEL_PERFECT, false);
+    let rgba_selected = menu_opts.selected_or(MENU_IMAGE_MODE_RGBA, false);
+    let indexed_selected = menu_opts.selected_or(MENU_IMAGE_MODE_INDEXED, false);
+    let indexed_disabled = menu_opts.disabled_or(MENU_IMAGE_MODE_INDEXED, false);
+
+    let mode_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-image-mode-menu").with_placeholder("Mode"),
+    )
+    .append(color_mode(MENU_IMAGE_MODE_RGBA, "RGBA", ColorMode::Rgba).selected_if(|| rgba_selected))
+    .append(
+        color_mode(MENU_IMAGE_MODE_INDEXED, "Indexed", ColorMode::Indexed)
+            .disabled_if(|| indexed_disabled)
+            .selected_if(|| indexed_selected),
+    );
+
     druid::MenuDesc::new(druid::LocalizedString::new("menu-image-menu").with_placeholder("Image"))
+        .append(mode_menu)
+        .append_separator()
         .append(fill())
+        .append(fill_shapes().selected_if(|| fill_shapes_selected))
+        .append(pixel_perfect().selected_if(|| pixel_perfect_selected))
+        .append_separator()
+        .append(crop().disabled_if(|| crop_disabled))
+        .append(trim())
+        .append_separator()
+        .append(orient(
+            "menu-image-flip-horizontal",
+            "Flip Horizontal",
+            Orientation::FlipHorizontal,
+        ))
+        .append(orient(
+            "menu-image-flip-vertical",
+            "Flip Vertical",
+            Orientation::FlipVertical,
+        ))
+        .append(orient(
+            "menu-image-rotate-90",
+            "Rotate 90° Clockwise",
+            Orientation::Rotate90,
+        ))
+        .append(orient(
+            "menu-image-rotate-180",
+            "Rotate 180°",
+            Orientation::Rotate180,
+        ))
+        .append(orient(
+            "menu-image-rotate-270",
+            "Rotate 90° Counterclockwise",
+            Orientation::Rotate270,
+        ))
         .append_separator()
         .append(brighten())
         .append(darken())
@@ -220,6 +773,306 @@ fn build_image_menu<T: Data>() -> druid::MenuDesc<T> {
         .append(dither_floyd())
 }
 
+fn build_layer_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
+    fn add<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-layer-add").with_placeholder("New Layer"),
+            commands::LAYER_ADD,
+        )
+        .hotkey(druid::SysMods::CmdShift, "N")
+    }
+
+    fn delete<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_LAYER_DELETE).with_placeholder("Delete Layer"),
+            commands::LAYER_DELETE,
+        )
+    }
+
+    fn select_above<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_LAYER_SELECT_ABOVE)
+                .with_placeholder("Select Layer Above"),
+            commands::LAYER_SELECT_ABOVE,
+        )
+        .hotkey(druid::SysMods::AltCmd, "]")
+    }
+
+    fn select_below<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_LAYER_SELECT_BELOW)
+                .with_placeholder("Select Layer Below"),
+            commands::LAYER_SELECT_BELOW,
+        )
+        .hotkey(druid::SysMods::AltCmd, "[")
+    }
+
+    fn visible<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_LAYER_VISIBLE).with_placeholder("Visible"),
+            commands::LAYER_TOGGLE_VISIBLE,
+        )
+    }
+
+    fn locked<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_LAYER_LOCKED).with_placeholder("Locked"),
+            commands::LAYER_TOGGLE_LOCKED,
+        )
+    }
+
+    fn blend_mode<T: Data>(
+        key: &'static str,
+        placeholder: &'static str,
+        blend_mode: BlendMode,
+    ) -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::LAYER_BLEND_MODE.with(blend_mode),
+        )
+    }
+
+    fn opacity<T: Data>(
+        key: &'static str,
+        placeholder: &'static str,
+        opacity: f64,
+    ) -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::LAYER_OPACITY.with(opacity),
+        )
+    }
+
+    let delete_disabled = menu_opts.disabled_or(MENU_LAYER_DELETE, false);
+    let above_disabled = menu_opts.disabled_or(MENU_LAYER_SELECT_ABOVE, false);
+    let below_disabled = menu_opts.disabled_or(MENU_LAYER_SELECT_BELOW, false);
+    let visible_selected = menu_opts.selected_or(MENU_LAYER_VISIBLE, true);
+    let locked_selected = menu_opts.selected_or(MENU_LAYER_LOCKED, false);
+    let normal_selected = menu_opts.selected_or(MENU_LAYER_BLEND_NORMAL, false);
+    let multiply_selected = menu_opts.selected_or(MENU_LAYER_BLEND_MULTIPLY, false);
+    let screen_selected = menu_opts.selected_or(MENU_LAYER_BLEND_SCREEN, false);
+    let add_selected = menu_opts.selected_or(MENU_LAYER_BLEND_ADD, false);
+    let opacity_100_selected = menu_opts.selected_or(MENU_LAYER_OPACITY_100, false);
+    let opacity_75_selected = menu_opts.selected_or(MENU_LAYER_OPACITY_75, false);
+    let opacity_50_selected = menu_opts.selected_or(MENU_LAYER_OPACITY_50, false);
+    let opacity_25_selected = menu_opts.selected_or(MENU_LAYER_OPACITY_25, false);
+
+    let blend_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-layer-blend-menu").with_placeholder("Blend Mode"),
+    )
+    .append(
+        blend_mode(MENU_LAYER_BLEND_NORMAL, "Normal", BlendMode::Normal)
+            .selected_if(|| normal_selected),
+    )
+    .append(
+        blend_mode(MENU_LAYER_BLEND_MULTIPLY, "Multiply", BlendMode::Multiply)
+            .selected_if(|| multiply_selected),
+    )
+    .append(
+        blend_mode(MENU_LAYER_BLEND_SCREEN, "Screen", BlendMode::Screen)
+            .selected_if(|| screen_selected),
+    )
+    .append(blend_mode(MENU_LAYER_BLEND_ADD, "Add", BlendMode::Add).selected_if(|| add_selected));
+
+    let opacity_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-layer-opacity-menu").with_placeholder("Opacity"),
+    )
+    .append(opacity(MENU_LAYER_OPACITY_100, "100%", 1.0).selected_if(|| opacity_100_selected))
+    .append(opacity(MENU_LAYER_OPACITY_75, "75%", 0.75).selected_if(|| opacity_75_selected))
+    .append(opacity(MENU_LAYER_OPACITY_50, "50%", 0.5).selected_if(|| opacity_50_selected))
+    .append(opacity(MENU_LAYER_OPACITY_25, "25%", 0.25).selected_if(|| opacity_25_selected));
+
+    druid::MenuDesc::new(druid::LocalizedString::new("menu-layer-menu").with_placeholder("Layer"))
+        .append(add())
+        .append(delete().disabled_if(|| delete_disabled))
+        .append_separator()
+        .append(select_above().disabled_if(|| above_disabled))
+        .append(select_below().disabled_if(|| below_disabled))
+        .append_separator()
+        .append(visible().selected_if(|| visible_selected))
+        .append(locked().selected_if(|| locked_selected))
+        .append_separator()
+        .append(blend_menu)
+        .append(opacity_menu)
+}
+
+fn build_frame_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
+    fn add<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-frame-add").with_placeholder("New Frame"),
+            commands::FRAME_ADD,
+        )
+        .hotkey(druid::SysMods::AltCmd, "n")
+    }
+
+    fn duplicate<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-frame-duplicate").with_placeholder("Duplicate Frame"),
+            commands::FRAME_DUPLICATE,
+        )
+        .hotkey(druid::SysMods::AltCmd, "d")
+    }
+
+    fn delete<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_FRAME_DELETE).with_placeholder("Delete Frame"),
+            commands::FRAME_DELETE,
+        )
+    }
+
+    fn previous<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-frame-previous").with_placeholder("Previous Frame"),
+            commands::FRAME_PREVIOUS,
+        )
+        .hotkey(druid::SysMods::None, ",")
+    }
+
+    fn next<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-frame-next").with_placeholder("Next Frame"),
+            commands::FRAME_NEXT,
+        )
+        .hotkey(druid::SysMods::None, ".")
+    }
+
+    fn move_earlier<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_FRAME_MOVE_EARLIER)
+                .with_placeholder("Move Frame Earlier"),
+            commands::FRAME_MOVE_EARLIER,
+        )
+    }
+
+    fn move_later<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_FRAME_MOVE_LATER).with_placeholder("Move Frame Later"),
+            commands::FRAME_MOVE_LATER,
+        )
+    }
+
+    fn tag<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-frame-tag").with_placeholder("Tag Frame..."),
+            commands::FRAME_TAG,
+        )
+    }
+
+    let delete_disabled = menu_opts.disabled_or(MENU_FRAME_DELETE, false);
+    let earlier_disabled = menu_opts.disabled_or(MENU_FRAME_MOVE_EARLIER, false);
+    let later_disabled = menu_opts.disabled_or(MENU_FRAME_MOVE_LATER, false);
+
+    let mut duration_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-frame-duration-menu").with_placeholder("Duration"),
+    );
+    for &(key, value) in MENU_FRAME_DURATIONS.iter() {
+        let selected = menu_opts.selected_or(key, false);
+        let item = druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(format!("{} ms", value)),
+            commands::FRAME_DURATION.with(value),
+        );
+        duration_menu = duration_menu.append(item.selected_if(|| selected));
+    }
+
+    druid::MenuDesc::new(druid::LocalizedString::new("menu-frame-menu").with_placeholder("Frame"))
+        .append(add())
+        .append(duplicate())
+        .append(delete().disabled_if(|| delete_disabled))
+        .append_separator()
+        .append(previous())
+        .append(next())
+        .append_separator()
+        .append(move_earlier().disabled_if(|| earlier_disabled))
+        .append(move_later().disabled_if(|| later_disabled))
+        .append_separator()
+        .append(duration_menu)
+        .append(tag())
+}
+
+fn build_palette_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
+    fn load<T: Data>() -> druid::MenuItem<T> {
+        let opts = druid::FileDialogOptions::default()
+            .allowed_types(constants::PALETTE_FILE_TYPES.to_vec())
+            .accept_command(commands::PALETTE_LOAD);
+
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-palette-load").with_placeholder("Load Palette..."),
+            druid::commands::SHOW_OPEN_PANEL.with(opts),
+        )
+    }
+
+    fn save<T: Data>() -> druid::MenuItem<T> {
+        let opts = druid::FileDialogOptions::default()
+            .allowed_types(constants::PALETTE_FILE_TYPES.to_vec())
+            .accept_command(commands::PALETTE_SAVE);
+
+        druid::MenuItem::new(
+            druid::LocalizedString::new("menu-palette-save").with_placeholder("Save Palette..."),
+            druid::commands::SHOW_SAVE_PANEL.with(opts),
+        )
+    }
+
+    fn add_color<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_PALETTE_ADD_COLOR).with_placeholder("Add Brush Color"),
+            commands::PALETTE_ADD_COLOR,
+        )
+    }
+
+    let add_color_disabled = menu_opts.disabled_or(MENU_PALETTE_ADD_COLOR, false);
+
+    let mut sort_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-palette-sort-menu").with_placeholder("Sort By"),
+    );
+    for &(key, placeholder, sort) in MENU_PALETTE_SORTS.iter() {
+        sort_menu = sort_menu.append(druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::PALETTE_SORT.with(sort),
+        ));
+    }
+
+    let mut presets_menu = druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-palette-presets-menu").with_placeholder("Presets"),
+    );
+    for &(key, placeholder, preset) in MENU_PALETTE_PRESETS.iter() {
+        let selected = menu_opts.selected_or(key, false);
+        let item = druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            commands::PALETTE_PRESET.with(preset),
+        );
+        presets_menu = presets_menu.append(item.selected_if(|| selected));
+    }
+
+    druid::MenuDesc::new(
+        druid::LocalizedString::new("menu-palette-menu").with_placeholder("Palette"),
+    )
+    .append(load())
+    .append(save())
+    .append_separator()
+    .append(add_color().disabled_if(|| add_color_disabled))
+    .append(sort_menu)
+    .append_separator()
+    .append(presets_menu)
+}
+
+/// Build the context menu for the palette swatch at the given index.
+pub fn palette_context_menu<T: Data>(idx: usize, delete_disabled: bool) -> druid::MenuDesc<T> {
+    druid::MenuDesc::empty()
+        .append(druid::MenuItem::new(
+            druid::LocalizedString::new("menu-palette-edit-color")
+                .with_placeholder("Edit Color..."),
+            commands::PALETTE_EDIT_COLOR.with(idx),
+        ))
+        .append(
+            druid::MenuItem::new(
+                druid::LocalizedString::new("menu-palette-delete-color")
+                    .with_placeholder("Delete Color"),
+                commands::PALETTE_DELETE_COLOR.with(idx),
+            )
+            .disabled_if(|| delete_disabled),
+        )
+}
+
 fn build_view_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
     fn toggle_grid<T: Data>() -> druid::MenuItem<T> {
         druid::MenuItem::new(
@@ -229,8 +1082,93 @@ fn build_view_menu<T: Data>(menu_opts: &MenuOpts) -> druid::MenuDesc<T> {
         .hotkey(druid::SysMods::AltCmd, "'")
     }
 
+    fn toggle_onion_skin<T: Data>() -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(MENU_VIEW_SHOW_ONION_SKIN)
+                .with_placeholder("Show Onion Skin"),
+            commands::VIEW_SHOW_ONION_SKIN,
+        )
+        .hotkey(druid::SysMods::AltCmd, "o")
+    }
+
+    fn onion_skin_menu<T: Data>(
+        menu_opts: &MenuOpts,
+        title: druid::LocalizedString<T>,
+        items: &[(&'static str, usize)],
+        selector: druid::Selector<usize>,
+    ) -> druid::MenuDesc<T> {
+        let mut menu = druid::MenuDesc::new(title);
+        for &(key, value) in items.iter() {
+            let selected = menu_opts.selected_or(key, false);
+            let item = druid::MenuItem::new(
+                druid::LocalizedString::new(key).with_placeholder(value.to_string()),
+                selector.with(value),
+            );
+            menu = menu.append(item.selected_if(|| selected));
+        }
+        menu
+    }
+
+    fn zoom<T: Data>(
+        key: &'static str,
+        placeholder: &'static str,
+        selector: druid::Selector,
+        hotkey: &'static str,
+    ) -> druid::MenuItem<T> {
+        druid::MenuItem::new(
+            druid::LocalizedString::new(key).with_placeholder(placeholder),
+            selector,
+        )
+        .hotkey(druid::SysMods::Cmd, hotkey)
+    }
+
     let grid_selected = menu_opts.selected_or(MENU_VIEW_SHOW_GRID, true);
+    let onion_skin_selected = menu_opts.selected_or(MENU_VIEW_SHOW_ONION_SKIN, false);
+
+    let before_menu = onion_skin_menu(
+        menu_opts,
+        druid::LocalizedString::new("menu-view-onion-skin-before-menu")
+            .with_placeholder("Onion Skin Before"),
+        &MENU_VIEW_ONION_SKIN_BEFORE,
+        commands::VIEW_ONION_SKIN_BEFORE,
+    );
+    let after_menu = onion_skin_menu(
+        menu_opts,
+        druid::LocalizedString::new("menu-view-onion-skin-after-menu")
+            .with_placeholder("Onion Skin After"),
+        &MENU_VIEW_ONION_SKIN_AFTER,
+        commands::VIEW_ONION_SKIN_AFTER,
+    );
 
     druid::MenuDesc::new(druid::LocalizedString::new("menu-view-menu").with_placeholder("View"))
+        .append(zoom(
+            "menu-view-zoom-in",
+            "Zoom In",
+            commands::VIEW_ZOOM_IN,
+            "=",
+        ))
+        .append(zoom(
+            "menu-view-zoom-out",
+            "Zoom Out",
+            commands::VIEW_ZOOM_OUT,
+            "-",
+        ))
+        .append(zoom(
+            "menu-view-zoom-actual",
+            "Actual Size",
+            commands::VIEW_ZOOM_ACTUAL,
+            "1",
+        ))
+        .append(zoom(
+            "menu-view-zoom-fit",
+            "Zoom to Fit",
+            commands::VIEW_ZOOM_FIT,
+            "0",
+        ))
+        .append_separator()
         .append(toggle_grid().selected_if(|| grid_selected))
+        .append_separator()
+        .append(toggle_onion_skin().selected_if(|| onion_skin_selected))
+        .append(before_menu)
+        .append(after_menu)
 }
@@ -14,7 +14,7 @@
 
 pub mod alert;
 mod button;
-mod canvas;
+pub mod canvas;
 pub mod menu;
 mod palette;
 mod theme;
@@ -14,45 +14,22 @@
 
 use druid::widget::prelude::*;
 
+use crate::common::commands;
 use crate::model::app::AppState;
+use crate::view::menu;
 use crate::view::theme;
 
-/// A palette that displays available colors. Each value is stored as a u32 representation
-/// of RGBA, with the alpha value in the least significant position. This matches what Color
-/// does internally.
+/// A palette that displays available colors. The values themselves live in the document,
+/// because indexed documents refer to them for every pixel. Click a swatch to pick it as
+/// the brush color, double-click to edit it, drag to move it, or right-click for more.
+#[derive(Default)]
 pub struct Palette {
     current_idx: usize,
     current_val: u32,
-    values: [u32; 256],
+    drag_idx: Option<usize>,
 }
 
 impl Palette {
-    /// Create a new palette with the given raw byte values.
-    pub fn new(bytes: &[u8]) -> Self {
-        Self {
-            current_idx: 0,
-            current_val: 0,
-            values: Self::read_values(bytes),
-        }
-    }
-
-    /// Read given values into palette storage. The format is RGBA8, so four bytes
-    /// per value including the alpha in the least significant position [a, r, g, b].
-    /// For now we expect it to be 256 values exactly.
-    fn read_values(bytes: &[u8]) -> [u32; 256] {
-        assert!(bytes.len() == 1024);
-
-        let mut values: [u32; 256] = [0; 256];
-
-        for (i, item) in values.iter_mut().enumerate() {
-            let j = i * 4;
-            let argb = [bytes[j], bytes[j + 1], bytes[j + 2], bytes[j + 3]];
-            *item = u32::from_le_bytes(argb);
-        }
-
-        values
-    }
-
     /// Translate from screen coordinates (typically the mouse position) to palette coordinates.
     fn screen_coords_to_palette_coords(pos: druid::Point) -> Option<druid::Point> {
         if pos.x < 1.0 || pos.y < 1.0 {
@@ -68,6 +45,13 @@ impl Palette {
         Some(druid::Point::new(x as f64, y as f64))
     }
 
+    /// Get the index of the swatch at the given screen coordinates, if there is one.
+    fn screen_coords_to_idx(pos: druid::Point, data: &AppState) -> Option<usize> {
+        let p = Self::screen_coords_to_palette_coords(pos)?;
+        let idx = Self::palette_coords_to_idx(p);
+        Some(idx).filter(|&idx| idx < data.doc().palette().len())
+    }
+
     /// Convert coordinates to an index within the palette storage.
     fn palette_coords_to_idx(p: druid::Point) -> usize {
         ((p.y - 1.0) * (theme::PALETTE_COLS as f64) + (p.x - 1.0)) as usize
@@ -111,8 +95,23 @@ impl Palette {
 impl druid::Widget<AppState> for Palette {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
         match event {
+            Event::MouseDown(e) if e.button.is_right() => {
+                if let Some(idx) = Self::screen_coords_to_idx(e.pos, data) {
+                    let delete_disabled = data.doc().palette().len() == 1;
+                    let menu = menu::palette_context_menu::<AppState>(idx, delete_disabled);
+                    ctx.show_context_menu(druid::ContextMenu::new(menu, e.window_pos));
+                }
+            }
+
+            Event::MouseDown(e) if e.count > 1 => {
+                if let Some(idx) = Self::screen_coords_to_idx(e.pos, data) {
+                    ctx.submit_command(commands::PALETTE_EDIT_COLOR.with(idx));
+                }
+            }
+
             Event::MouseDown(e) => {
                 if !e.focus {
+                    self.drag_idx = Self::screen_coords_to_idx(e.pos, data);
                     ctx.set_active(true);
                 }
             }
@@ -120,10 +119,11 @@ impl druid::Widget<AppState> for Palette {
             Event::MouseMove(e) => match Self::screen_coords_to_palette_coords(e.pos) {
                 Some(p) => {
                     let idx = Self::palette_coords_to_idx(p);
-                    let val = self.values[idx];
-                    let color = druid::Color::from_rgba32_u32(val);
-                    if color != *data.pos_color() {
-                        data.set_pos_color(color);
+                    if let Some(val) = data.doc().palette().values().get(idx) {
+                        let color = druid::Color::from_rgba32_u32(*val);
+                        if color != *data.pos_color() {
+                            data.set_pos_color(color);
+                        }
                     }
                 }
                 None => {
@@ -134,11 +134,18 @@ impl druid::Widget<AppState> for Palette {
             },
 
             Event::MouseUp(e) if ctx.is_active() => {
-                if let Some(p) = Self::screen_coords_to_palette_coords(e.pos) {
-                    self.current_idx = Self::palette_coords_to_idx(p);
-                    self.current_val = self.values[self.current_idx];
-                    data.set_brush_color(druid::Color::from_rgba32_u32(self.current_val));
-                    ctx.request_paint();
+                let drop_idx = Self::screen_coords_to_idx(e.pos, data);
+                match (self.drag_idx.take(), drop_idx) {
+                    (Some(from), Some(to)) if from != to => {
+                        ctx.submit_command(commands::PALETTE_MOVE_COLOR.with((from, to)));
+                    }
+                    (_, Some(idx)) => {
+                        self.current_idx = idx;
+                        self.current_val = data.doc().palette().values()[idx];
+                        data.set_brush_color(druid::Color::from_rgba32_u32(self.current_val));
+                        ctx.request_paint();
+                    }
+                    _ => {}
                 }
                 ctx.set_active(false);
             }
@@ -161,6 +168,10 @@ impl druid::Widget<AppState> for Palette {
             self.current_val = data.brush_color().as_rgba_u32();
             ctx.request_paint();
         }
+
+        if !old_data.doc().palette().same(data.doc().palette()) {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -170,16 +181,15 @@ impl druid::Widget<AppState> for Palette {
         _data: &AppState,
         _env: &Env,
     ) -> Size {
-        let rect = Self::idx_to_screen_rect(self.values.len() - 1);
+        let rect = Self::idx_to_screen_rect(theme::PALETTE_COLS * theme::PALETTE_ROWS - 1);
         let size = Size::new(rect.x1 + 1.0, rect.y1 + 1.0);
         bc.constrain(size)
     }
 
-    fn paint(&mut self, ctx: &mut PaintCtx, _data: &AppState, _env: &Env) {
-        for i in 0..self.values.len() {
-            let color = self.values[i];
-            let selected = self.current_val == color;
-            Self::paint_idx(ctx, i, color, selected);
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
+        for (i, color) in data.doc().palette().values().iter().enumerate() {
+            let selected = self.current_val == *color;
+            Self::paint_idx(ctx, i, *color, selected);
         }
     }
 }
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use druid::Color;
 
 use crate::common::constants;
@@ -31,7 +33,7 @@ pub const COLOR_WELL_SIZE: druid::Size = druid::Size::new(70.0, 30.0);
 
 pub const PREVIEW_FILL: Color = CANVAS_FILL_LIGHT;
 pub const PREVIEW_STROKE: Color = MAIN_STROKE;
-pub const PREVIEW_SIZE: druid::Size = druid::Size::new(CANVAS_DIMS as f64, CANVAS_DIMS as f64);
+pub const PREVIEW_SIZE: druid::Size = druid::Size::new(48.0, 48.0);
 
 pub const PALETTE_COLS: usize = 8;
 pub const PALETTE_ROWS: usize = 32;
@@ -39,8 +41,11 @@ pub const PALETTE_PIXEL_SIZE: f64 = 12.0;
 pub const PALETTE_FILL: Color = Color::BLACK;
 pub const PALETTE_STROKE_SELECTED: Color = Color::BLACK;
 
-pub const CANVAS_DIMS: usize = constants::MAX_PIXEL_DIMS as usize;
-pub const CANVAS_PIXEL_SIZE: f64 = 16.0;
+pub const CANVAS_MIN_VIEW_SIZE: druid::Size = druid::Size::new(128.0, 128.0);
+pub const CANVAS_MAX_VIEW_RECT: druid::Rect = druid::Rect::new(1.0, 1.0, 769.0, 769.0);
+pub const CANVAS_WHEEL_STEP: f64 = 50.0;
+pub const CANVAS_ANTS_DASH: f64 = 4.0;
+pub const CANVAS_ANTS_INTERVAL: Duration = Duration::from_millis(100);
 pub const CANVAS_FILL_DARK: Color = Color::rgb8(80, 80, 80);
 pub const CANVAS_FILL_LIGHT: Color = Color::rgb8(96, 96, 96);
 pub const CANVAS_STROKE: Color = MAIN_STROKE;
@@ -48,6 +53,17 @@ pub const CANVAS_STROKE_SELECTED_DARK: Color = Color::BLACK;
 pub const CANVAS_STROKE_SELECTED_LIGHT: Color = Color::WHITE;
 pub const CANVAS_STROKE_GRID_DARK: Color = Color::BLACK;
 pub const CANVAS_STROKE_GRID_LIGHT: Color = MAIN_STROKE;
+pub const CANVAS_ONION_SKIN_OPACITY: f64 = 0.5;
+pub const CANVAS_ONION_SKIN_BEFORE: [Color; constants::MAX_ONION_SKIN_FRAMES] = [
+    Color::rgb8(255, 64, 64),
+    Color::rgb8(255, 128, 0),
+    Color::rgb8(255, 192, 0),
+];
+pub const CANVAS_ONION_SKIN_AFTER: [Color; constants::MAX_ONION_SKIN_FRAMES] = [
+    Color::rgb8(64, 192, 64),
+    Color::rgb8(0, 160, 192),
+    Color::rgb8(64, 64, 255),
+];
 
 pub const BUTTON_DEFAULT_DARK: Color = Color::rgb8(0, 92, 252);
 pub const BUTTON_DEFAULT_LIGHT: Color = Color::rgb8(0, 124, 252);
@@ -56,8 +72,15 @@ pub const BUTTON_LIGHT: Color = Color::rgb8(200, 200, 200);
 
 pub const WINDOW_SIZE: druid::Size = druid::Size::new(960.0, 816.0);
 pub const UNSAVED_FILE_ALERT_SIZE: druid::Size = druid::Size::new(208.0, 268.0);
+pub const COLOR_PICKER_ALERT_SIZE: druid::Size = druid::Size::new(280.0, 560.0);
+pub const FRAME_TAG_ALERT_SIZE: druid::Size = druid::Size::new(208.0, 164.0);
+pub const PALETTE_COLOR_ALERT_SIZE: druid::Size = druid::Size::new(208.0, 164.0);
+pub const NEW_IMAGE_ALERT_SIZE: druid::Size = druid::Size::new(280.0, 384.0);
+pub const SHEET_IMPORT_ALERT_SIZE: druid::Size = druid::Size::new(208.0, 376.0);
 pub const WARNING_ALERT_SIZE: druid::Size = druid::Size::new(208.0, 164.0);
 
+pub const ALERT_FIELD_WIDTH: f64 = 56.0;
+pub const ALERT_LABEL_WIDTH: f64 = 20.0;
 pub const ALERT_MESSAGE_FONT: druid::FontDescriptor =
     druid::FontDescriptor::new(druid::FontFamily::SYSTEM_UI);
 pub const ALERT_MESSAGE_FONT_BOLD: druid::FontDescriptor =
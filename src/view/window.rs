@@ -23,7 +23,9 @@ use super::palette::Palette;
 use super::theme;
 use super::tool::ToolButton;
 use super::tool::ToolsController;
+use crate::common::commands;
 use crate::model::app::AppState;
+use crate::model::compositor;
 use crate::model::types::*;
 
 pub fn window() -> druid::WindowDesc<AppState> {
@@ -67,6 +69,12 @@ fn build_tools() -> impl druid::Widget<AppState> {
     let eraser_bytes = include_bytes!("../assets/eraser.png");
     let fill_bytes = include_bytes!("../assets/fill.png");
     let dropper_bytes = include_bytes!("../assets/dropper.png");
+    let lasso_bytes = include_bytes!("../assets/lasso.png");
+    let polygon_lasso_bytes = include_bytes!("../assets/polygon_lasso.png");
+    let magic_wand_bytes = include_bytes!("../assets/magic_wand.png");
+    let line_bytes = include_bytes!("../assets/line.png");
+    let rectangle_bytes = include_bytes!("../assets/rectangle.png");
+    let ellipse_bytes = include_bytes!("../assets/ellipse.png");
 
     Flex::column()
         .with_spacer(1.0)
@@ -75,6 +83,16 @@ fn build_tools() -> impl druid::Widget<AppState> {
             ToolButton::new(ToolType::Move, move_bytes),
         ))
         .with_spacer(theme::TOOLS_PADDING)
+        .with_child(build_tools_row(
+            ToolButton::new(ToolType::Lasso, lasso_bytes),
+            ToolButton::new(ToolType::PolygonLasso, polygon_lasso_bytes),
+        ))
+        .with_spacer(theme::TOOLS_PADDING)
+        .with_child(build_tools_row(
+            ToolButton::new(ToolType::MagicWand, magic_wand_bytes),
+            ToolButton::new(ToolType::Dropper, dropper_bytes),
+        ))
+        .with_spacer(theme::TOOLS_PADDING)
         .with_child(build_tools_row(
             ToolButton::new(ToolType::Paint, paint_bytes),
             ToolButton::new(ToolType::Eraser, eraser_bytes),
@@ -82,12 +100,18 @@ fn build_tools() -> impl druid::Widget<AppState> {
         .with_spacer(theme::TOOLS_PADDING)
         .with_child(build_tools_row(
             ToolButton::new(ToolType::Fill, fill_bytes),
-            ToolButton::new(ToolType::Dropper, dropper_bytes),
+            ToolButton::new(ToolType::Line, line_bytes),
+        ))
+        .with_spacer(theme::TOOLS_PADDING)
+        .with_child(build_tools_row(
+            ToolButton::new(ToolType::Rectangle, rectangle_bytes),
+            ToolButton::new(ToolType::Ellipse, ellipse_bytes),
         ))
         .with_spacer(theme::TOOLS_PADDING)
         .controller(ToolsController)
 }
 
+/// Build the color well, which shows the brush color. Click it to pick another one.
 fn build_color_well() -> impl druid::Widget<AppState> {
     druid::widget::Painter::new(|ctx, data: &AppState, _env| {
         let rect = ctx.size().to_rect();
@@ -101,6 +125,7 @@ fn build_color_well() -> impl druid::Widget<AppState> {
     })
     .fix_size(theme::COLOR_WELL_SIZE.width, theme::COLOR_WELL_SIZE.height)
     .border(theme::COLOR_WELL_STROKE, 1.0)
+    .on_click(|ctx, _data, _env| ctx.submit_command(commands::EDIT_BRUSH_COLOR))
 }
 
 fn build_left_pane() -> impl druid::Widget<AppState> {
@@ -119,29 +144,53 @@ fn build_canvas() -> impl druid::Widget<AppState> {
 }
 
 fn build_palette() -> impl druid::Widget<AppState> {
-    Palette::new(include_bytes!("../assets/vga.pal")).background(theme::PALETTE_FILL)
+    Palette::default().background(theme::PALETTE_FILL)
 }
 
 fn build_preview() -> impl druid::Widget<AppState> {
     druid::widget::Painter::new(|ctx, data: &AppState, _env| {
-        let pixels = data.doc().pixels();
+        let pixels = data.doc().flatten();
         let height = pixels.header().height();
         let width = pixels.header().width();
 
-        for y in 0..height {
-            for x in 0..width {
-                let fx = x as f64;
-                let fy = y as f64;
-                let rect = druid::Rect::new(fx, fy, fx + 1.0, fy + 1.0);
-
-                let color = pixels.read_xy_unchecked(x + 1, y + 1);
-                let (_, _, _, a) = color.as_rgba8();
-                if a != 255 {
-                    ctx.fill(rect, &theme::PREVIEW_FILL);
-                };
-                ctx.fill(rect, &color);
-            }
+        let (fill_r, fill_g, fill_b, fill_a) = theme::PREVIEW_FILL.as_rgba8();
+        let mut bytes = Vec::with_capacity(width * height * 4);
+        for src in pixels.bytes().chunks_exact(4) {
+            let color = compositor::blend(
+                [fill_r, fill_g, fill_b, fill_a],
+                [src[0], src[1], src[2], src[3]],
+                1.0,
+                BlendMode::Normal,
+            );
+            bytes.extend_from_slice(&color);
         }
+
+        // Small images are shown at actual size. Anything bigger is scaled down to fit.
+        let scale = f64::min(
+            1.0,
+            f64::min(
+                theme::PREVIEW_SIZE.width / width as f64,
+                theme::PREVIEW_SIZE.height / height as f64,
+            ),
+        );
+        let rect = druid::Rect::from_origin_size(
+            druid::Point::ZERO,
+            (width as f64 * scale, height as f64 * scale),
+        );
+
+        let image = ctx
+            .make_image(
+                width,
+                height,
+                &bytes,
+                druid::piet::ImageFormat::RgbaSeparate,
+            )
+            .unwrap();
+        ctx.draw_image(
+            &image,
+            rect,
+            druid::piet::InterpolationMode::NearestNeighbor,
+        );
     })
     .fix_size(theme::PREVIEW_SIZE.width, theme::PREVIEW_SIZE.height)
     .border(theme::PREVIEW_STROKE, 1.0)
@@ -179,9 +228,12 @@ fn build_status_label() -> impl druid::Widget<AppState> {
         let (r, g, b, a) = data.pos_color().as_rgba8();
         let current_pos = data.current_pos();
         let selection = data.doc().selection().unwrap_or(druid::Rect::ZERO);
+        let layer = data.doc().layer(data.doc().current_layer());
+        let current_frame = data.doc().current_frame();
+        let frame = data.doc().frame(current_frame);
 
         format!(
-            "r:{:3} g:{:3} b:{:3} a:{:3}  {:02}:{:02}-{:02}:{:02}  {:02}:{:02}",
+            "r:{:3} g:{:3} b:{:3} a:{:3}  {:02}:{:02}-{:02}:{:02}  {:02}:{:02}  {}  {}/{} {}ms",
             r,
             g,
             b,
@@ -191,7 +243,11 @@ fn build_status_label() -> impl druid::Widget<AppState> {
             selection.x1,
             selection.y1,
             current_pos.x,
-            current_pos.y
+            current_pos.y,
+            layer.name(),
+            current_frame + 1,
+            data.doc().frames().len(),
+            frame.duration()
         )
     })
     .with_font(druid::FontDescriptor::new(druid::FontFamily::MONOSPACE))